@@ -0,0 +1,47 @@
+// Measures parse and serialize throughput on a large batch file, built by repeating the sample
+// document's transaction. `VecDeque` is kept for the collections: parsing only ever pushes to the
+// back, so its storage stays contiguous and the difference from `Vec` is within noise here.
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use edi::parse;
+use std::fs::read_to_string;
+
+fn large_document() -> String {
+    let sample = read_to_string(format!(
+        "{}/examples/sample_edi.txt",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap();
+    let lines: Vec<&str> = sample.lines().filter(|x| !x.trim().is_empty()).collect();
+    let (header, rest) = lines.split_at(2);
+    let transaction = &rest[..rest.len() - 2];
+
+    let transaction_count = 1000;
+    let mut document = header.join("\n");
+    for _ in 0..transaction_count {
+        document.push('\n');
+        document.push_str(&transaction.join("\n"));
+    }
+    document.push_str(&format!("\nGE*{}*49\nIEA*1*000003438", transaction_count));
+    document
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let input = large_document();
+    let mut group = c.benchmark_group("large_document");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function("parse", |b| b.iter(|| parse(&input).unwrap()));
+    group.bench_function("parse_and_serialize_json", |b| {
+        b.iter(|| serde_json::to_string(&parse(&input).unwrap()).unwrap())
+    });
+    let document = parse(&input).unwrap();
+    group.bench_function("serialize_json", |b| {
+        b.iter(|| serde_json::to_string(&document).unwrap())
+    });
+    group.bench_function("iterate_segments", |b| {
+        b.iter(|| document.segments().count())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
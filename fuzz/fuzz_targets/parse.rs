@@ -0,0 +1,16 @@
+// Partner input is untrusted, so parsing arbitrary bytes must only ever produce an error, never a panic.
+// Run with `cargo fuzz run parse` from the crate root.
+#![no_main]
+use edi::{loose_parse, parse_bytes};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(document) = parse_bytes(data) {
+        let _ = document.to_x12_string();
+    }
+    if let Ok(input) = std::str::from_utf8(data) {
+        if let Ok(document) = loose_parse(input) {
+            let _ = document.to_x12_string();
+        }
+    }
+});
@@ -0,0 +1,316 @@
+use crate::edi_parse_error::{EdiErrorKind, EdiParseError};
+use crate::functional_group::FunctionalGroup;
+use crate::generic_segment::GenericSegment;
+use crate::interchange_control::InterchangeControl;
+use crate::transaction::Transaction;
+use alloc::borrow::Cow;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// The version/release/industry identifier code of the HIPAA 999 implementation guide (GS08 and ST03).
+const IMPLEMENTATION_ACKNOWLEDGEMENT_VERSION: &str = "005010X231A1";
+
+/// A problem found in a received transaction, to be reported in an acknowledgement built by
+/// [EdiDocument::build_999_for](struct.EdiDocument.html#method.build_999_for). Which level it is reported at
+/// depends on which positions are set: an element error (IK4) needs a segment and an element position, a
+/// segment error (IK3) a segment position, and a transaction error (IK5) neither.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AckError {
+    /// The position (starting from zero) of the functional group within the acknowledged interchange.
+    pub functional_group: usize,
+    /// The position (starting from zero) of the transaction within its functional group.
+    pub transaction: usize,
+    /// The position of the segment within the transaction set, counting the ST segment as 1 (IK302).
+    pub segment_position: Option<usize>,
+    /// The position of the element within the segment, counting the first element as 1 (IK401).
+    pub element_position: Option<usize>,
+    /// The error code: the implementation transaction set syntax error code (IK502) for transaction errors,
+    /// the segment syntax error code (IK304) for segment errors, or the element syntax error code (IK403)
+    /// for element errors.
+    pub error_code: String,
+}
+
+impl AckError {
+    /// A problem with a transaction as a whole, reported in IK502, e.g. "2" for a missing SE segment.
+    pub fn transaction(functional_group: usize, transaction: usize, error_code: &str) -> AckError {
+        AckError {
+            functional_group,
+            transaction,
+            segment_position: None,
+            element_position: None,
+            error_code: error_code.to_string(),
+        }
+    }
+
+    /// A problem with a segment, reported in IK304, e.g. "3" for a missing mandatory segment.
+    pub fn segment(
+        functional_group: usize,
+        transaction: usize,
+        segment_position: usize,
+        error_code: &str,
+    ) -> AckError {
+        AckError {
+            segment_position: Some(segment_position),
+            ..AckError::transaction(functional_group, transaction, error_code)
+        }
+    }
+
+    /// A problem with an element, reported in IK403, e.g. "8" for an invalid date.
+    pub fn element(
+        functional_group: usize,
+        transaction: usize,
+        segment_position: usize,
+        element_position: usize,
+        error_code: &str,
+    ) -> AckError {
+        AckError {
+            segment_position: Some(segment_position),
+            element_position: Some(element_position),
+            ..AckError::transaction(functional_group, transaction, error_code)
+        }
+    }
+}
+
+/// Build an interchange holding a 999 for every functional group of `interchange`, reporting `errors`.
+pub(crate) fn build_999(
+    interchange: &InterchangeControl,
+    errors: &[AckError],
+) -> Result<InterchangeControl<'static, 'static>, EdiParseError> {
+    edi_assert!(
+        EdiErrorKind::InvalidArgument,
+        !interchange.functional_groups.is_empty(),
+        "cannot acknowledge an interchange without functional groups"
+    );
+    for error in errors.iter() {
+        check_error(interchange, error)?;
+    }
+
+    let first_group = &interchange.functional_groups[0];
+    let mut acknowledgement_group = FunctionalGroup {
+        functional_identifier_code: Cow::from("FA"),
+        application_sender_code: owned(&first_group.application_receiver_code),
+        application_receiver_code: owned(&first_group.application_sender_code),
+        date: owned(&first_group.date),
+        time: owned(&first_group.time),
+        group_control_number: Cow::from("1"),
+        responsible_agency_code: Cow::from("X"),
+        version: Cow::from(IMPLEMENTATION_ACKNOWLEDGEMENT_VERSION),
+        transactions: VecDeque::new(),
+        original_trailer: None,
+    };
+    for (group_idx, functional_group) in interchange.functional_groups.iter().enumerate() {
+        let control_number = format!("{:04}", group_idx + 1);
+        let mut acknowledgement = Transaction::parse_from_tokens(vec![
+            "ST",
+            "999",
+            &control_number,
+            IMPLEMENTATION_ACKNOWLEDGEMENT_VERSION,
+        ])?
+        .into_owned();
+        acknowledgement.push_segment(segment(vec![
+            "AK1",
+            &functional_group.functional_identifier_code,
+            &functional_group.group_control_number,
+            &functional_group.version,
+        ]));
+
+        let mut accepted = 0;
+        for (transaction_idx, transaction) in functional_group.transactions.iter().enumerate() {
+            let transaction_errors: Vec<&AckError> = errors
+                .iter()
+                .filter(|error| {
+                    error.functional_group == group_idx && error.transaction == transaction_idx
+                })
+                .collect();
+            let mut ak2 = vec![
+                "AK2",
+                &transaction.transaction_code,
+                &transaction.transaction_set_control_number,
+            ];
+            if let Some(reference) = &transaction.implementation_convention_reference {
+                ak2.push(reference);
+            }
+            acknowledgement.push_segment(segment(ak2));
+            push_segment_errors(&mut acknowledgement, transaction, &transaction_errors);
+
+            let mut ik5 = vec!["IK5"];
+            if transaction_errors.is_empty() {
+                accepted += 1;
+                ik5.push("A");
+            } else {
+                ik5.push("R");
+                let codes: Vec<&str> = transaction_errors
+                    .iter()
+                    .filter(|error| error.segment_position.is_none())
+                    .map(|error| error.error_code.as_str())
+                    .take(5)
+                    .collect();
+                if codes.is_empty() {
+                    // One or more segments in error
+                    ik5.push("5");
+                } else {
+                    ik5.extend(codes);
+                }
+            }
+            acknowledgement.push_segment(segment(ik5));
+        }
+
+        let received = functional_group.transactions.len();
+        let group_status = if accepted == received {
+            "A"
+        } else if accepted == 0 {
+            "R"
+        } else {
+            "P"
+        };
+        let (received, accepted) = (received.to_string(), accepted.to_string());
+        acknowledgement.push_segment(segment(vec![
+            "AK9",
+            group_status,
+            &received,
+            &received,
+            &accepted,
+        ]));
+        acknowledgement_group.push_transaction(acknowledgement);
+    }
+
+    Ok(InterchangeControl {
+        authorization_qualifier: owned(&interchange.authorization_qualifier),
+        authorization_information: owned(&interchange.authorization_information),
+        security_qualifier: owned(&interchange.security_qualifier),
+        security_information: owned(&interchange.security_information),
+        sender_qualifier: owned(&interchange.receiver_qualifier),
+        sender_id: owned(&interchange.receiver_id),
+        receiver_qualifier: owned(&interchange.sender_qualifier),
+        receiver_id: owned(&interchange.sender_id),
+        date: owned(&interchange.date),
+        time: owned(&interchange.time),
+        standards_id: owned(&interchange.standards_id),
+        version: owned(&interchange.version),
+        interchange_control_number: Cow::from("000000001"),
+        acknowledgement_requested: Cow::from("0"),
+        test_indicator: owned(&interchange.test_indicator),
+        interchange_segments: VecDeque::new(),
+        functional_groups: VecDeque::from(vec![acknowledgement_group]),
+        original_trailer: None,
+    })
+}
+
+/// Report the segment and element errors of a transaction with IK3 and IK4 segments, in segment order. Element
+/// errors in the same segment share one IK3.
+fn push_segment_errors(
+    acknowledgement: &mut Transaction<'static, 'static>,
+    transaction: &Transaction,
+    errors: &[&AckError],
+) {
+    let mut segment_positions: Vec<usize> = errors
+        .iter()
+        .filter_map(|error| error.segment_position)
+        .collect();
+    segment_positions.sort_unstable();
+    segment_positions.dedup();
+
+    for position in segment_positions {
+        let segment_errors = errors
+            .iter()
+            .filter(|error| error.segment_position == Some(position));
+        let received_segment = position
+            .checked_sub(2)
+            .and_then(|idx| transaction.segments.get(idx));
+        let segment_id = match (position, received_segment) {
+            (1, _) => "ST",
+            (_, Some(received_segment)) => &received_segment.segment_abbreviation,
+            (_, None) => "SE",
+        };
+        let position_string = position.to_string();
+        for error in segment_errors.clone() {
+            if error.element_position.is_none() {
+                acknowledgement.push_segment(segment(vec![
+                    "IK3",
+                    segment_id,
+                    &position_string,
+                    "",
+                    &error.error_code,
+                ]));
+            }
+        }
+
+        let mut element_errors = segment_errors
+            .filter_map(|error| error.element_position.map(|element| (element, error)))
+            .peekable();
+        if element_errors.peek().is_some() {
+            // Segment has data element errors
+            acknowledgement.push_segment(segment(vec![
+                "IK3",
+                segment_id,
+                &position_string,
+                "",
+                "8",
+            ]));
+        }
+        for (element_position, error) in element_errors {
+            let element_position_string = element_position.to_string();
+            let mut ik4 = vec!["IK4", &element_position_string, "", &error.error_code];
+            if let Some(bad_data) =
+                received_segment.and_then(|received| received.element(element_position))
+            {
+                ik4.push(bad_data);
+            }
+            acknowledgement.push_segment(segment(ik4));
+        }
+    }
+}
+
+/// Check that an error refers to a transaction, segment, and element which exist.
+fn check_error(interchange: &InterchangeControl, error: &AckError) -> Result<(), EdiParseError> {
+    let transaction = interchange
+        .functional_groups
+        .get(error.functional_group)
+        .and_then(|functional_group| functional_group.transactions.get(error.transaction));
+    let transaction = match transaction {
+        Some(transaction) => transaction,
+        None => {
+            return Err(EdiParseError::new(
+                EdiErrorKind::InvalidArgument,
+                &format!(
+                    "acknowledgement error refers to transaction {} of functional group {}, which does not exist",
+                    error.transaction, error.functional_group
+                ),
+                None,
+            ))
+        }
+    };
+    if let Some(position) = error.segment_position {
+        edi_assert!(
+            EdiErrorKind::InvalidArgument,
+            position >= 1 && position <= transaction.segment_count_including_envelope(),
+            "acknowledgement error refers to a segment position outside of the transaction",
+            format!("1 to {}", transaction.segment_count_including_envelope()),
+            position
+        );
+    }
+    edi_assert!(
+        EdiErrorKind::InvalidArgument,
+        error.element_position != Some(0),
+        "acknowledgement error element positions start from 1"
+    );
+    Ok(())
+}
+
+fn owned(value: &str) -> Cow<'static, str> {
+    Cow::Owned(value.to_string())
+}
+
+/// Build an owned segment from its ID and elements, dropping trailing empty elements.
+fn segment(mut tokens: Vec<&str>) -> GenericSegment<'static> {
+    while tokens.last() == Some(&"") {
+        tokens.pop();
+    }
+    GenericSegment::new(
+        tokens[0].to_string(),
+        tokens[1..].iter().map(|x| x.to_string()),
+    )
+}
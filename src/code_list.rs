@@ -0,0 +1,178 @@
+/// A list of the codes a coded element may hold and what each one means, e.g. "ST" is "Ship To" in an N101.
+/// Used with [GenericSegment::decode_element](struct.GenericSegment.html#method.decode_element) to make
+/// coded values human-readable. The common entity identifier, reference qualifier, and functional identifier codes
+/// are bundled as [ENTITY_IDENTIFIER_CODES], [REFERENCE_IDENTIFICATION_QUALIFIERS], and
+/// [FUNCTIONAL_IDENTIFIER_CODES]; other lists, e.g. from an implementation guide, can be built with [CodeList::new].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CodeList {
+    /// The codes and their descriptions.
+    pub codes: &'static [(&'static str, &'static str)],
+}
+
+impl CodeList {
+    /// Construct a [CodeList] from pairs of codes and their descriptions.
+    pub const fn new(codes: &'static [(&'static str, &'static str)]) -> CodeList {
+        CodeList { codes }
+    }
+
+    /// Look up the description of a code, or `None` if it is not in this list.
+    pub fn description(&self, code: &str) -> Option<&'static str> {
+        self.codes
+            .iter()
+            .find(|(listed_code, _)| *listed_code == code)
+            .map(|(_, description)| *description)
+    }
+}
+
+/// The common entity identifier codes (data element 98), used in N101, NM101, and similar elements to say which
+/// party a name or address belongs to.
+pub const ENTITY_IDENTIFIER_CODES: CodeList = CodeList::new(&[
+    ("1P", "Provider"),
+    ("40", "Receiver"),
+    ("41", "Submitter"),
+    ("77", "Service Location"),
+    ("82", "Rendering Provider"),
+    ("85", "Billing Provider"),
+    ("87", "Pay-to Provider"),
+    ("BS", "Bill and Ship To"),
+    ("BT", "Bill-to-Party"),
+    ("BY", "Buying Party (Purchaser)"),
+    ("CA", "Carrier"),
+    ("CN", "Consignee"),
+    ("DK", "Ordering Physician"),
+    ("DN", "Referring Provider"),
+    ("FA", "Facility"),
+    ("II", "Issuer of Invoice"),
+    ("IL", "Insured or Subscriber"),
+    ("IN", "Insurer"),
+    ("MF", "Manufacturer of Goods"),
+    ("OB", "Ordered By"),
+    ("PE", "Payee"),
+    ("PR", "Payer"),
+    ("QC", "Patient"),
+    ("RI", "Remit To"),
+    ("SE", "Selling Party"),
+    ("SF", "Ship From"),
+    ("SH", "Shipper"),
+    ("ST", "Ship To"),
+    ("SU", "Supplier/Manufacturer"),
+    ("VN", "Vendor"),
+    ("WH", "Warehouse"),
+    ("Z7", "Mark-for Party"),
+]);
+
+/// The common reference identification qualifiers (data element 128), used in REF01 to say what kind of
+/// reference number REF02 holds.
+pub const REFERENCE_IDENTIFICATION_QUALIFIERS: CodeList = CodeList::new(&[
+    ("0B", "State License Number"),
+    ("11", "Account Number"),
+    ("12", "Billing Account"),
+    ("1L", "Group or Policy Number"),
+    ("2I", "Tracking Number"),
+    ("6P", "Group Number"),
+    ("9F", "Referral Number"),
+    ("BM", "Bill of Lading Number"),
+    ("CN", "Carrier's Reference Number (PRO/Invoice)"),
+    ("CO", "Customer Order Number"),
+    ("CR", "Customer Reference Number"),
+    ("D9", "Claim Number"),
+    ("DP", "Department Number"),
+    ("EA", "Medical Record Identification Number"),
+    ("EI", "Employer's Identification Number"),
+    ("EJ", "Patient Account Number"),
+    ("F8", "Original Reference Number"),
+    ("G1", "Prior Authorization Number"),
+    ("IA", "Internal Vendor Number"),
+    ("IT", "Internal Customer Number"),
+    ("IV", "Seller's Invoice Number"),
+    ("LU", "Location Number"),
+    ("MR", "Merchandise Type Code"),
+    ("PK", "Packing List Number"),
+    ("PO", "Purchase Order Number"),
+    ("SI", "Shipper's Identifying Number for Shipment (SID)"),
+    ("SY", "Social Security Number"),
+    ("TJ", "Federal Taxpayer's Identification Number"),
+    ("VN", "Vendor Order Number"),
+    ("VR", "Vendor ID Number"),
+    ("ZZ", "Mutually Defined"),
+]);
+
+/// The common functional identifier codes (data element 479), used in GS01 to say which kind of transaction sets a
+/// functional group holds. The transaction set codes each code is used for are given in parentheses.
+pub const FUNCTIONAL_IDENTIFIER_CODES: CodeList = CodeList::new(&[
+    ("AG", "Application Advice (824)"),
+    ("AR", "Warehouse Stock Transfer Shipment Advice (943)"),
+    ("BE", "Benefit Enrollment and Maintenance (834)"),
+    (
+        "CA",
+        "Purchase Order Change Acknowledgment/Request - Seller Initiated (865)",
+    ),
+    ("CT", "Application Control Totals (831)"),
+    (
+        "FA",
+        "Functional or Implementation Acknowledgment Transaction Sets (997, 999)",
+    ),
+    ("GF", "Response to a Load Tender (990)"),
+    ("HB", "Eligibility, Coverage or Benefit Information (271)"),
+    ("HC", "Health Care Claim (837)"),
+    ("HI", "Health Care Services Review Information (278)"),
+    ("HN", "Health Care Information Status Notification (277)"),
+    ("HP", "Health Care Claim Payment/Advice (835)"),
+    ("HR", "Health Care Claim Status Request (276)"),
+    ("HS", "Eligibility, Coverage or Benefit Inquiry (270)"),
+    ("IB", "Inventory Inquiry/Advice (846)"),
+    ("IM", "Motor Carrier Freight Details and Invoice (210)"),
+    ("IN", "Invoice Information (810, 819)"),
+    ("OW", "Warehouse Shipping Order (940)"),
+    (
+        "PC",
+        "Purchase Order Change Request - Buyer Initiated (860)",
+    ),
+    ("PD", "Product Activity Data (852)"),
+    ("PO", "Purchase Order (850)"),
+    ("PR", "Purchase Order Acknowledgement (855)"),
+    ("PS", "Planning Schedule with Release Capability (830)"),
+    ("PT", "Product Transfer and Resale Report (867)"),
+    ("QM", "Transportation Carrier Shipment Status Message (214)"),
+    ("RA", "Payment Order/Remittance Advice (820)"),
+    ("RC", "Receiving Advice/Acceptance Certificate (861)"),
+    ("RE", "Warehouse Stock Transfer Receipt Advice (944)"),
+    ("SH", "Ship Notice/Manifest (856)"),
+    ("SM", "Motor Carrier Load Tender (204)"),
+    ("SS", "Shipping Schedule (862)"),
+    ("SW", "Warehouse Shipping Advice (945)"),
+    ("TX", "Text Message (864)"),
+]);
+
+#[test]
+fn look_up_codes() {
+    assert_eq!(ENTITY_IDENTIFIER_CODES.description("ST"), Some("Ship To"));
+    assert_eq!(
+        REFERENCE_IDENTIFICATION_QUALIFIERS.description("IA"),
+        Some("Internal Vendor Number")
+    );
+    assert_eq!(ENTITY_IDENTIFIER_CODES.description("st"), None);
+    assert_eq!(ENTITY_IDENTIFIER_CODES.description(""), None);
+
+    let custom = CodeList::new(&[("A", "Accepted"), ("R", "Rejected")]);
+    assert_eq!(custom.description("R"), Some("Rejected"));
+    assert_eq!(custom.description("P"), None);
+
+    // each bundled code is listed once
+    for code_list in [
+        ENTITY_IDENTIFIER_CODES,
+        REFERENCE_IDENTIFICATION_QUALIFIERS,
+        FUNCTIONAL_IDENTIFIER_CODES,
+    ]
+    .iter()
+    {
+        for (idx, (code, _)) in code_list.codes.iter().enumerate() {
+            assert_eq!(
+                code_list.codes[idx + 1..]
+                    .iter()
+                    .find(|(other, _)| other == code),
+                None
+            );
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::edi_parse_error::{EdiErrorKind, EdiParseError};
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+
+/// The delimiters which separate the parts of an EDI document, as found in its ISA segment. More delimiters
+/// may be added in the future, so construct it with [Delimiters::new] or take it from
+/// [EdiDocument::delimiters](struct.EdiDocument.html#method.delimiters).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Delimiters {
+    /// Separates segments from each other.
+    pub segment: char,
+    /// Separates the elements within a segment.
+    pub element: char,
+    /// Separates the components within a composite element (ISA16).
+    pub sub_element: char,
+    /// The release character which escapes a delimiter appearing in element data, e.g. '?' in EDIFACT. X12 has
+    /// none, so it is `None` unless set with [with_release](#method.with_release).
+    pub release: Option<char>,
+}
+
+impl Delimiters {
+    /// Group the segment, element, and sub-element delimiters.
+    pub fn new(segment: char, element: char, sub_element: char) -> Delimiters {
+        Delimiters {
+            segment,
+            element,
+            sub_element,
+            release: None,
+        }
+    }
+
+    /// These delimiters with `release` as the release character.
+    pub fn with_release(self, release: char) -> Delimiters {
+        Delimiters {
+            release: Some(release),
+            ..self
+        }
+    }
+
+    /// Check that the three delimiters differ from each other, since a document can't be split otherwise. A
+    /// failed check is reported with the given kind of error.
+    pub(crate) fn check_distinct(&self, kind: EdiErrorKind) -> Result<(), EdiParseError> {
+        edi_assert!(
+            kind,
+            self.element != self.sub_element,
+            "element and subelement delimiters cannot be the same",
+            self.element,
+            self.sub_element
+        );
+        edi_assert!(
+            kind,
+            self.sub_element != self.segment,
+            "subelement and segment delimiters cannot be the same",
+            self.sub_element,
+            self.segment
+        );
+        edi_assert!(
+            kind,
+            self.element != self.segment,
+            "element and segment delimiters cannot be the same",
+            self.element,
+            self.segment
+        );
+        if let Some(release) = self.release {
+            edi_assert!(
+                kind,
+                release != self.segment && release != self.element && release != self.sub_element,
+                "release character cannot be one of the delimiters",
+                format!(
+                    "a character other than {:?}, {:?}, and {:?}",
+                    self.segment, self.element, self.sub_element
+                ),
+                format!("{:?}", release)
+            );
+        }
+        Ok(())
+    }
+
+    /// Remove the release characters escaping segment and element delimiters from an element value, or `None`
+    /// if it has nothing to unescape. Escaped sub-element delimiters and release characters are left in place,
+    /// because the value may be a composite element whose components are split on the sub-element delimiter.
+    pub(crate) fn unescape(&self, value: &str) -> Option<String> {
+        let release = self.release?;
+        if !value.contains(release) {
+            return None;
+        }
+        let mut unescaped = String::with_capacity(value.len());
+        let mut chars = value.chars();
+        while let Some(x) = chars.next() {
+            if x != release {
+                unescaped.push(x);
+                continue;
+            }
+            match chars.next() {
+                Some(next) if next == self.segment || next == self.element => unescaped.push(next),
+                Some(next) => {
+                    unescaped.push(release);
+                    unescaped.push(next);
+                }
+                None => unescaped.push(release),
+            }
+        }
+        // Only escapes which were removed make the value shorter.
+        (unescaped.len() < value.len()).then_some(unescaped)
+    }
+
+    /// Write an element value, escaping the segment and element delimiters in it with the release character, the
+    /// reverse of [unescape](#method.unescape). A release character which does not already escape a sub-element
+    /// delimiter or another release character is data, and is escaped too. Without a release character, the
+    /// value is written as is.
+    pub(crate) fn write_escaped<W: fmt::Write>(&self, writer: &mut W, value: &str) -> fmt::Result {
+        let release = match self.release {
+            Some(release) => release,
+            None => return writer.write_str(value),
+        };
+        let mut chars = value.chars().peekable();
+        while let Some(x) = chars.next() {
+            if x == self.segment || x == self.element {
+                writer.write_char(release)?;
+                writer.write_char(x)?;
+            } else if x == release {
+                writer.write_char(release)?;
+                match chars.peek() {
+                    Some(&next) if next == release || next == self.sub_element => {
+                        writer.write_char(next)?;
+                        chars.next();
+                    }
+                    _ => writer.write_char(release)?,
+                }
+            } else {
+                writer.write_char(x)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn escape_delimiters() {
+    let delimiters = Delimiters::new('\'', '+', ':').with_release('?');
+    assert_eq!(delimiters.unescape("NO ESCAPES"), None);
+    assert_eq!(delimiters.unescape("A?+B?'C").unwrap(), "A+B'C");
+    // escaped sub-element delimiters and release characters stay escaped
+    assert_eq!(delimiters.unescape("A?:B??"), None);
+    assert_eq!(Delimiters::new('~', '*', '>').unescape("A?*B"), None);
+
+    let mut escaped = String::new();
+    for element in ["A+B'C", "A?:B??", "WHAT?", "A:B"].iter() {
+        delimiters.write_escaped(&mut escaped, element).unwrap();
+        escaped.push('|');
+    }
+    assert_eq!(escaped, "A?+B?'C|A?:B??|WHAT??|A:B|");
+
+    assert!(Delimiters::new('~', '*', '>')
+        .with_release('>')
+        .check_distinct(EdiErrorKind::InvalidArgument)
+        .is_err());
+}
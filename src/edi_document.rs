@@ -1,17 +1,68 @@
-use crate::edi_parse_error::{try_option, EdiParseError};
+use crate::acknowledgement::{build_999, AckError};
+use crate::delimiters::Delimiters;
+use crate::edi_parse_error::{try_option, EdiErrorKind, EdiParseError};
+use crate::element_spec::{parse_element_reference, validate_segment, ElementSpec};
+use crate::functional_group::FunctionalGroup;
+use crate::generic_segment::GenericSegment;
 use crate::interchange_control::InterchangeControl;
-use crate::tokenizer::tokenize;
-use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use crate::parse_options::ParseOptions;
+use crate::parse_stats::ParseStats;
+use crate::tokenizer::{
+    binary_data_position, isa_delimiters, tokenize, DocumentTokens, SegmentTokens, TokenizeResult,
+};
+use crate::transaction::{Transaction, UNIDENTIFIED_TRANSACTION_NAME};
+use alloc::borrow::Cow;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The IDs of the segments which make up the envelope, as opposed to generic segments.
+const CONTROL_SEGMENT_IDS: [&str; 6] = ["ISA", "GS", "ST", "SE", "GE", "IEA"];
+
+/// The minimum and maximum number of elements of each envelope segment, not counting the segment ID.
+/// ST03 (the implementation convention reference) is optional, and ISA16 (the sub-element separator) may be missing
+/// with [ParseOptions::allow_isa_without_sub_element_separator].
+const CONTROL_SEGMENT_ELEMENT_COUNTS: [(&str, usize, usize); 6] = [
+    ("ISA", 16, 16),
+    ("GS", 8, 8),
+    ("ST", 2, 3),
+    ("SE", 2, 2),
+    ("GE", 2, 2),
+    ("IEA", 2, 2),
+];
+
+/// The byte order mark some editors put at the beginning of UTF-8 files.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// The version of the serde representation of [EdiDocument], written to its `edi_schema_version` field. It is
+/// bumped whenever a change to the structs would stop an older version of this crate from reading a stored
+/// document.
+pub const EDI_SCHEMA_VERSION: u32 = 1;
+
+/// How many segments [parse_with_progress] processes between calls to its progress callback.
+const PROGRESS_INTERVAL: usize = 1000;
 
 /// Represents an entire parsed EDI document with both the envelope (i.e. metadata) and
-/// the data segments.
-#[derive(Serialize, Deserialize)]
+/// the data segments. Documents compare equal when their envelopes, segments, and delimiters are equal; the
+/// [segment_offsets](#structfield.segment_offsets) and the original trailers kept with
+/// [ParseOptions::preserve_original_trailers] take part in the comparison too, so documents parsed with and
+/// without recording them are not equal.
+///
+/// With serde, a document is serialized with its fields in declaration order, preceded by an `edi_schema_version`
+/// field holding [EDI_SCHEMA_VERSION], so stored documents can be read back by later versions of this crate.
+/// Documents without the field, stored before it was introduced, are read as version 0; documents from a newer
+/// version are rejected. Fields added to the structs later are optional, so documents stored without them in a
+/// self-describing format such as JSON or YAML still deserialize.
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub struct EdiDocument<'a, 'b> {
     // Here I chose a VecDeque because when I output an EDI document, I want to pull from
     // it in a queue style.
     /// Represents the interchanges (ISA/IEA) held within this document.
-    #[serde(borrow = "'a + 'b")]
     pub interchanges: VecDeque<InterchangeControl<'a, 'b>>,
     /// Represents the separator between segments in the EDI document.
     pub segment_delimiter: char,
@@ -19,90 +70,1179 @@ pub struct EdiDocument<'a, 'b> {
     pub sub_element_delimiter: char,
     /// Represents the separator between elements in the EDI document.
     pub element_delimiter: char,
+    /// The release character which escapes delimiters in element data, if the document was parsed with
+    /// [ParseOptions::release_character](struct.ParseOptions.html#structfield.release_character). When set,
+    /// segment and element delimiters in the elements of generic segments are escaped with it on output.
+    pub release_character: Option<char>,
+    /// The `(start, end)` byte range of every segment (including ISA, GS, ST, and their closers) within
+    /// the original input, in document order. The ranges exclude the segment delimiter but include any
+    /// surrounding whitespace, so slicing the input with them yields the untouched segment text.
+    /// Only recorded when parsing with [ParseOptions::record_segment_offsets].
+    pub segment_offsets: Option<Vec<(usize, usize)>>,
 }
 
-impl EdiDocument<'_, '_> {
+impl Serialize for EdiDocument<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut document = serializer.serialize_struct("EdiDocument", 7)?;
+        document.serialize_field("edi_schema_version", &EDI_SCHEMA_VERSION)?;
+        document.serialize_field("interchanges", &self.interchanges)?;
+        document.serialize_field("segment_delimiter", &self.segment_delimiter)?;
+        document.serialize_field("sub_element_delimiter", &self.sub_element_delimiter)?;
+        document.serialize_field("element_delimiter", &self.element_delimiter)?;
+        document.serialize_field("release_character", &self.release_character)?;
+        document.serialize_field("segment_offsets", &self.segment_offsets)?;
+        document.end()
+    }
+}
+
+/// The serde representation of an [EdiDocument], which is checked for its schema version before it becomes one.
+#[derive(Deserialize)]
+#[serde(rename = "EdiDocument")]
+struct StoredEdiDocument<'a, 'b> {
+    #[serde(default)]
+    edi_schema_version: u32,
+    #[serde(borrow = "'a + 'b")]
+    interchanges: VecDeque<InterchangeControl<'a, 'b>>,
+    segment_delimiter: char,
+    sub_element_delimiter: char,
+    element_delimiter: char,
+    #[serde(default)]
+    release_character: Option<char>,
+    #[serde(default)]
+    segment_offsets: Option<Vec<(usize, usize)>>,
+}
+
+impl<'de: 'a + 'b, 'a, 'b> Deserialize<'de> for EdiDocument<'a, 'b> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredEdiDocument::deserialize(deserializer)?;
+        if stored.edi_schema_version > EDI_SCHEMA_VERSION {
+            return Err(serde::de::Error::custom(format!(
+                "document has schema version {}, but only versions up to {} are supported",
+                stored.edi_schema_version, EDI_SCHEMA_VERSION
+            )));
+        }
+        Ok(EdiDocument {
+            interchanges: stored.interchanges,
+            segment_delimiter: stored.segment_delimiter,
+            sub_element_delimiter: stored.sub_element_delimiter,
+            element_delimiter: stored.element_delimiter,
+            release_character: stored.release_character,
+            segment_offsets: stored.segment_offsets,
+        })
+    }
+}
+
+impl<'a, 'b> EdiDocument<'a, 'b> {
+    /// Construct an empty [EdiDocument] to be filled with interchanges and serialized with the given delimiters,
+    /// e.g. for building an outbound document. Fails if any two of the delimiters are the same, since the document
+    /// could not be parsed again.
+    pub fn new(delimiters: Delimiters) -> Result<EdiDocument<'a, 'b>, EdiParseError> {
+        delimiters.check_distinct(EdiErrorKind::InvalidArgument)?;
+        Ok(EdiDocument {
+            interchanges: VecDeque::new(),
+            segment_delimiter: delimiters.segment,
+            sub_element_delimiter: delimiters.sub_element,
+            element_delimiter: delimiters.element,
+            release_character: delimiters.release,
+            segment_offsets: None,
+        })
+    }
+
+    /// Iterate over every [FunctionalGroup] in the document, across all interchanges, in document order.
+    pub fn functional_groups(&self) -> impl Iterator<Item = &FunctionalGroup<'a, 'b>> {
+        self.interchanges
+            .iter()
+            .flat_map(|interchange| interchange.functional_groups.iter())
+    }
+
+    /// Iterate over every [Transaction] in the document, across all interchanges and functional groups,
+    /// in document order.
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction<'a, 'b>> {
+        self.functional_groups()
+            .flat_map(|functional_group| functional_group.transactions.iter())
+    }
+
+    /// Iterate over the transactions with the given transaction set code (ST01), e.g. "810" for every invoice,
+    /// across all interchanges and functional groups, in document order.
+    pub fn transactions_of_type<'s>(
+        &'s self,
+        code: &'s str,
+    ) -> impl Iterator<Item = &'s Transaction<'a, 'b>> + 's {
+        self.transactions()
+            .filter(move |transaction| transaction.transaction_code == code)
+    }
+
+    /// Iterate over the transactions whose [transaction_name](struct.Transaction.html#structfield.transaction_name)
+    /// is the given name, e.g. "Invoice", in document order.
+    pub fn transactions_named<'s>(
+        &'s self,
+        name: &'s str,
+    ) -> impl Iterator<Item = &'s Transaction<'a, 'b>> + 's {
+        self.transactions()
+            .filter(move |transaction| transaction.transaction_name == name)
+    }
+
+    /// Iterate over every [GenericSegment] in the document in document order, regardless of nesting.
+    /// This includes segments which were kept outside of a functional group.
+    pub fn segments(&self) -> impl Iterator<Item = &GenericSegment<'a>> {
+        // Parsing only ever pushes to the back, so the segments are almost always in the first slice
+        // and iterating the slices directly skips the ring buffer bookkeeping.
+        self.interchanges.iter().flat_map(|interchange| {
+            interchange.interchange_segments.iter().chain(
+                interchange
+                    .functional_groups
+                    .iter()
+                    .flat_map(|functional_group| functional_group.transactions.iter())
+                    .flat_map(|transaction| {
+                        let (front, back) = transaction.segments.as_slices();
+                        front.iter().chain(back.iter())
+                    }),
+            )
+        })
+    }
+
+    /// Check that control numbers are not reused: interchange control numbers (ISA13) must be unique within
+    /// the document, group control numbers (GS06) within their interchange, and transaction set control
+    /// numbers (ST02) within their functional group. Every repeated control number is reported.
+    /// This does not change how documents are parsed.
+    pub fn check_control_number_uniqueness(&self) -> Result<(), Vec<EdiParseError>> {
+        let mut errors = Vec::new();
+        let mut interchange_control_numbers = BTreeSet::new();
+        for interchange in self.interchanges.iter() {
+            if !interchange_control_numbers.insert(interchange.interchange_control_number.as_ref())
+            {
+                errors.push(EdiParseError::new(
+                    EdiErrorKind::DuplicateControlNumber,
+                    &format!(
+                        "duplicate interchange control number {}",
+                        interchange.interchange_control_number
+                    ),
+                    None,
+                ));
+            }
+            let mut group_control_numbers = BTreeSet::new();
+            for functional_group in interchange.functional_groups.iter() {
+                if !group_control_numbers.insert(functional_group.group_control_number.as_ref()) {
+                    errors.push(EdiParseError::new(
+                        EdiErrorKind::DuplicateControlNumber,
+                        &format!(
+                            "duplicate group control number {} in interchange {}",
+                            functional_group.group_control_number,
+                            interchange.interchange_control_number
+                        ),
+                        None,
+                    ));
+                }
+                let mut transaction_control_numbers = BTreeSet::new();
+                for transaction in functional_group.transactions.iter() {
+                    if !transaction_control_numbers
+                        .insert(transaction.transaction_set_control_number.as_ref())
+                    {
+                        errors.push(EdiParseError::new(
+                            EdiErrorKind::DuplicateControlNumber,
+                            &format!(
+                                "duplicate transaction set control number {} in functional group {}",
+                                transaction.transaction_set_control_number,
+                                functional_group.group_control_number
+                            ),
+                            None,
+                        ));
+                    }
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Make every closing segment (SE, GE, IEA) agree with the structure of the document, e.g. to forward a
+    /// document which was accepted with [loose_parse] despite wrong counts. The original trailers kept with
+    /// [ParseOptions::preserve_original_trailers] are dropped, so serializing the document writes each trailer's
+    /// count from the segments, transactions, or functional groups it closes, and its control number (SE02, GE02,
+    /// IEA02) from its opener. The output then passes strict validation with [parse].
+    pub fn repair_control_counts(&mut self) {
+        for interchange in self.interchanges.iter_mut() {
+            interchange.original_trailer = None;
+            for functional_group in interchange.functional_groups.iter_mut() {
+                functional_group.original_trailer = None;
+                for transaction in functional_group.transactions.iter_mut() {
+                    transaction.original_trailer = None;
+                }
+            }
+        }
+    }
+
+    /// Assign sequential control numbers throughout the document, e.g. after building or modifying it. Interchanges
+    /// are numbered from `starting_interchange` as nine digit ISA13s (wrapping around after 999999999), functional
+    /// groups from 1 within each interchange (GS06), and transactions from 0001 within each functional group (ST02).
+    /// The trailers (IEA, GE, SE) are written from their openers, so they stay consistent.
+    pub fn renumber(&mut self, starting_interchange: u64) {
+        for (interchange_idx, interchange) in self.interchanges.iter_mut().enumerate() {
+            let interchange_control_number =
+                starting_interchange.wrapping_add(interchange_idx as u64) % 1_000_000_000;
+            interchange.interchange_control_number =
+                format!("{:09}", interchange_control_number).into();
+            for (group_idx, functional_group) in
+                interchange.functional_groups.iter_mut().enumerate()
+            {
+                functional_group.group_control_number = format!("{}", group_idx + 1).into();
+                for (transaction_idx, transaction) in
+                    functional_group.transactions.iter_mut().enumerate()
+                {
+                    transaction.transaction_set_control_number =
+                        format!("{:04}", transaction_idx + 1).into();
+                }
+            }
+        }
+    }
+
+    /// Combine several documents into one, e.g. to transmit them as a single batch. The interchanges of all the
+    /// documents are concatenated in order. If `merge_functional_groups` is set, an interchange is instead folded
+    /// into an earlier one with the same envelope (every ISA element except the control number, date, and time),
+    /// and its functional groups likewise into earlier groups with the same GS01-GS03, GS07, and GS08; the
+    /// earlier envelope's date and time are kept. The result is [renumbered](#method.renumber) starting from the
+    /// first interchange's control number (or 1, if it is not a number). Fails if the documents do not all use
+    /// the same delimiters.
+    pub fn merge(
+        documents: Vec<EdiDocument<'a, 'b>>,
+        merge_functional_groups: bool,
+    ) -> Result<EdiDocument<'a, 'b>, EdiParseError> {
+        let delimiters = match documents.first() {
+            Some(document) => document.delimiters(),
+            None => {
+                return Err(EdiParseError::new(
+                    EdiErrorKind::InvalidArgument,
+                    "cannot merge an empty list of documents",
+                    None,
+                ))
+            }
+        };
+        let mut interchanges: VecDeque<InterchangeControl<'a, 'b>> = VecDeque::new();
+        for document in documents {
+            edi_assert!(
+                EdiErrorKind::InvalidArgument,
+                document.delimiters() == delimiters,
+                "cannot merge documents with conflicting delimiters",
+                format!("{:?}", delimiters),
+                format!("{:?}", document.delimiters())
+            );
+            for interchange in document.interchanges {
+                let existing = interchanges
+                    .iter_mut()
+                    .find(|existing| same_interchange_envelope(existing, &interchange));
+                match existing {
+                    Some(existing) if merge_functional_groups => {
+                        existing
+                            .interchange_segments
+                            .extend(interchange.interchange_segments);
+                        for functional_group in interchange.functional_groups {
+                            match existing
+                                .functional_groups
+                                .iter_mut()
+                                .find(|existing| same_group_envelope(existing, &functional_group))
+                            {
+                                Some(existing) => {
+                                    existing.transactions.extend(functional_group.transactions)
+                                }
+                                None => existing.functional_groups.push_back(functional_group),
+                            }
+                        }
+                    }
+                    _ => interchanges.push_back(interchange),
+                }
+            }
+        }
+        let starting_interchange = interchanges
+            .front()
+            .and_then(|interchange| interchange.interchange_control_number.parse().ok())
+            .unwrap_or(1);
+        let mut merged = EdiDocument {
+            interchanges,
+            element_delimiter: delimiters.element,
+            sub_element_delimiter: delimiters.sub_element,
+            segment_delimiter: delimiters.segment,
+            release_character: delimiters.release,
+            segment_offsets: None,
+        };
+        merged.renumber(starting_interchange);
+        Ok(merged)
+    }
+
+    /// Build a 999 implementation acknowledgement for the interchange at position `interchange_index` (starting
+    /// from zero), with one 999 transaction per functional group. Every transaction is accepted unless `errors`
+    /// reports problems with it; finding those problems is up to the caller. The acknowledgement is addressed
+    /// from the interchange's receiver to its sender and uses this document's delimiters. Its envelope copies the
+    /// date and time of the acknowledged interchange and has control numbers starting from 1, so set those (e.g.
+    /// with [renumber](#method.renumber)) before sending it.
+    pub fn build_999_for(
+        &self,
+        interchange_index: usize,
+        errors: &[AckError],
+    ) -> Result<EdiDocument<'static, 'static>, EdiParseError> {
+        let interchange = match self.interchanges.get(interchange_index) {
+            Some(interchange) => interchange,
+            None => {
+                return Err(EdiParseError::new(
+                    EdiErrorKind::InvalidArgument,
+                    &format!("interchange {} does not exist", interchange_index),
+                    None,
+                ))
+            }
+        };
+        Ok(EdiDocument {
+            interchanges: VecDeque::from(vec![build_999(interchange, errors)?]),
+            segment_delimiter: self.segment_delimiter,
+            sub_element_delimiter: self.sub_element_delimiter,
+            element_delimiter: self.element_delimiter,
+            release_character: self.release_character,
+            segment_offsets: None,
+        })
+    }
+
+    /// Split the document into one document per transaction, in document order. Each carries a copy of the
+    /// ISA and GS envelope which enclosed its transaction, and the trailer counts are recomputed when it is
+    /// serialized. With `starting_interchange`, the documents are renumbered (see [renumber](#method.renumber))
+    /// with consecutive interchange control numbers from there; without it, the original control numbers are kept.
+    pub fn explode(&self, starting_interchange: Option<u64>) -> Vec<EdiDocument<'a, 'b>> {
+        let mut documents = Vec::new();
+        for interchange in self.interchanges.iter() {
+            for functional_group in interchange.functional_groups.iter() {
+                for transaction in functional_group.transactions.iter() {
+                    let mut group = functional_group.clone_envelope();
+                    group.push_transaction(transaction.clone());
+                    let mut envelope = interchange.clone_envelope();
+                    envelope.push_functional_group(group);
+                    let mut document = EdiDocument {
+                        interchanges: VecDeque::from(vec![envelope]),
+                        segment_delimiter: self.segment_delimiter,
+                        sub_element_delimiter: self.sub_element_delimiter,
+                        element_delimiter: self.element_delimiter,
+                        release_character: self.release_character,
+                        segment_offsets: None,
+                    };
+                    if let Some(starting_interchange) = starting_interchange {
+                        document
+                            .renumber(starting_interchange.wrapping_add(documents.len() as u64));
+                    }
+                    documents.push(document);
+                }
+            }
+        }
+        documents
+    }
+
+    /// Check the elements of every [GenericSegment] against the given specs, which are keyed by element
+    /// reference (a segment ID followed by a two digit position, e.g. "BEG05" or "N104"). Elements which are
+    /// empty or absent are not checked. Every violation is reported, along with any malformed reference.
+    /// The envelope segments are not checked, and this does not change how documents are parsed.
+    pub fn validate_elements<K: AsRef<str>>(
+        &self,
+        specs: &BTreeMap<K, ElementSpec>,
+    ) -> Result<(), Vec<EdiParseError>> {
+        let mut errors = Vec::new();
+        let mut parsed_specs = Vec::new();
+        for (reference, spec) in specs.iter() {
+            match parse_element_reference(reference.as_ref()) {
+                Ok((segment_id, position)) => parsed_specs.push((segment_id, position, spec)),
+                Err(error) => errors.push(error),
+            }
+        }
+        for segment in self.segments() {
+            validate_segment(segment, &parsed_specs, &mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Copy every element still borrowed from the input into an owned string, so that the document no longer
+    /// borrows from the input and can outlive it. Documents returned by [parse] become `EdiDocument<'static, 'static>`.
+    pub fn into_owned(self) -> EdiDocument<'static, 'b> {
+        EdiDocument {
+            interchanges: self
+                .interchanges
+                .into_iter()
+                .map(InterchangeControl::into_owned)
+                .collect(),
+            segment_delimiter: self.segment_delimiter,
+            sub_element_delimiter: self.sub_element_delimiter,
+            element_delimiter: self.element_delimiter,
+            release_character: self.release_character,
+            segment_offsets: self.segment_offsets,
+        }
+    }
+
+    /// The delimiters this document was parsed with, which are also the ones it is serialized with.
+    pub fn delimiters(&self) -> Delimiters {
+        let delimiters = Delimiters::new(
+            self.segment_delimiter,
+            self.element_delimiter,
+            self.sub_element_delimiter,
+        );
+        match self.release_character {
+            Some(release) => delimiters.with_release(release),
+            None => delimiters,
+        }
+    }
+
     /// Turns this [EdiDocument] into an ANSI x12 string.
     pub fn to_x12_string(&self) -> String {
-        let mut buffer = String::new();
-        let mut idx = 0;
+        X12Output(self, self.delimiters(), false).to_string()
+    }
+
+    /// Like [to_x12_string](#method.to_x12_string), but returns an error instead of writing an envelope which a
+    /// receiver would reject: an ISA element which is longer than its fixed width, or a required ISA, GS, or ST
+    /// element which is empty. This matters for documents built or modified by hand; parsed documents pass.
+    pub fn try_to_x12_string(&self) -> Result<String, EdiParseError> {
         for interchange in self.interchanges.iter() {
+            interchange.check_required_elements()?;
+        }
+        Ok(self.to_x12_string())
+    }
+
+    /// Turns this [EdiDocument] into an ANSI x12 string using the given delimiters instead of its own, e.g. to
+    /// send a document to a partner which expects different ones. The delimiters are not checked against the
+    /// content: unless they have a [release](struct.Delimiters.html#structfield.release) character to escape them
+    /// with, an element containing one of them produces a document which will not parse the same way.
+    pub fn to_x12_string_with(&self, delimiters: Delimiters) -> String {
+        X12Output(self, delimiters, false).to_string()
+    }
+
+    /// Writes this [EdiDocument] as ANSI x12 to an [io::Write](std::io::Write) sink, producing the same output as
+    /// [to_x12_string](#method.to_x12_string). The document is written segment by segment rather than built up
+    /// in memory first, so wrap unbuffered sinks such as files and sockets in a
+    /// [BufWriter](std::io::BufWriter). Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn write_x12<W: std::io::Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "{}", X12Output(self, self.delimiters(), false))
+    }
+
+    /// Writes the document with the given delimiters, and a line break after every segment delimiter if
+    /// `line_breaks` is set.
+    fn write_x12_fmt<W: fmt::Write>(
+        &self,
+        writer: &mut W,
+        delimiters: Delimiters,
+        line_breaks: bool,
+    ) -> fmt::Result {
+        let mut segment_separator = String::from(delimiters.segment);
+        if line_breaks && delimiters.segment != '\n' {
+            segment_separator.push('\n');
+        }
+        for (idx, interchange) in self.interchanges.iter().enumerate() {
             if idx > 0 {
-                buffer.push(self.segment_delimiter);
+                writer.write_str(&segment_separator)?;
             }
-            buffer.push_str(&interchange.to_x12_string(
-                self.segment_delimiter,
-                self.element_delimiter,
-                self.sub_element_delimiter,
-            ));
-            idx += 1;
+            interchange.write_x12(
+                writer,
+                &segment_separator,
+                delimiters.element,
+                delimiters.sub_element,
+                Some(&delimiters),
+            )?;
         }
+        Ok(())
+    }
 
-        buffer
+    /// Turns this [EdiDocument] into an ANSI x12 string with each segment on its own line, for debugging and
+    /// logging. The document's delimiters are kept, so the output still parses; the line breaks are just
+    /// whitespace around segments, and are only written after segment delimiters which end a segment, not after
+    /// escaped ones or those in binary data. [to_x12_string](#method.to_x12_string) remains the canonical output.
+    pub fn to_x12_string_pretty(&self) -> String {
+        X12Output(self, self.delimiters(), true).to_string()
+    }
+
+    /// Serialize this [EdiDocument] to pretty-printed JSON in a canonical form, for storing documents and
+    /// reviewing changes to them with diff tools. Object keys are sorted, and every segment has an additional
+    /// `segment_name` field with its human-readable name (see [segment_name](fn.segment_name.html)), or null,
+    /// next to the `transaction_name` every transaction already has. Because of the additional fields, the
+    /// output is not meant to be deserialized; the serde representation is unchanged. Requires the
+    /// `serde_json` feature.
+    #[cfg(feature = "serde_json")]
+    pub fn to_canonical_json(&self) -> String {
+        let mut document =
+            serde_json::to_value(self).expect("a document always serializes to JSON");
+        let interchanges = document["interchanges"]
+            .as_array_mut()
+            .into_iter()
+            .flatten();
+        for interchange in interchanges {
+            name_segments(&mut interchange["interchange_segments"]);
+            let functional_groups = interchange["functional_groups"].as_array_mut();
+            for functional_group in functional_groups.into_iter().flatten() {
+                let transactions = functional_group["transactions"].as_array_mut();
+                for transaction in transactions.into_iter().flatten() {
+                    name_segments(&mut transaction["segments"]);
+                }
+            }
+        }
+        serde_json::to_string_pretty(&sort_keys(document))
+            .expect("a document always serializes to JSON")
+    }
+}
+
+/// Add the `segment_name` field to each of a JSON array of segments, for [EdiDocument::to_canonical_json].
+#[cfg(feature = "serde_json")]
+fn name_segments(segments: &mut serde_json::Value) {
+    for segment in segments.as_array_mut().into_iter().flatten() {
+        let name = segment["segment_abbreviation"]
+            .as_str()
+            .and_then(crate::generic_segment::segment_name);
+        if let Some(segment) = segment.as_object_mut() {
+            segment.insert(String::from("segment_name"), name.into());
+        }
+    }
+}
+
+/// Rebuild every JSON object with its keys in sorted order, whether or not serde_json preserves insertion order.
+#[cfg(feature = "serde_json")]
+fn sort_keys(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(object) => {
+            let sorted: BTreeMap<String, serde_json::Value> = object
+                .into_iter()
+                .map(|(key, value)| (key, sort_keys(value)))
+                .collect();
+            serde_json::Value::Object(sorted.into_iter().collect())
+        }
+        serde_json::Value::Array(array) => {
+            serde_json::Value::Array(array.into_iter().map(sort_keys).collect())
+        }
+        value => value,
+    }
+}
+
+/// Whether two interchanges can be merged by [EdiDocument::merge]: everything but their control numbers, dates,
+/// and times is the same.
+fn same_interchange_envelope(first: &InterchangeControl, second: &InterchangeControl) -> bool {
+    first.authorization_qualifier == second.authorization_qualifier
+        && first.authorization_information == second.authorization_information
+        && first.security_qualifier == second.security_qualifier
+        && first.security_information == second.security_information
+        && first.sender_qualifier == second.sender_qualifier
+        && first.sender_id == second.sender_id
+        && first.receiver_qualifier == second.receiver_qualifier
+        && first.receiver_id == second.receiver_id
+        && first.standards_id == second.standards_id
+        && first.version == second.version
+        && first.acknowledgement_requested == second.acknowledgement_requested
+        && first.test_indicator == second.test_indicator
+}
+
+/// Whether two functional groups can be merged by [EdiDocument::merge]: they carry the same kind of transactions
+/// between the same applications, in the same version.
+fn same_group_envelope(first: &FunctionalGroup, second: &FunctionalGroup) -> bool {
+    first.functional_identifier_code == second.functional_identifier_code
+        && first.application_sender_code == second.application_sender_code
+        && first.application_receiver_code == second.application_receiver_code
+        && first.responsible_agency_code == second.responsible_agency_code
+        && first.version == second.version
+}
+
+/// Formats a document as ANSI x12 with the given delimiters, and line breaks between segments if the flag is set,
+/// so that [String]s and [io::Write](std::io::Write) sinks can share one writer.
+struct X12Output<'d, 'a, 'b>(&'d EdiDocument<'a, 'b>, Delimiters, bool);
+
+impl fmt::Display for X12Output<'_, '_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.write_x12_fmt(f, self.1, self.2)
     }
 }
 
 /// This is the main entry point to the crate. Parse an input str and output either
 /// an [EdiParseError] or a resulting [EdiDocument].
-pub fn parse(input: &str) -> Result<EdiDocument, EdiParseError> {
-    parse_inner(input, false)
+pub fn parse(input: &str) -> Result<EdiDocument<'_, 'static>, EdiParseError> {
+    parse_with_options(input, &ParseOptions::default())
 }
 
 /// This is an alternate parser which does not perform closing tag validation. If you are receiving
 /// EDI documents which have had less rigor applied to their construction, this may help. The number
 /// of documents in the confirmation and the IDs on the closing tags don't need to match.
-pub fn loose_parse(input: &str) -> Result<EdiDocument, EdiParseError> {
-    parse_inner(input, true)
+/// Serializing the document writes the closing segments from its actual structure, so re-serializing a loosely
+/// parsed document corrects its counts and control numbers, unless its original trailers were kept with
+/// [ParseOptions::preserve_original_trailers]; [EdiDocument::repair_control_counts] drops those.
+pub fn loose_parse(input: &str) -> Result<EdiDocument<'_, 'static>, EdiParseError> {
+    parse_with_options(input, &ParseOptions::loose())
+}
+
+/// Parse an input str according to the given [ParseOptions]. [parse] and [loose_parse] are shorthands
+/// for this function with [ParseOptions::default] and [ParseOptions::loose] respectively.
+pub fn parse_with_options<'a>(
+    input: &'a str,
+    options: &ParseOptions,
+) -> Result<EdiDocument<'a, 'static>, EdiParseError> {
+    parse_inner(input, options, None).map(|(document, _)| document)
+}
+
+/// Parse an input str according to the given [ParseOptions] like [parse_with_options], and also return
+/// [ParseStats] describing the document and how many closing segments went unchecked. This is meant for
+/// triaging documents received through [loose_parse]-style parsing.
+pub fn parse_with_stats<'a>(
+    input: &'a str,
+    options: &ParseOptions,
+) -> Result<(EdiDocument<'a, 'static>, ParseStats), EdiParseError> {
+    parse_inner(input, options, None)
+}
+
+/// Parse an input str like [parse], calling `progress` with the number of segments processed so far and the total
+/// number of segments in the document. It is called every 1000 segments and once more when every
+/// segment has been processed, so that an application can show a progress bar while importing a large document.
+pub fn parse_with_progress(
+    input: &str,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<EdiDocument<'_, 'static>, EdiParseError> {
+    let options = ParseOptions::default();
+    build_document(
+        tokenize(input, &options)?,
+        &options,
+        None,
+        Some(&mut progress),
+    )
+    .map(|(document, _)| document)
+}
+
+/// Find the transaction set codes (ST01) of every transaction in the input, in document order, without building
+/// an [EdiDocument]. The ISA header is still checked so that the delimiters can be found. This is meant for
+/// routing inbound documents to the right handler cheaply; `transaction_set_name` resolves the codes to names.
+/// The codes are trimmed of whitespace, like the codes of a parsed document.
+pub fn peek_transaction_codes(input: &str) -> Result<Vec<String>, EdiParseError> {
+    let tokenize_result = tokenize(input, &ParseOptions::default())?;
+    tokenize_result
+        .tokens
+        .iter()
+        .filter(|segment| segment[0].trim() == "ST")
+        .map(|segment| {
+            edi_assert!(
+                EdiErrorKind::MissingElements,
+                segment.len() >= 2,
+                "ST segment does not contain a transaction set code",
+                segment.clone()
+            );
+            Ok(segment[1].trim().to_string())
+        })
+        .collect()
+}
+
+/// Check cheaply whether the input is a complete interchange, e.g. to reject a truncated transmission before
+/// parsing it: it must start with an ISA segment, every ISA must be closed by an IEA with the same control number
+/// (ISA13 and IEA02) before the next one starts, and nothing but whitespace may follow the last IEA. The segments
+/// are only split far enough to read their IDs and control numbers, and the check stops at the first problem.
+/// Nothing else is validated, so a complete interchange may still fail to parse. Binary segments are not
+/// recognized, so their data must not contain the segment delimiter.
+pub fn is_interchange_complete(input: &str) -> bool {
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    let (element_delimiter, _, segment_delimiter) = match isa_delimiters(input) {
+        Some(delimiters) => delimiters,
+        None => return false,
+    };
+    let mut open_control_number = None;
+    let mut closed = false;
+    for segment in input
+        .split(segment_delimiter)
+        .map(str::trim)
+        .filter(|segment| !segment.is_empty())
+    {
+        let mut elements = segment.split(element_delimiter).map(str::trim);
+        match (elements.next(), open_control_number) {
+            (Some("ISA"), None) => {
+                // ISA13
+                open_control_number = elements.nth(12);
+                if open_control_number.is_none() {
+                    return false;
+                }
+                closed = false;
+            }
+            (Some("IEA"), Some(control_number)) => {
+                // IEA02
+                if elements.nth(1) != Some(control_number) {
+                    return false;
+                }
+                open_control_number = None;
+                closed = true;
+            }
+            (Some("ISA"), Some(_)) | (Some("IEA"), None) => return false,
+            // Segments outside of an interchange
+            (_, None) => return false,
+            _ => {}
+        }
+    }
+    closed
 }
 
-/// An internal function which is the root of the parsing. It is accessed publicly via [parse] and [loose_parse].
-fn parse_inner(input: &str, loose: bool) -> Result<EdiDocument, EdiParseError> {
-    let tokenize_result = tokenize(input)?;
+/// Parse a UTF-8 encoded byte slice. A leading byte order mark is skipped. Input which is not valid UTF-8
+/// produces an [EdiParseError]; with the `encoding` feature enabled, [decode_bytes] can be used to transcode
+/// such input first.
+pub fn parse_bytes(input: &[u8]) -> Result<EdiDocument<'_, 'static>, EdiParseError> {
+    let input = input.strip_prefix(UTF8_BOM).unwrap_or(input);
+    match core::str::from_utf8(input) {
+        Ok(input) => parse(input),
+        Err(error) => Err(EdiParseError::new(
+            EdiErrorKind::InvalidEncoding,
+            &format!("input is not valid UTF-8: {}", error),
+            None,
+        )),
+    }
+}
+
+/// Decode a byte slice into a str which can be handed to [parse]. A leading UTF-8 byte order mark is skipped.
+/// Valid UTF-8 is borrowed as-is; anything else is assumed to be in a single-byte encoding and is transcoded
+/// from Windows-1252 (a superset of the printable Latin-1 characters).
+#[cfg(feature = "encoding")]
+pub fn decode_bytes(input: &[u8]) -> Cow<'_, str> {
+    let input = input.strip_prefix(UTF8_BOM).unwrap_or(input);
+    match core::str::from_utf8(input) {
+        Ok(input) => Cow::Borrowed(input),
+        Err(_) => {
+            encoding_rs::WINDOWS_1252
+                .decode_without_bom_handling(input)
+                .0
+        }
+    }
+}
+
+/// Parse an input str like [parse], but keep going past failed closing segment validations (mismatched counts
+/// or control numbers) and report all of them at once. Errors which prevent the document from being built,
+/// such as a segment appearing before any ISA, still stop the parse; they are returned after any validation
+/// errors collected up to that point.
+pub fn parse_collecting(input: &str) -> Result<EdiDocument<'_, 'static>, Vec<EdiParseError>> {
+    let mut validation_errors = Vec::new();
+    let result = parse_inner(
+        input,
+        &ParseOptions::default(),
+        Some(&mut validation_errors),
+    );
+    match result {
+        Ok((document, _)) if validation_errors.is_empty() => Ok(document),
+        Ok(_) => Err(validation_errors),
+        Err(error) => {
+            validation_errors.push(error);
+            Err(validation_errors)
+        }
+    }
+}
+
+/// Parse an input str with the checks of [parse], but return the document even if its closing segment validations
+/// fail, together with the verdict [parse] would have reached: `Ok(())` if it would have accepted the document,
+/// or every validation failure otherwise. The input is only parsed once. Errors which prevent the document from
+/// being built, such as a segment appearing before any ISA or an empty segment, still stop the parse.
+pub fn parse_with_validation(
+    input: &str,
+) -> Result<(EdiDocument<'_, 'static>, Result<(), Vec<EdiParseError>>), EdiParseError> {
+    let mut validation_errors = Vec::new();
+    let (document, _) = parse_inner(
+        input,
+        &ParseOptions::default(),
+        Some(&mut validation_errors),
+    )?;
+    if validation_errors.is_empty() {
+        Ok((document, Ok(())))
+    } else {
+        Ok((document, Err(validation_errors)))
+    }
+}
+
+/// Parse an input str as leniently as [loose_parse], but still run the closing segment validations and return
+/// their failures (mismatched counts or control numbers, unbalanced ISA/IEA) alongside the document instead of
+/// discarding them. Envelope segments with an unexpected number of elements, which hint at a delimiter inside
+/// the data, are reported too (see [ParseOptions::check_envelope_element_counts]). Errors which prevent the
+/// document from being built still stop the parse.
+pub fn loose_parse_with_report(
+    input: &str,
+) -> Result<(EdiDocument<'_, 'static>, Vec<EdiParseError>), EdiParseError> {
+    let options = ParseOptions {
+        validate_control_counts: true,
+        check_envelope_element_counts: true,
+        ..ParseOptions::loose()
+    };
+    let mut validation_errors = Vec::new();
+    let (document, _) = parse_inner(input, &options, Some(&mut validation_errors))?;
+    Ok((document, validation_errors))
+}
+
+/// Build an [EdiDocument] from a document which has already been split into segments and elements, e.g. to
+/// inspect or modify the tokens first, or because they come from a source other than a string. Each segment is
+/// its ID followed by its elements, i.e. the segment split on the element delimiter; whitespace around the
+/// elements is trimmed as usual. The delimiters are only kept for serializing the document. If `loose` is set,
+/// the document is built like [loose_parse] builds it; otherwise, like [parse].
+pub fn parse_from_tokens(
+    tokens: DocumentTokens<'_>,
+    delimiters: Delimiters,
+    loose: bool,
+) -> Result<EdiDocument<'_, 'static>, EdiParseError> {
+    edi_assert!(
+        EdiErrorKind::MalformedSegment,
+        tokens.iter().all(|segment| !segment.is_empty()),
+        "every segment must have at least a segment ID"
+    );
+    let options = if loose {
+        ParseOptions::loose()
+    } else {
+        ParseOptions::default()
+    };
+    let tokenize_result = TokenizeResult {
+        element_delimiter: delimiters.element,
+        sub_element_delimiter: delimiters.sub_element,
+        segment_delimiter: delimiters.segment,
+        release_character: delimiters.release,
+        tokens,
+        segment_offsets: None,
+        raw_segments: None,
+    };
+    build_document(tokenize_result, &options, None, None).map(|(document, _)| document)
+}
+
+/// An internal function which is the root of the parsing. It is accessed publicly via [parse_with_options],
+/// [parse_with_stats], [parse_collecting], [parse_with_validation], and [loose_parse_with_report]. If
+/// `validation_errors` is given, failed closing segment validations are pushed onto it instead of stopping the
+/// parse.
+fn parse_inner<'a>(
+    input: &'a str,
+    options: &ParseOptions,
+    validation_errors: Option<&mut Vec<EdiParseError>>,
+) -> Result<(EdiDocument<'a, 'static>, ParseStats), EdiParseError> {
+    build_document(tokenize(input, options)?, options, validation_errors, None)
+}
+
+/// The second half of [parse_inner]: build the envelopes and segments of an [EdiDocument] out of the tokens. If
+/// `progress` is given, it is called as described in [parse_with_progress].
+fn build_document<'a>(
+    tokenize_result: TokenizeResult<'a>,
+    options: &ParseOptions,
+    mut validation_errors: Option<&mut Vec<EdiParseError>>,
+    mut progress: Option<&mut dyn FnMut(usize, usize)>,
+) -> Result<(EdiDocument<'a, 'static>, ParseStats), EdiParseError> {
     let document_tokens = tokenize_result.tokens;
+    let total_segments = document_tokens.len();
 
     // Go through all the segments and parse them either into an interchange control header,
     // functional group header, transaction header, or generic segment. Also verify that
     // the nesting order is correct.
     let mut interchanges: VecDeque<InterchangeControl> = VecDeque::new();
+    // Whether the most recent ISA has been closed by an IEA yet.
+    let mut interchange_open = false;
+    // Whether the most recent ST has been closed by an SE yet.
+    let mut transaction_open = false;
+    let mut skipped_validations = 0;
+    let mut unclosed_transactions = 0;
 
-    for segment in document_tokens {
+    for (idx, segment) in document_tokens.into_iter().enumerate() {
+        if let Some(progress) = progress.as_mut() {
+            if idx > 0 && idx % PROGRESS_INTERVAL == 0 {
+                progress(idx, total_segments);
+            }
+        }
+        let mut segment: SegmentTokens = if options.preserve_whitespace {
+            segment
+        } else {
+            // The data of a binary segment is taken exactly as sent.
+            let binary_data = binary_data_position(segment[0]);
+            segment
+                .into_iter()
+                .enumerate()
+                .map(|(idx, x)| {
+                    if Some(idx) == binary_data {
+                        x
+                    } else {
+                        x.trim()
+                    }
+                })
+                .collect()
+        };
+        if options.case_insensitive_segment_ids {
+            if let Some(control_segment_id) = CONTROL_SEGMENT_IDS
+                .iter()
+                .find(|id| segment[0].trim().eq_ignore_ascii_case(id))
+            {
+                segment[0] = control_segment_id;
+            }
+        }
+        if options.preserve_whitespace && CONTROL_SEGMENT_IDS.contains(&segment[0].trim()) {
+            // The envelope is always trimmed, so that whitespace can't make a closer differ from its opener.
+            segment = segment.into_iter().map(str::trim).collect();
+        }
+        if options.check_envelope_element_counts {
+            record_validation(
+                check_element_count(
+                    &segment,
+                    tokenize_result.element_delimiter,
+                    tokenize_result.segment_delimiter,
+                    options,
+                ),
+                &mut validation_errors,
+            )?;
+        }
+        if !options.validate_control_counts && ["SE", "GE", "IEA"].contains(&segment[0]) {
+            skipped_validations += 1;
+        }
+        if transaction_open && ["ST", "GE", "IEA", "ISA"].contains(&segment[0]) {
+            unclosed_transactions += 1;
+            if options.validate_control_counts {
+                record_validation(
+                    Err(EdiParseError::new(
+                        EdiErrorKind::OutOfOrder,
+                        &format!(
+                            "transaction validation failed: {} received before the previous transaction was closed by an SE",
+                            segment[0]
+                        ),
+                        Some(segment.clone()),
+                    )),
+                    &mut validation_errors,
+                )?;
+            }
+            transaction_open = false;
+        }
         match segment[0] {
             "ISA" => {
+                if options.validate_control_counts && interchange_open {
+                    record_validation(
+                        Err(EdiParseError::new(
+                            EdiErrorKind::OutOfOrder,
+                            "interchange validation failed: ISA received before the previous interchange was closed by an IEA",
+                            Some(segment.clone()),
+                        )),
+                        &mut validation_errors,
+                    )?;
+                }
+                if let Some(max_interchanges) = options.max_interchanges {
+                    edi_assert!(
+                        EdiErrorKind::LimitExceeded,
+                        interchanges.len() < max_interchanges,
+                        "document contains more interchanges than allowed",
+                        format!("at most {} interchanges", max_interchanges),
+                        format!("more than {}", max_interchanges)
+                    );
+                }
                 interchanges.push_back(InterchangeControl::parse_from_tokens(segment)?);
+                interchange_open = true;
             }
             "GS" => {
+                if options.allow_missing_interchange && interchanges.is_empty() {
+                    interchanges.push_back(InterchangeControl::placeholder());
+                }
                 try_option(interchanges.back_mut(), &segment)?.add_functional_group(segment)?;
             }
             "ST" => {
+                if options.allow_missing_interchange && interchanges.is_empty() {
+                    let mut interchange = InterchangeControl::placeholder();
+                    interchange
+                        .functional_groups
+                        .push_back(FunctionalGroup::placeholder());
+                    interchanges.push_back(interchange);
+                }
                 try_option(interchanges.back_mut(), &segment)?.add_transaction(segment)?;
+                transaction_open = true;
             }
             "IEA" => {
-                if !loose {
-                    try_option(interchanges.back(), &segment)?
-                        .validate_interchange_control(segment)?;
+                if options.preserve_original_trailers && interchange_open {
+                    if let Some(interchange) = interchanges.back_mut() {
+                        interchange.original_trailer = Some(trailer_elements(&segment));
+                    }
+                }
+                if options.validate_control_counts {
+                    let validation = if interchange_open {
+                        try_option(interchanges.back(), &segment)?
+                            .validate_interchange_control(segment, options)
+                    } else {
+                        Err(EdiParseError::new(
+                            EdiErrorKind::OutOfOrder,
+                            "interchange validation failed: IEA received with no open interchange",
+                            Some(segment),
+                        ))
+                    };
+                    record_validation(validation, &mut validation_errors)?;
                 };
+                interchange_open = false;
             }
             "GE" => {
-                if !loose {
-                    try_option(interchanges.back(), &segment)?
-                        .validate_functional_group(segment)?;
+                if options.preserve_original_trailers {
+                    if let Some(functional_group) = interchanges
+                        .back_mut()
+                        .and_then(|interchange| interchange.functional_groups.back_mut())
+                    {
+                        functional_group.original_trailer = Some(trailer_elements(&segment));
+                    }
+                }
+                if options.validate_control_counts {
+                    let validation = try_option(interchanges.back(), &segment)?
+                        .validate_functional_group(segment, options);
+                    record_validation(validation, &mut validation_errors)?;
                 };
             }
             "SE" => {
-                if !loose {
-                    try_option(interchanges.back(), &segment)?.validate_transaction(segment)?;
+                if options.preserve_original_trailers && transaction_open {
+                    if let Some(transaction) = interchanges
+                        .back_mut()
+                        .and_then(|interchange| interchange.functional_groups.back_mut())
+                        .and_then(|functional_group| functional_group.transactions.back_mut())
+                    {
+                        transaction.original_trailer = Some(trailer_elements(&segment));
+                    }
+                }
+                transaction_open = false;
+                if options.validate_control_counts {
+                    let validation = try_option(interchanges.back(), &segment)?
+                        .validate_transaction(segment, options);
+                    record_validation(validation, &mut validation_errors)?;
                 };
             }
             _ => {
-                try_option(interchanges.back_mut(), &segment)?.add_generic_segment(segment)?;
+                let raw = tokenize_result.raw_segments.as_ref().map(|raw| raw[idx]);
+                let interchange = try_option(interchanges.back_mut(), &segment)?;
+                if options.allow_segments_before_group && interchange.functional_groups.is_empty() {
+                    interchange.add_interchange_segment(segment, raw)?;
+                } else {
+                    interchange.add_generic_segment(segment, raw)?;
+                }
             }
         }
     }
 
-    return Ok(EdiDocument {
+    if transaction_open {
+        unclosed_transactions += 1;
+        if options.validate_control_counts {
+            record_validation(
+                Err(EdiParseError::new(
+                    EdiErrorKind::OutOfOrder,
+                    "transaction validation failed: the document ended before the last transaction was closed by an SE",
+                    None,
+                )),
+                &mut validation_errors,
+            )?;
+        }
+    }
+
+    if let Some(progress) = progress {
+        progress(total_segments, total_segments);
+    }
+
+    if options.unidentified_transaction_name != UNIDENTIFIED_TRANSACTION_NAME {
+        for transaction in interchanges
+            .iter_mut()
+            .flat_map(|interchange| interchange.functional_groups.iter_mut())
+            .flat_map(|functional_group| functional_group.transactions.iter_mut())
+            .filter(|transaction| !transaction.is_recognized())
+        {
+            transaction.transaction_name = options.unidentified_transaction_name;
+        }
+    }
+
+    if let Some(release) = tokenize_result.release_character {
+        let delimiters = Delimiters::new(
+            tokenize_result.segment_delimiter,
+            tokenize_result.element_delimiter,
+            tokenize_result.sub_element_delimiter,
+        )
+        .with_release(release);
+        for interchange in interchanges.iter_mut() {
+            let transaction_segments = interchange
+                .functional_groups
+                .iter_mut()
+                .flat_map(|functional_group| functional_group.transactions.iter_mut())
+                .flat_map(|transaction| transaction.segments.iter_mut());
+            for segment in interchange
+                .interchange_segments
+                .iter_mut()
+                .chain(transaction_segments)
+            {
+                // binary data is delimited by its length, so it is never escaped
+                let binary_data = binary_data_position(&segment.segment_abbreviation);
+                for (idx, element) in segment.elements.iter_mut().enumerate() {
+                    if Some(idx + 1) == binary_data {
+                        continue;
+                    }
+                    if let Some(unescaped) = delimiters.unescape(element) {
+                        *element = Cow::Owned(unescaped);
+                    }
+                }
+            }
+        }
+    }
+
+    let document = EdiDocument {
         interchanges,
         element_delimiter: tokenize_result.element_delimiter,
         sub_element_delimiter: tokenize_result.sub_element_delimiter,
         segment_delimiter: tokenize_result.segment_delimiter,
-    });
+        release_character: tokenize_result.release_character,
+        segment_offsets: tokenize_result.segment_offsets,
+    };
+    let stats = ParseStats {
+        interchanges: document.interchanges.len(),
+        functional_groups: document.functional_groups().count(),
+        transactions: document.transactions().count(),
+        generic_segments: document.segments().count(),
+        skipped_validations,
+        unclosed_transactions,
+    };
+    Ok((document, stats))
+}
+
+/// The elements of a closing segment after its ID, kept as its original trailer. See
+/// [ParseOptions::preserve_original_trailers].
+fn trailer_elements<'a>(segment: &SegmentTokens<'a>) -> Vec<Cow<'a, str>> {
+    segment[1..]
+        .iter()
+        .map(|element| Cow::from(*element))
+        .collect()
+}
+
+/// Check that an envelope segment has the number of elements the standard defines for it. Other segments are
+/// not checked.
+fn check_element_count(
+    segment: &SegmentTokens,
+    element_delimiter: char,
+    segment_delimiter: char,
+    options: &ParseOptions,
+) -> Result<(), EdiParseError> {
+    let element_count = segment.len() - 1;
+    let counts = CONTROL_SEGMENT_ELEMENT_COUNTS
+        .iter()
+        .find(|(id, _, _)| *id == segment[0])
+        .map(|&(id, min, max)| match id {
+            "ISA" if options.allow_isa_without_sub_element_separator => (id, 15, max),
+            _ => (id, min, max),
+        });
+    match counts {
+        Some((id, min, max)) if element_count < min || element_count > max => {
+            let expected = if min == max {
+                format!("{}", min)
+            } else {
+                format!("{} to {}", min, max)
+            };
+            Err(EdiParseError::new(
+                EdiErrorKind::MalformedSegment,
+                &format!(
+                    "envelope validation failed: {} segment has {} elements but {} are expected; an element may contain the element delimiter ({}) or the segment delimiter ({})",
+                    id,
+                    element_count,
+                    expected,
+                    element_delimiter,
+                    segment_delimiter
+                ),
+                Some(segment.clone()),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Pass a failed validation on to the caller, or, if errors are being collected, record it and carry on.
+fn record_validation(
+    validation: Result<(), EdiParseError>,
+    validation_errors: &mut Option<&mut Vec<EdiParseError>>,
+) -> Result<(), EdiParseError> {
+    match (validation, validation_errors) {
+        (Err(error), Some(validation_errors)) => {
+            validation_errors.push(error);
+            Ok(())
+        }
+        (validation, _) => validation,
+    }
 }
@@ -1,13 +1,63 @@
 use crate::tokenizer::SegmentTokens;
-use std::{error, fmt};
+use alloc::string::{String, ToString};
+#[cfg(feature = "std")]
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::error;
+/// The category of an [EdiParseError], for code which needs to react to different kinds of errors differently
+/// without matching on the reason string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum EdiErrorKind {
+    /// The ISA header is missing, too short, not laid out at its fixed offsets, or declares unusable delimiters.
+    MalformedHeader,
+    /// A segment could not be split into elements, e.g. an empty segment or a binary segment whose data does
+    /// not match its declared length.
+    MalformedSegment,
+    /// The input is not valid text in the expected encoding.
+    InvalidEncoding,
+    /// A closing segment (SE, GE, IEA) reports a different number of segments, transactions, or functional
+    /// groups than were received, or a CTT segment a different number of line items or hash total.
+    CountMismatch,
+    /// A closing segment (SE, GE, IEA) has a different control number than its opener.
+    ControlNumberMismatch,
+    /// Two envelopes at the same level share a control number.
+    DuplicateControlNumber,
+    /// A segment was received where it is not allowed, e.g. an ST without an open functional group.
+    OutOfOrder,
+    /// A segment which a check relies on is not in the transaction, e.g. the CTT segment for
+    /// [Transaction::validate_ctt](struct.Transaction.html#method.validate_ctt).
+    MissingSegment,
+    /// A segment does not contain an element it requires, or a required element is empty.
+    MissingElements,
+    /// An element is present but its value is not valid, e.g. an unknown qualifier or a malformed date.
+    InvalidElement,
+    /// The HL segments of a transaction do not form a valid hierarchy.
+    InvalidHierarchy,
+    /// A schema, such as a file of custom transaction set names, could not be loaded.
+    SchemaLoad,
+    /// A method was called with an argument which does not fit the document, e.g. an index out of range.
+    InvalidArgument,
+    /// The document exceeds a limit set in [ParseOptions](struct.ParseOptions.html), e.g.
+    /// [max_segments](struct.ParseOptions.html#structfield.max_segments).
+    LimitExceeded,
+}
+
 /// Represents an error that occurred at any point in parsing a document.
-/// Contains a reason the error occurred and the segment in which the error occurred.
+/// Contains the kind of error, a reason the error occurred, and the segment in which the error occurred.
 #[derive(Debug, Clone)]
 pub struct EdiParseError {
+    /// The category of the error.
+    kind: EdiErrorKind,
     /// The reason for the error.
     reason: String,
     /// The segment in which the error occurred.
     error_segment: Option<Vec<String>>,
+    /// The underlying error which caused this one, if any. It is reference counted so that the error stays `Clone`.
+    #[cfg(feature = "std")]
+    source: Option<Arc<dyn error::Error + Send + Sync>>,
 }
 
 impl fmt::Display for EdiParseError {
@@ -16,26 +66,72 @@ impl fmt::Display for EdiParseError {
     }
 }
 
+/// Two errors are equal if they have the same kind, reason, and error segment. The underlying error, if any, is not
+/// compared, since `dyn Error` has no notion of equality.
+impl PartialEq for EdiParseError {
+    fn eq(&self, other: &EdiParseError) -> bool {
+        self.kind == other.kind
+            && self.reason == other.reason
+            && self.error_segment == other.error_segment
+    }
+}
+
+impl Eq for EdiParseError {}
+
+#[cfg(feature = "std")]
 impl error::Error for EdiParseError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        None
+        self.source
+            .as_deref()
+            .map(|source| source as &(dyn error::Error + 'static))
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<csv::Error> for EdiParseError {
+    fn from(error: csv::Error) -> EdiParseError {
+        EdiParseError {
+            kind: EdiErrorKind::SchemaLoad,
+            reason: alloc::format!("failed to read csv: {}", error),
+            error_segment: None,
+            source: Some(Arc::new(error)),
+        }
     }
 }
 
 impl EdiParseError {
-    #[doc(skip)]
+    #[doc(hidden)]
     /// Construct a new [EdiParseError].
-    pub fn new(reason: &str, error_segment: Option<SegmentTokens>) -> EdiParseError {
-        let error_segment = if let Some(error_segment) = error_segment {
-            Some(error_segment.iter().map(|x| x.to_string()).collect())
-        } else {
-            None
-        };
+    pub fn new(
+        kind: EdiErrorKind,
+        reason: &str,
+        error_segment: Option<SegmentTokens>,
+    ) -> EdiParseError {
+        let error_segment = error_segment
+            .map(|error_segment| error_segment.iter().map(|x| x.to_string()).collect());
         EdiParseError {
+            kind,
             reason: String::from(reason),
             error_segment,
+            #[cfg(feature = "std")]
+            source: None,
         }
     }
+
+    /// The category of the error.
+    pub fn kind(&self) -> EdiErrorKind {
+        self.kind
+    }
+
+    /// The reason the error occurred.
+    pub fn reason(&self) -> &str {
+        &self.reason
+    }
+
+    /// The elements of the segment in which the error occurred, if the error is tied to a segment.
+    pub fn error_segment(&self) -> Option<&[String]> {
+        self.error_segment.as_deref()
+    }
 }
 
 /// Since implementing `From<NoneError>` is unstable right now, this is a temporary way to emulate
@@ -44,18 +140,41 @@ pub fn try_option<T>(
     maybe_segment: Option<T>,
     error_segment: &SegmentTokens,
 ) -> Result<T, EdiParseError> {
-    if maybe_segment.is_some() {
-        return Ok(maybe_segment.unwrap());
-    } else {
-        return Err(EdiParseError{
-            reason: "EDI file out of order: from out to in, the file must have ISA, GS, ST, and then generic segments".to_string(),
-            error_segment: Some(error_segment.iter().map(|x| x.to_string()).collect())
-        });
+    match maybe_segment {
+        Some(segment) => Ok(segment),
+        None => Err(EdiParseError{
+            kind: EdiErrorKind::OutOfOrder,
+            reason: alloc::format!(
+                "EDI file out of order: received {} but no interchange (ISA) is open; from out to in, the file must have ISA, GS, ST, and then generic segments",
+                error_segment.first().unwrap_or(&"")
+            ),
+            error_segment: Some(error_segment.iter().map(|x| x.to_string()).collect()),
+            #[cfg(feature = "std")]
+            source: None,
+        }),
+    }
+}
+
+/// Parse the count element of a closing segment (IEA01, GE01, SE01) into a number, producing an
+/// [EdiParseError] instead of panicking if a partner sent something that isn't one.
+pub fn parse_count(count: &str, error_segment: &SegmentTokens) -> Result<usize, EdiParseError> {
+    match str::parse::<usize>(count) {
+        Ok(count) => Ok(count),
+        Err(_) => Err(EdiParseError {
+            kind: EdiErrorKind::InvalidElement,
+            reason: alloc::format!(
+                "closing segment count is not a number  --  expected: a number  received: {}",
+                count
+            ),
+            error_segment: Some(error_segment.iter().map(|x| x.to_string()).collect()),
+            #[cfg(feature = "std")]
+            source: None,
+        }),
     }
 }
 
-/// returns an EDI error with a custom error message if the given condition is false.
-/// Supports three use cases:
+/// returns an EDI error of the given [EdiErrorKind] with a custom error message if the given condition is false.
+/// The kind always comes first, followed by one of these use cases:
 ///    `(condition, reason)` - if not condition, display reason
 ///    `(condition, reason, error_segment)` - if not condition, display reason with the segment it occurred in
 ///    `(condition, reason, expected, result)` - if not condition, display reason with what was expected and what occurred.
@@ -65,34 +184,40 @@ pub fn try_option<T>(
 ///                                                             similar to `assert_eq!`.
 // perhaps someday this can become edi_assert_eq, edi_assert_neq, and edi_assert
 macro_rules! edi_assert {
-    ($condition:expr, $reason:expr) => {{
+    ($kind:expr, $condition:expr, $reason:expr) => {{
         if !$condition {
-            return Err(EdiParseError::new($reason, None));
+            return Err(EdiParseError::new($kind, $reason, None));
         }
     }};
-    ($condition:expr, $reason:expr, $error_segment:expr) => {{
+    ($kind:expr, $condition:expr, $reason:expr, $error_segment:expr) => {{
         if !$condition {
-            return Err(EdiParseError::new($reason, Some($error_segment)));
+            return Err(EdiParseError::new($kind, $reason, Some($error_segment)));
         }
     }};
-    ($condition:expr, $reason:expr, $expected:expr, $result:expr) => {{
+    ($kind:expr, $condition:expr, $reason:expr, $expected:expr, $result:expr) => {{
         if !$condition {
             return Err(EdiParseError::new(
-                format!(
+                $kind,
+                alloc::format!(
                     "{}  --  expected: {}  received: {}",
-                    $reason, $expected, $result
+                    $reason,
+                    $expected,
+                    $result
                 )
                 .as_str(),
                 None,
             ));
         }
     }};
-    ($condition:expr, $reason:expr, $expected:expr, $result:expr, $error_segment:expr) => {{
+    ($kind:expr, $condition:expr, $reason:expr, $expected:expr, $result:expr, $error_segment:expr) => {{
         if !$condition {
             return Err(EdiParseError::new(
-                format!(
+                $kind,
+                alloc::format!(
                     "{}  --  expected: {}  received: {}",
-                    $reason, $expected, $result
+                    $reason,
+                    $expected,
+                    $result
                 )
                 .as_str(),
                 Some($error_segment),
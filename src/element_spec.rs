@@ -0,0 +1,251 @@
+use crate::edi_parse_error::{EdiErrorKind, EdiParseError};
+use crate::generic_segment::GenericSegment;
+use alloc::format;
+use alloc::vec::Vec;
+
+/// The data types X12 assigns to simple data elements in its data dictionary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementType {
+    /// An identifier (ID), i.e. a code from a code list. Only its length is checked, since the code lists
+    /// are not bundled.
+    Identifier,
+    /// A string (AN). Only its length is checked.
+    String,
+    /// A numeric (Nn), with the given number of implied decimal places: digits and an optional minus sign,
+    /// but no decimal point.
+    Numeric(u8),
+    /// A decimal number (R), which may contain an explicit decimal point.
+    Decimal,
+    /// A date (DT), either CCYYMMDD or YYMMDD.
+    Date,
+    /// A time (TM), HHMM optionally followed by seconds (SS) and decimal seconds.
+    Time,
+}
+
+/// What the X12 data dictionary says an element should look like: its data type and its minimum and maximum
+/// length. For numerics and decimals, the length does not count a minus sign or decimal point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ElementSpec {
+    /// The data type of the element.
+    pub element_type: ElementType,
+    /// The minimum length of the element, when it is present.
+    pub min_length: usize,
+    /// The maximum length of the element.
+    pub max_length: usize,
+}
+
+impl ElementSpec {
+    /// Describe an element by its data type and length bounds, e.g. `ElementSpec::new(ElementType::Date, 8, 8)`
+    /// for BEG05.
+    pub fn new(element_type: ElementType, min_length: usize, max_length: usize) -> ElementSpec {
+        ElementSpec {
+            element_type,
+            min_length,
+            max_length,
+        }
+    }
+
+    /// Check a single element value against this spec. Empty values are accepted, since whether an element
+    /// is required is a property of the segment rather than of the element.
+    pub(crate) fn check(&self, value: &str) -> Result<(), &'static str> {
+        if value.is_empty() {
+            return Ok(());
+        }
+        let digits = value
+            .strip_prefix('-')
+            .unwrap_or(value)
+            .bytes()
+            .filter(|x| *x != b'.');
+        let length = match self.element_type {
+            ElementType::Numeric(_) | ElementType::Decimal => digits.count(),
+            _ => value.chars().count(),
+        };
+        if length < self.min_length {
+            return Err("element is shorter than its minimum length");
+        }
+        if length > self.max_length {
+            return Err("element is longer than its maximum length");
+        }
+        let valid = match self.element_type {
+            ElementType::Identifier | ElementType::String => true,
+            ElementType::Numeric(_) => is_number(value, false),
+            ElementType::Decimal => is_number(value, true),
+            ElementType::Date => is_date(value),
+            ElementType::Time => is_time(value),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(match self.element_type {
+                ElementType::Date => "element is not a valid date (CCYYMMDD or YYMMDD)",
+                ElementType::Time => "element is not a valid time (HHMM[SS[d..d]])",
+                _ => "element is not numeric",
+            })
+        }
+    }
+}
+
+/// Whether `value` is a number with an optional leading minus sign and, if allowed, one decimal point.
+fn is_number(value: &str, allow_decimal_point: bool) -> bool {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+    let decimal_points = digits.bytes().filter(|x| *x == b'.').count();
+    digits.bytes().any(|x| x.is_ascii_digit())
+        && digits.bytes().all(|x| x.is_ascii_digit() || x == b'.')
+        && (decimal_points == 0 || (allow_decimal_point && decimal_points == 1))
+}
+
+/// Read an all-digit slice of `value` as a number.
+fn digits_at(value: &str, start: usize, end: usize) -> Option<u32> {
+    let digits = value.get(start..end)?;
+    if digits.bytes().all(|x| x.is_ascii_digit()) {
+        digits.parse().ok()
+    } else {
+        None
+    }
+}
+
+fn is_date(value: &str) -> bool {
+    let year_length = match value.len() {
+        8 => 4,
+        6 => 2,
+        _ => return false,
+    };
+    let (year, month, day) = match (
+        digits_at(value, 0, year_length),
+        digits_at(value, year_length, year_length + 2),
+        digits_at(value, year_length + 2, year_length + 4),
+    ) {
+        (Some(year), Some(month), Some(day)) => (year, month, day),
+        _ => return false,
+    };
+    // A two digit year could be in either century, so only reject February 29th if no century makes it valid.
+    let leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0 || year_length == 2);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap_year => 29,
+        2 => 28,
+        _ => return false,
+    };
+    day >= 1 && day <= days_in_month
+}
+
+fn is_time(value: &str) -> bool {
+    if value.len() < 4 || value.len() > 8 || value.len() == 5 {
+        return false;
+    }
+    let hours = digits_at(value, 0, 2);
+    let minutes = digits_at(value, 2, 4);
+    let seconds = if value.len() >= 6 {
+        digits_at(value, 4, 6)
+    } else {
+        Some(0)
+    };
+    let decimal_seconds = value.len() <= 6 || digits_at(value, 6, value.len()).is_some();
+    matches!((hours, minutes, seconds), (Some(h), Some(m), Some(s)) if h < 24 && m < 60 && s < 60)
+        && decimal_seconds
+}
+
+/// Split an element reference such as "BEG05" or "N104" into the segment ID and the element's position.
+/// The position is always the last two digits.
+pub(crate) fn parse_element_reference(reference: &str) -> Result<(&str, usize), EdiParseError> {
+    let split = reference.len().saturating_sub(2);
+    let position = reference
+        .get(split..)
+        .filter(|_| split > 0)
+        .and_then(|position| digits_at(position, 0, 2))
+        .filter(|position| *position > 0);
+    match position {
+        Some(position) => Ok((&reference[..split], position as usize)),
+        None => Err(EdiParseError::new(
+            EdiErrorKind::InvalidArgument,
+            &format!(
+                "invalid element reference {}: expected a segment ID followed by a two digit position, e.g. BEG05",
+                reference
+            ),
+            None,
+        )),
+    }
+}
+
+/// Check the elements of `segment` against every spec which applies to it, pushing any violations onto `errors`.
+pub(crate) fn validate_segment(
+    segment: &GenericSegment,
+    specs: &[(&str, usize, &ElementSpec)],
+    errors: &mut Vec<EdiParseError>,
+) {
+    for (segment_id, position, spec) in specs.iter() {
+        if *segment_id != segment.segment_abbreviation {
+            continue;
+        }
+        let value = match segment.element(*position) {
+            Some(value) => value,
+            None => continue,
+        };
+        if let Err(reason) = spec.check(value) {
+            let tokens = core::iter::once(segment.segment_abbreviation.as_ref())
+                .chain(segment.elements.iter().map(|x| x.as_ref()))
+                .collect();
+            errors.push(EdiParseError::new(
+                EdiErrorKind::InvalidElement,
+                &format!(
+                    "{}{:02} failed validation: {}  --  expected: {:?} of length {} to {}  received: {}",
+                    segment_id,
+                    position,
+                    reason,
+                    spec.element_type,
+                    spec.min_length,
+                    spec.max_length,
+                    value
+                ),
+                Some(tokens),
+            ));
+        }
+    }
+}
+
+#[test]
+fn check_element_types() {
+    let date = ElementSpec::new(ElementType::Date, 6, 8);
+    assert!(date.check("20200229").is_ok());
+    assert!(date.check("200229").is_ok());
+    assert!(date.check("").is_ok());
+    assert!(date.check("20210229").is_err());
+    assert!(date.check("19000229").is_err());
+    assert!(date.check("20201301").is_err());
+    assert!(date.check("2020-1-1").is_err());
+    assert!(date.check("2020").is_err());
+
+    let time = ElementSpec::new(ElementType::Time, 4, 8);
+    assert!(time.check("1534").is_ok());
+    assert!(time.check("153459").is_ok());
+    assert!(time.check("15345912").is_ok());
+    assert!(time.check("2400").is_err());
+    assert!(time.check("15345").is_err());
+
+    let quantity = ElementSpec::new(ElementType::Numeric(0), 1, 3);
+    assert!(quantity.check("-123").is_ok());
+    assert!(quantity.check("1234").is_err());
+    assert!(quantity.check("1.5").is_err());
+    assert!(quantity.check("EA").is_err());
+
+    let price = ElementSpec::new(ElementType::Decimal, 1, 4);
+    assert!(price.check("-86.00").is_ok());
+    assert!(price.check("8.600.0").is_err());
+    assert!(price.check("86.000").is_err());
+
+    let code = ElementSpec::new(ElementType::Identifier, 2, 2);
+    assert!(code.check("EA").is_ok());
+    assert!(code.check("E").is_err());
+}
+
+#[test]
+fn parse_element_references() {
+    assert_eq!(parse_element_reference("BEG05").unwrap(), ("BEG", 5));
+    assert_eq!(parse_element_reference("N104").unwrap(), ("N1", 4));
+    assert_eq!(parse_element_reference("TD110").unwrap(), ("TD1", 10));
+    assert!(parse_element_reference("05").is_err());
+    assert!(parse_element_reference("BEG00").is_err());
+    assert!(parse_element_reference("BEG").is_err());
+    assert!(parse_element_reference("BEGé").is_err());
+}
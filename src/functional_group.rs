@@ -1,15 +1,29 @@
-use crate::edi_parse_error::EdiParseError;
+use crate::code_list::FUNCTIONAL_IDENTIFIER_CODES;
+use crate::delimiters::Delimiters;
+use crate::edi_parse_error::{parse_count, EdiErrorKind, EdiParseError};
 
-use crate::transaction::Transaction;
+use crate::parse_options::ParseOptions;
+use crate::standards_version::StandardsVersion;
+use crate::transaction::{owned_trailer, write_trailer, Transaction};
 
 use crate::tokenizer::SegmentTokens;
+use alloc::borrow::Cow;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+#[cfg(feature = "chrono")]
+use chrono::NaiveDateTime;
+use core::fmt;
 use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
-use std::collections::VecDeque;
+
+/// The known responsible agency codes (GS07): "T" for the Transportation Data Coordinating Committee
+/// and "X" for the Accredited Standards Committee X12.
+const RESPONSIBLE_AGENCY_CODES: [&str; 2] = ["T", "X"];
 
 /// Represents a GS/GE segment which wraps a functional group.
 /// Documentation here gleaned mostly from [here](http://u.sezna.dev/b)
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
 pub struct FunctionalGroup<'a, 'b> {
     /// Identifies the function of this group.
     /// See http://ecomgx17.ecomtoday.com/edi/EDI_4010/el479.htm for a list of
@@ -50,9 +64,41 @@ pub struct FunctionalGroup<'a, 'b> {
     /// The transactions that this functional group contains.
     #[serde(borrow = "'a + 'b")]
     pub transactions: VecDeque<Transaction<'a, 'b>>,
+    /// The elements of the GE segment which closed this functional group, after its ID, when parsing with
+    /// [ParseOptions::preserve_original_trailers](struct.ParseOptions.html#structfield.preserve_original_trailers).
+    /// As long as they still agree with the functional group, they are written out verbatim instead of a generated GE.
+    #[serde(borrow, default)]
+    pub original_trailer: Option<Vec<Cow<'a, str>>>,
 }
 
 impl<'a, 'b> FunctionalGroup<'a, 'b> {
+    /// A functional group with empty envelope fields, which holds transactions parsed without their GS. See
+    /// [ParseOptions::allow_missing_interchange](struct.ParseOptions.html#structfield.allow_missing_interchange).
+    pub(crate) fn placeholder() -> FunctionalGroup<'a, 'b> {
+        FunctionalGroup {
+            functional_identifier_code: Cow::from(""),
+            application_sender_code: Cow::from(""),
+            application_receiver_code: Cow::from(""),
+            date: Cow::from(""),
+            time: Cow::from(""),
+            group_control_number: Cow::from(""),
+            responsible_agency_code: Cow::from(""),
+            version: Cow::from(""),
+            transactions: VecDeque::new(),
+            original_trailer: None,
+        }
+    }
+
+    /// Parse a single GS segment, e.g. "GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010", into a [FunctionalGroup]
+    /// without any transactions, splitting it on the given element delimiter. Surrounding whitespace is ignored,
+    /// but the segment delimiter must not be included.
+    pub fn parse_from_str(
+        input: &'a str,
+        element_delimiter: char,
+    ) -> Result<FunctionalGroup<'a, 'b>, EdiParseError> {
+        FunctionalGroup::parse_from_tokens(input.trim().split(element_delimiter).collect())
+    }
+
     /// Given [SegmentTokens](struct.SegmentTokens.html) (where the first token is "GS"), construct a [FunctionalGroup].
     pub(crate) fn parse_from_tokens(
         input: SegmentTokens<'a>,
@@ -61,11 +107,13 @@ impl<'a, 'b> FunctionalGroup<'a, 'b> {
         // I always inject invariants wherever I can to ensure debugging is quick and painless,
         // and to check my assumptions.
         edi_assert!(
+            EdiErrorKind::InvalidArgument,
             elements[0] == "GS",
             "attempted to parse GS from non-GS segment",
             input
         );
         edi_assert!(
+            EdiErrorKind::MissingElements,
             elements.len() >= 9,
             "GS segment does not contain enough elements. At least 9 required",
             input
@@ -100,9 +148,46 @@ impl<'a, 'b> FunctionalGroup<'a, 'b> {
             responsible_agency_code,
             version,
             transactions: VecDeque::new(),
+            original_trailer: None,
         })
     }
 
+    /// Copy every element into an owned string, so that the group no longer borrows from the input.
+    pub fn into_owned(self) -> FunctionalGroup<'static, 'b> {
+        FunctionalGroup {
+            functional_identifier_code: Cow::Owned(self.functional_identifier_code.into_owned()),
+            application_sender_code: Cow::Owned(self.application_sender_code.into_owned()),
+            application_receiver_code: Cow::Owned(self.application_receiver_code.into_owned()),
+            date: Cow::Owned(self.date.into_owned()),
+            time: Cow::Owned(self.time.into_owned()),
+            group_control_number: Cow::Owned(self.group_control_number.into_owned()),
+            responsible_agency_code: Cow::Owned(self.responsible_agency_code.into_owned()),
+            version: Cow::Owned(self.version.into_owned()),
+            transactions: self
+                .transactions
+                .into_iter()
+                .map(Transaction::into_owned)
+                .collect(),
+            original_trailer: self.original_trailer.map(owned_trailer),
+        }
+    }
+
+    /// Clone the GS header, but none of the transactions.
+    pub(crate) fn clone_envelope(&self) -> FunctionalGroup<'a, 'b> {
+        FunctionalGroup {
+            functional_identifier_code: self.functional_identifier_code.clone(),
+            application_sender_code: self.application_sender_code.clone(),
+            application_receiver_code: self.application_receiver_code.clone(),
+            date: self.date.clone(),
+            time: self.time.clone(),
+            group_control_number: self.group_control_number.clone(),
+            responsible_agency_code: self.responsible_agency_code.clone(),
+            version: self.version.clone(),
+            transactions: VecDeque::new(),
+            original_trailer: None,
+        }
+    }
+
     /// Enqueue a [Transaction] into the group. Subsequent segments will be enqueued into this transaction.
     pub(crate) fn add_transaction(
         &mut self,
@@ -113,40 +198,134 @@ impl<'a, 'b> FunctionalGroup<'a, 'b> {
         Ok(())
     }
 
+    /// Append a [Transaction] to the end of this functional group. [to_x12_string](#method.to_x12_string)
+    /// recomputes the GE transaction count, so it does not need to be updated.
+    pub fn push_transaction(&mut self, transaction: Transaction<'a, 'b>) {
+        self.transactions.push_back(transaction);
+    }
+
     /// Enqueue a [GenericSegment](struct.GenericSegment.html) into the most recently enqueued [Transaction].
     pub(crate) fn add_generic_segment(
         &mut self,
         tokens: SegmentTokens<'a>,
+        raw: Option<&'a str>,
     ) -> Result<(), EdiParseError> {
         if let Some(transaction) = self.transactions.back_mut() {
-            transaction.add_generic_segment(tokens)
+            transaction.add_generic_segment(tokens, raw)
         } else {
             Err(EdiParseError::new(
-                "unable to enqueue generic segment when no transactions have been enqueued",
+                EdiErrorKind::OutOfOrder,
+                &format!(
+                    "EDI file out of order: received {} but no transaction (ST) is open",
+                    tokens[0]
+                ),
                 Some(tokens),
             ))
         }
     }
 
+    /// The number of transactions in this group, as counted by the GE segment (GE01).
+    pub fn transaction_count(&self) -> usize {
+        self.transactions.len()
+    }
+
+    /// The human-readable name of the [functional_identifier_code](#structfield.functional_identifier_code) (GS01),
+    /// e.g. "PO" is "Purchase Order (850)", looked up in
+    /// [FUNCTIONAL_IDENTIFIER_CODES](constant.FUNCTIONAL_IDENTIFIER_CODES.html). `None` if the code is not bundled.
+    pub fn functional_identifier_name(&self) -> Option<&'static str> {
+        FUNCTIONAL_IDENTIFIER_CODES.description(&self.functional_identifier_code)
+    }
+
+    /// The version, release, and subrelease part of the [version](#structfield.version), i.e. its first six
+    /// characters: "004010" for "004010VICS". A version shorter than six characters is returned whole.
+    pub fn base_version(&self) -> &str {
+        self.version.get(..6).unwrap_or(&self.version)
+    }
+
+    /// The [version](#structfield.version) (GS08) as a comparable [StandardsVersion], e.g. 5010 for
+    /// "005010X222A1". `None` if it does not start with a version and release number.
+    pub fn standards_version(&self) -> Option<StandardsVersion> {
+        StandardsVersion::parse(&self.version)
+    }
+
+    /// The industry identifier following the first six characters of the [version](#structfield.version):
+    /// "VICS" for "004010VICS". `None` if the version has no industry identifier.
+    pub fn industry_identifier(&self) -> Option<&str> {
+        self.version
+            .get(6..)
+            .filter(|identifier| !identifier.is_empty())
+    }
+
+    /// The group date (GS04, `CCYYMMDD`) and time (GS05, `HHMM`, `HHMMSS`, or `HHMMSS` followed by one or two
+    /// digits of decimal seconds) combined into one timestamp. Requires the `chrono` feature.
+    #[cfg(feature = "chrono")]
+    pub fn datetime(&self) -> Result<NaiveDateTime, EdiParseError> {
+        edi_assert!(
+            EdiErrorKind::InvalidElement,
+            self.date.len() == 8 && [4, 6, 7, 8].contains(&self.time.len()),
+            "functional group date and time are not the expected length",
+            "CCYYMMDD HHMM[SS[D[D]]]",
+            format!("{} {}", self.date, self.time)
+        );
+        let mut timestamp = format!("{}{}", self.date, self.time.get(..4).unwrap_or_default());
+        match (self.time.get(4..6), self.time.get(6..)) {
+            (Some(seconds), Some(decimal_seconds)) => {
+                timestamp.push_str(seconds);
+                if !decimal_seconds.is_empty() {
+                    timestamp.push('.');
+                    timestamp.push_str(decimal_seconds);
+                }
+            }
+            _ => timestamp.push_str("00"),
+        }
+        NaiveDateTime::parse_from_str(&timestamp, "%Y%m%d%H%M%S%.f").map_err(|_| {
+            EdiParseError::new(
+                EdiErrorKind::InvalidElement,
+                &format!(
+                    "functional group date and time are not a valid timestamp  --  received: {} {}",
+                    self.date, self.time
+                ),
+                None,
+            )
+        })
+    }
+
+    /// The transaction at position `index` (starting from zero) within this group, or `None` if the group
+    /// contains fewer transactions.
+    pub fn transaction(&self, index: usize) -> Option<&Transaction<'a, 'b>> {
+        self.transactions.get(index)
+    }
+
     /// Verify this [FunctionalGroup] with a GE segment.
     pub(crate) fn validate_functional_group(
         &self,
         tokens: SegmentTokens<'a>,
+        options: &ParseOptions,
     ) -> Result<(), EdiParseError> {
         edi_assert!(
+            EdiErrorKind::InvalidArgument,
             tokens[0] == "GE",
             "attempted to call GE verification on non-GE segment",
             tokens
         );
         edi_assert!(
-            self.transactions.len() == str::parse::<usize>(tokens[1]).unwrap(),
+            EdiErrorKind::MissingElements,
+            tokens.len() >= 3,
+            "GE segment does not contain enough elements. At least 3 required",
+            tokens
+        );
+        let transaction_count = parse_count(tokens[1], &tokens)?;
+        edi_assert!(
+            EdiErrorKind::CountMismatch,
+            self.transaction_count() == transaction_count,
             "functional group validation failed: incorrect number of transactions",
-            self.transactions.len(),
-            str::parse::<usize>(tokens[1]).unwrap(),
+            self.transaction_count(),
+            transaction_count,
             tokens
         );
         edi_assert!(
-            self.group_control_number == tokens[2],
+            EdiErrorKind::ControlNumberMismatch,
+            options.control_numbers_match(&self.group_control_number, tokens[2]),
             "functional group validation failed: mismatched ID",
             self.group_control_number,
             tokens[2],
@@ -159,56 +338,150 @@ impl<'a, 'b> FunctionalGroup<'a, 'b> {
     pub(crate) fn validate_transaction(
         &self,
         tokens: SegmentTokens<'a>,
+        options: &ParseOptions,
     ) -> Result<(), EdiParseError> {
         if let Some(transaction) = self.transactions.back() {
-            transaction.validate_transaction(tokens)
+            transaction.validate_transaction(tokens, options)
         } else {
             Err(EdiParseError::new(
-                "unable to validate nonexistent transaction",
+                EdiErrorKind::OutOfOrder,
+                "EDI file out of order: received SE but no transaction (ST) is open",
                 Some(tokens),
             ))
         }
     }
 
+    /// Check that the responsible agency code is a known one and, for X12 ("X") groups, that the version has the
+    /// `VVVRRR` shape (three digits of version followed by three digits of release and subrelease), optionally
+    /// followed by an industry identifier of up to six characters. This is opt-in; parsing never calls it.
+    pub fn validate_version(&self) -> Result<(), EdiParseError> {
+        edi_assert!(
+            EdiErrorKind::InvalidElement,
+            RESPONSIBLE_AGENCY_CODES.contains(&self.responsible_agency_code.as_ref()),
+            "functional group validation failed: unknown responsible agency code",
+            RESPONSIBLE_AGENCY_CODES.join(" or "),
+            self.responsible_agency_code
+        );
+        if self.responsible_agency_code == "X" {
+            edi_assert!(
+                EdiErrorKind::InvalidElement,
+                self.version.len() >= 6
+                    && self.version.len() <= 12
+                    && self.version.bytes().take(6).all(|x| x.is_ascii_digit()),
+                "functional group validation failed: version must be six digits followed by an optional industry identifier",
+                "VVVRRR[industry identifier]",
+                self.version
+            );
+        }
+        Ok(())
+    }
+
     /// Converts this functional group into an ANSI x12 string for use in an EDI document.
     pub fn to_x12_string(&self, segment_delimiter: char, element_delimiter: char) -> String {
-        let header = String::from("GS");
-        let elements_of_gs = vec![
-            self.functional_identifier_code.clone(),
-            self.application_sender_code.clone(),
-            self.application_receiver_code.clone(),
-            self.date.clone(),
-            self.time.clone(),
-            self.group_control_number.clone(),
-            self.responsible_agency_code.clone(),
-            self.version.clone(),
+        let mut buffer = String::new();
+        self.write_x12(
+            &mut buffer,
+            segment_delimiter.encode_utf8(&mut [0; 4]),
+            element_delimiter,
+            None,
+        )
+        .expect("writing to a String cannot fail");
+        buffer
+    }
+
+    /// Like [to_x12_string](#method.to_x12_string), but returns an error instead of writing a GS segment with
+    /// an empty element or a transaction which is missing required elements.
+    pub fn try_to_x12_string(
+        &self,
+        segment_delimiter: char,
+        element_delimiter: char,
+    ) -> Result<String, EdiParseError> {
+        self.check_required_elements()?;
+        Ok(self.to_x12_string(segment_delimiter, element_delimiter))
+    }
+
+    /// Check that GS01 through GS08, which are all required, are not empty, and that every transaction has
+    /// its required elements.
+    pub(crate) fn check_required_elements(&self) -> Result<(), EdiParseError> {
+        let gs_elements = [
+            &self.functional_identifier_code,
+            &self.application_sender_code,
+            &self.application_receiver_code,
+            &self.date,
+            &self.time,
+            &self.group_control_number,
+            &self.responsible_agency_code,
+            &self.version,
         ];
+        for (idx, element) in gs_elements.iter().enumerate() {
+            edi_assert!(
+                EdiErrorKind::MissingElements,
+                !element.is_empty(),
+                format!("GS{:02} is required but empty", idx + 1).as_str()
+            );
+        }
+        for transaction in self.transactions.iter() {
+            transaction.check_required_elements()?;
+        }
+        Ok(())
+    }
 
-        let mut buffer = elements_of_gs.iter().fold(header, |mut acc, elem| {
-            acc.push(element_delimiter);
-            acc.push_str(&elem);
-            acc
-        });
-        let transactions = self
-            .transactions
-            .iter()
-            .fold(String::new(), |mut acc, transaction| {
-                acc.push(segment_delimiter);
-                acc.push_str(&transaction.to_x12_string(segment_delimiter, element_delimiter));
-                acc
-            });
-
-        buffer.push_str(&transactions);
-
-        let mut closer = String::from("GE");
-        closer.push(element_delimiter);
-        closer.push_str(&self.transactions.len().to_string());
-        closer.push(element_delimiter);
-        closer.push_str(&self.group_control_number);
-
-        buffer.push(segment_delimiter);
-        buffer.push_str(&closer);
-        buffer
+    /// Writes this functional group to `writer` segment by segment, formatted like
+    /// [to_x12_string](#method.to_x12_string).
+    pub(crate) fn write_x12<W: fmt::Write>(
+        &self,
+        writer: &mut W,
+        segment_separator: &str,
+        element_delimiter: char,
+        escape: Option<&Delimiters>,
+    ) -> fmt::Result {
+        let elements_of_gs = [
+            &self.functional_identifier_code,
+            &self.application_sender_code,
+            &self.application_receiver_code,
+            &self.date,
+            &self.time,
+            &self.group_control_number,
+            &self.responsible_agency_code,
+            &self.version,
+        ];
+
+        writer.write_str("GS")?;
+        for element in elements_of_gs.iter() {
+            writer.write_char(element_delimiter)?;
+            writer.write_str(element)?;
+        }
+
+        for transaction in self.transactions.iter() {
+            writer.write_str(segment_separator)?;
+            transaction.write_x12(writer, segment_separator, element_delimiter, escape)?;
+        }
+
+        writer.write_str(segment_separator)?;
+        write_trailer(
+            writer,
+            "GE",
+            element_delimiter,
+            self.original_trailer.as_deref(),
+            self.transaction_count(),
+            &self.group_control_number,
+        )
+    }
+}
+
+impl fmt::Display for FunctionalGroup<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = self.transactions.len();
+        write!(
+            f,
+            "Functional group {} #{} from {} to {} with {} transaction{}",
+            self.functional_identifier_code,
+            self.group_control_number,
+            self.application_sender_code,
+            self.application_receiver_code,
+            count,
+            if count == 1 { "" } else { "s" }
+        )
     }
 }
 
@@ -216,31 +489,31 @@ impl<'a, 'b> FunctionalGroup<'a, 'b> {
 fn functional_group_to_string() {
     use crate::GenericSegment;
     use std::iter::FromIterator;
-    let segments = VecDeque::from_iter(
-        vec![
-            GenericSegment {
-                segment_abbreviation: Cow::from("BGN"),
-                elements: vec!["20", "TEST_ID", "200615", "0000"]
-                    .iter()
-                    .map(|x| Cow::from(*x))
-                    .collect::<VecDeque<Cow<str>>>(),
-            },
-            GenericSegment {
-                segment_abbreviation: Cow::from("BGN"),
-                elements: vec!["15", "OTHER_TEST_ID", "", "", "END"]
-                    .iter()
-                    .map(|x| Cow::from(*x))
-                    .collect::<VecDeque<Cow<str>>>(),
-            },
-        ]
-        .into_iter(),
-    );
+    let segments = VecDeque::from_iter(vec![
+        GenericSegment {
+            segment_abbreviation: Cow::from("BGN"),
+            elements: ["20", "TEST_ID", "200615", "0000"]
+                .iter()
+                .map(|x| Cow::from(*x))
+                .collect::<VecDeque<Cow<str>>>(),
+            raw: None,
+        },
+        GenericSegment {
+            segment_abbreviation: Cow::from("BGN"),
+            elements: ["15", "OTHER_TEST_ID", "", "", "END"]
+                .iter()
+                .map(|x| Cow::from(*x))
+                .collect::<VecDeque<Cow<str>>>(),
+            raw: None,
+        },
+    ]);
     let transaction = Transaction {
         transaction_code: Cow::from("140"),
         transaction_name: "",
         transaction_set_control_number: Cow::from("100000001"),
         implementation_convention_reference: None,
-        segments: segments,
+        segments,
+        original_trailer: None,
     };
 
     let functional_group = FunctionalGroup {
@@ -252,9 +525,25 @@ fn functional_group_to_string() {
         group_control_number: Cow::from("1"),
         responsible_agency_code: Cow::from("X"),
         version: Cow::from("004010"),
-        transactions: VecDeque::from_iter(vec![transaction].into_iter()),
+        transactions: VecDeque::from_iter(vec![transaction]),
+        original_trailer: None,
     };
-    assert_eq!(functional_group.to_x12_string('\n', '*'), "GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010\nST*140*100000001*\nBGN*20*TEST_ID*200615*0000\nBGN*15*OTHER_TEST_ID***END\nSE*4*100000001\nGE*1*1");
+    assert_eq!(functional_group.to_x12_string('\n', '*'), "GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010\nST*140*100000001\nBGN*20*TEST_ID*200615*0000\nBGN*15*OTHER_TEST_ID***END\nSE*4*100000001\nGE*1*1");
+}
+
+#[cfg(test)]
+fn sample_gs_tokens() -> Vec<&'static str> {
+    vec![
+        "GS",
+        "PO",
+        "SENDERGS",
+        "007326879",
+        "20020226",
+        "1534",
+        "1",
+        "X",
+        "004010",
+    ]
 }
 
 #[test]
@@ -269,22 +558,127 @@ fn construct_functional_group() {
         responsible_agency_code: Cow::from("X"),
         version: Cow::from("004010"),
         transactions: VecDeque::new(),
+        original_trailer: None,
     };
 
-    let test_input = vec![
-        "GS",
-        "PO",
-        "SENDERGS",
-        "007326879",
-        "20020226",
-        "1534",
-        "1",
-        "X",
-        "004010",
-    ];
-
     assert_eq!(
-        FunctionalGroup::parse_from_tokens(test_input).unwrap(),
+        FunctionalGroup::parse_from_tokens(sample_gs_tokens()).unwrap(),
         expected_result
     );
 }
+
+#[test]
+fn validate_functional_group_version() {
+    let mut functional_group = FunctionalGroup::parse_from_str(
+        "GS*IN*4405197800*999999999*20101205*1710*1320*X*004010VICS",
+        '*',
+    )
+    .unwrap();
+    assert!(functional_group.validate_version().is_ok());
+
+    functional_group.version = Cow::from("04010");
+    assert!(functional_group.validate_version().is_err());
+
+    functional_group.version = Cow::from("004010");
+    functional_group.responsible_agency_code = Cow::from("Q");
+    assert!(functional_group.validate_version().is_err());
+
+    // other version formats are allowed for TDCC groups
+    functional_group.version = Cow::from("4010");
+    functional_group.responsible_agency_code = Cow::from("T");
+    assert!(functional_group.validate_version().is_ok());
+}
+
+#[test]
+fn split_functional_group_version() {
+    let mut functional_group = FunctionalGroup::parse_from_str(
+        "GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010VICS",
+        '*',
+    )
+    .unwrap();
+    assert_eq!(functional_group.base_version(), "004010");
+    assert_eq!(functional_group.industry_identifier(), Some("VICS"));
+    assert_eq!(
+        functional_group.standards_version(),
+        Some(StandardsVersion::V4010)
+    );
+
+    functional_group.version = Cow::from("004010");
+    assert_eq!(functional_group.base_version(), "004010");
+    assert_eq!(functional_group.industry_identifier(), None);
+
+    functional_group.version = Cow::from("4010");
+    assert_eq!(functional_group.base_version(), "4010");
+    assert_eq!(functional_group.industry_identifier(), None);
+    assert_eq!(functional_group.standards_version(), None);
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn functional_group_datetime() {
+    use chrono::{NaiveDate, NaiveDateTime};
+    let mut functional_group = FunctionalGroup::parse_from_tokens(sample_gs_tokens()).unwrap();
+    let expected = |h, m, s, milli| -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2002, 2, 26)
+            .unwrap()
+            .and_hms_milli_opt(h, m, s, milli)
+            .unwrap()
+    };
+    assert_eq!(functional_group.datetime().unwrap(), expected(15, 34, 0, 0));
+
+    functional_group.time = Cow::from("153412");
+    assert_eq!(
+        functional_group.datetime().unwrap(),
+        expected(15, 34, 12, 0)
+    );
+    functional_group.time = Cow::from("1534125");
+    assert_eq!(
+        functional_group.datetime().unwrap(),
+        expected(15, 34, 12, 500)
+    );
+    functional_group.time = Cow::from("15341207");
+    assert_eq!(
+        functional_group.datetime().unwrap(),
+        expected(15, 34, 12, 70)
+    );
+
+    functional_group.time = Cow::from("2534");
+    assert!(functional_group.datetime().is_err());
+    functional_group.time = Cow::from("15341");
+    assert!(functional_group.datetime().is_err());
+}
+
+#[test]
+fn parse_functional_group_from_str() {
+    let functional_group = FunctionalGroup::parse_from_str(
+        " GS|PO|SENDERGS|007326879|20020226|1534|1|X|004010\n",
+        '|',
+    )
+    .unwrap();
+    assert_eq!(functional_group.functional_identifier_code, "PO");
+    assert_eq!(functional_group.version, "004010");
+    assert!(functional_group.transactions.is_empty());
+    assert_eq!(
+        functional_group,
+        FunctionalGroup::parse_from_tokens(sample_gs_tokens()).unwrap()
+    );
+
+    assert!(FunctionalGroup::parse_from_str("GS|PO|SENDERGS", '|').is_err());
+    assert!(FunctionalGroup::parse_from_str("ST|850|0001", '|').is_err());
+}
+
+#[test]
+fn look_up_functional_identifier_names() {
+    let functional_group =
+        FunctionalGroup::parse_from_str("GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010", '*')
+            .unwrap();
+    assert_eq!(
+        functional_group.functional_identifier_name(),
+        Some("Purchase Order (850)")
+    );
+    let functional_group =
+        FunctionalGroup::parse_from_str("GS*ZZ*SENDERGS*007326879*20020226*1534*1*X*004010", '*')
+            .unwrap();
+    assert_eq!(functional_group.functional_identifier_code, "ZZ");
+    assert_eq!(functional_group.functional_identifier_name(), None);
+}
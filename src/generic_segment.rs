@@ -1,11 +1,47 @@
-use crate::edi_parse_error::EdiParseError;
-use crate::tokenizer::SegmentTokens;
+use crate::code_list::CodeList;
+#[cfg(test)]
+use crate::code_list::{ENTITY_IDENTIFIER_CODES, REFERENCE_IDENTIFICATION_QUALIFIERS};
+use crate::delimiters::Delimiters;
+use crate::edi_parse_error::{EdiErrorKind, EdiParseError};
+use crate::tokenizer::{binary_data_position, SegmentTokens};
+#[cfg(feature = "std")]
+use crate::transaction::load_names;
+use alloc::borrow::Cow;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
+#[cfg(feature = "rust_decimal")]
+use core::str::FromStr;
+#[cfg(feature = "std")]
+use lazy_static::lazy_static;
+#[cfg(feature = "rust_decimal")]
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
-use std::collections::VecDeque;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+
+// Load the names of the common segments from a csv, the same way transaction set names are loaded.
+#[cfg(feature = "std")]
+lazy_static! {
+    static ref SEGMENT_NAMES: Result<HashMap<String, String>, EdiParseError> =
+        load_names("segments.csv");
+}
+
+/// Look up the human-readable name of a segment ID, e.g. "REF" is "Reference Identification".
+/// The bundled names cover the envelope and the segments common across the standard X12 transaction sets.
+/// Returns `None` for every ID if the bundled segment file could not be read. Requires the `std` feature.
+#[cfg(feature = "std")]
+pub fn segment_name(segment_id: &str) -> Option<&'static str> {
+    SEGMENT_NAMES
+        .as_ref()
+        .ok()?
+        .get(segment_id)
+        .map(|name| name.as_str())
+}
 
 /// A generic segment.
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
 pub struct GenericSegment<'a> {
     /// The first element in the segment which denotes the segment type.
     #[serde(borrow)]
@@ -13,24 +49,74 @@ pub struct GenericSegment<'a> {
     /// The ordered list of elements in the segment.
     #[serde(borrow)]
     pub elements: VecDeque<Cow<'a, str>>,
+    /// The text this segment was parsed from, when parsing with
+    /// [ParseOptions::keep_raw](struct.ParseOptions.html#structfield.keep_raw). Unlike
+    /// [to_x12_string](#method.to_x12_string), it reproduces the segment exactly as the partner sent it.
+    #[serde(borrow, default)]
+    pub raw: Option<Cow<'a, str>>,
 }
 
 impl<'a> GenericSegment<'a> {
-    #[doc(skip)]
-    /// Given [SegmentTokens](struct.SegmentTokens.html), construct a [GenericSegment].
+    /// Construct a [GenericSegment] from its abbreviation and elements. Both `&str` and `String` are accepted,
+    /// so this works for borrowed data as well as data built up for an outbound document.
+    pub fn new<S, E>(abbreviation: S, elements: E) -> GenericSegment<'a>
+    where
+        S: Into<Cow<'a, str>>,
+        E: IntoIterator,
+        E::Item: Into<Cow<'a, str>>,
+    {
+        GenericSegment {
+            segment_abbreviation: abbreviation.into(),
+            elements: elements.into_iter().map(Into::into).collect(),
+            raw: None,
+        }
+    }
+
+    /// Copy the abbreviation and elements into owned strings, so that the segment no longer borrows from the input.
+    pub fn into_owned(self) -> GenericSegment<'static> {
+        GenericSegment {
+            segment_abbreviation: Cow::Owned(self.segment_abbreviation.into_owned()),
+            elements: self
+                .elements
+                .into_iter()
+                .map(|element| Cow::Owned(element.into_owned()))
+                .collect(),
+            raw: self.raw.map(|raw| Cow::Owned(raw.into_owned())),
+        }
+    }
+
+    /// The human-readable name of this segment, looked up from its
+    /// [segment_abbreviation](#structfield.segment_abbreviation). `None` if the segment is not in the bundled
+    /// dictionary. Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn segment_name(&self) -> Option<&'static str> {
+        segment_name(&self.segment_abbreviation)
+    }
+
+    /// Translate the coded value of the element at the given X12 position (see [element](#method.element)) to its
+    /// description in `code_list`, e.g. "ST" in an N101 is "Ship To" according to
+    /// [ENTITY_IDENTIFIER_CODES](constant.ENTITY_IDENTIFIER_CODES.html). `None` if the element is missing or its
+    /// value is not in the list.
+    pub fn decode_element(&self, index: usize, code_list: &CodeList) -> Option<&'static str> {
+        self.element(index)
+            .and_then(|code| code_list.description(code))
+    }
+
+    /// Given [SegmentTokens](struct.SegmentTokens.html), construct a [GenericSegment]. The tokens are
+    /// taken as-is; any whitespace trimming has already happened in the parser. Empty elements, including
+    /// trailing ones, are kept, because dropping one would shift the position of every element after it.
     pub(crate) fn parse_from_tokens(
         tokens: SegmentTokens<'a>,
-    ) -> Result<GenericSegment, EdiParseError> {
-        let elements: Vec<&str> = tokens.iter().map(|x| x.trim()).collect();
+    ) -> Result<GenericSegment<'a>, EdiParseError> {
         edi_assert!(
-            elements.len() >= 2,
+            EdiErrorKind::MissingElements,
+            tokens.len() >= 2,
             "at least two elements are required in a segment",
             tokens
         );
-        let segment_abbreviation = Cow::from(elements[0]);
+        let segment_abbreviation = Cow::from(tokens[0]);
 
-        let elements = elements[1..]
-            .to_vec()
+        let elements = tokens[1..]
             .iter()
             .map(|x| Cow::from(*x))
             .collect::<VecDeque<Cow<str>>>();
@@ -38,19 +124,169 @@ impl<'a> GenericSegment<'a> {
         Ok(GenericSegment {
             segment_abbreviation,
             elements,
+            raw: None,
         })
     }
 
+    /// Get an element by its X12 position: index 1 is the first element after the abbreviation (e.g. REF01),
+    /// and index 0 is the abbreviation itself. Returns `None` if the segment has no element at that position.
+    pub fn element(&self, index: usize) -> Option<&str> {
+        match index {
+            0 => Some(&self.segment_abbreviation),
+            _ => self.elements.get(index - 1).map(|x| x.as_ref()),
+        }
+    }
+
+    /// Replace the element at the given X12 position (see [element](#method.element)), e.g. to redact an ID or
+    /// correct a date. Index 0 replaces the abbreviation. If the segment has fewer elements, it is padded with
+    /// empty elements up to the position. Both `&str` and `String` are accepted; [raw](#structfield.raw) keeps the
+    /// text as it was received.
+    pub fn set_element<V: Into<Cow<'a, str>>>(&mut self, index: usize, value: V) {
+        if index == 0 {
+            self.segment_abbreviation = value.into();
+            return;
+        }
+        if self.elements.len() < index {
+            self.elements.resize(index, Cow::Borrowed(""));
+        }
+        self.elements[index - 1] = value.into();
+    }
+
+    /// Read the element at the given X12 position (see [element](#method.element)) as an integer (X12 type N0).
+    /// A leading or trailing minus sign is accepted. Missing or non-numeric elements produce an [EdiParseError]
+    /// rather than a panic.
+    pub fn element_as_integer(&self, index: usize) -> Result<i64, EdiParseError> {
+        let (negative, digits) = self.numeric_element(index, false)?;
+        match str::parse::<i64>(digits) {
+            Ok(value) if negative => Ok(-value),
+            Ok(value) => Ok(value),
+            Err(_) => Err(self.numeric_error(index, "integer out of range")),
+        }
+    }
+
+    /// Read the element at the given X12 position (see [element](#method.element)) as a decimal number (X12 type R),
+    /// which may contain an explicit decimal point and a leading or trailing minus sign.
+    /// Requires the `rust_decimal` feature.
+    #[cfg(feature = "rust_decimal")]
+    pub fn element_as_decimal(&self, index: usize) -> Result<Decimal, EdiParseError> {
+        let (negative, digits) = self.numeric_element(index, true)?;
+        match Decimal::from_str(digits) {
+            Ok(value) if negative => Ok(-value),
+            Ok(value) => Ok(value),
+            Err(_) => Err(self.numeric_error(index, "decimal out of range")),
+        }
+    }
+
+    /// Read the element at the given X12 position (see [element](#method.element)) as a numeric with
+    /// `implied_scale` implied decimal places (X12 type Nn), e.g. "2174000" read as an N2 is 21740.00.
+    /// Per X12, a decimal point which is present overrides the implied scale, so "21740.5" is 21740.5.
+    /// Requires the `rust_decimal` feature.
+    #[cfg(feature = "rust_decimal")]
+    pub fn element_as_amount(
+        &self,
+        index: usize,
+        implied_scale: u32,
+    ) -> Result<Decimal, EdiParseError> {
+        let (negative, digits) = self.numeric_element(index, true)?;
+        let value = if digits.contains('.') {
+            Decimal::from_str(digits).ok()
+        } else {
+            str::parse::<i128>(digits).ok().and_then(|mantissa| {
+                Decimal::try_from_i128_with_scale(mantissa, implied_scale).ok()
+            })
+        };
+        match value {
+            Some(value) if negative => Ok(-value),
+            Some(value) => Ok(value),
+            None => Err(self.numeric_error(index, "amount out of range")),
+        }
+    }
+
+    /// Split the element at `index` into its sign and its unsigned digits, checking that it is numeric.
+    pub(crate) fn numeric_element(
+        &self,
+        index: usize,
+        allow_decimal_point: bool,
+    ) -> Result<(bool, &str), EdiParseError> {
+        let value = match self.element(index) {
+            Some(value) if index > 0 => value,
+            _ => return Err(self.numeric_error(index, "element does not exist")),
+        };
+        let (negative, digits) = if let Some(digits) = value.strip_prefix('-') {
+            (true, digits)
+        } else if let Some(digits) = value.strip_suffix('-') {
+            (true, digits)
+        } else {
+            (false, value)
+        };
+        let decimal_points = digits.bytes().filter(|x| *x == b'.').count();
+        let is_numeric = digits.bytes().any(|x| x.is_ascii_digit())
+            && digits.bytes().all(|x| x.is_ascii_digit() || x == b'.')
+            && (decimal_points == 0 || (allow_decimal_point && decimal_points == 1));
+        if !is_numeric {
+            return Err(self.numeric_error(index, "element is not numeric"));
+        }
+        Ok((negative, digits))
+    }
+
+    fn numeric_error(&self, index: usize, reason: &str) -> EdiParseError {
+        EdiParseError::new(
+            EdiErrorKind::InvalidElement,
+            &format!(
+                "unable to read {}{:02} as a number: {}",
+                self.segment_abbreviation, index, reason
+            ),
+            None,
+        )
+    }
+
     /// Converts a single generic segment into an ANSI x12 compliant string to be used in an EDI
     /// document.
     pub fn to_x12_string(&self, element_delimiter: char) -> String {
-        self.elements
-            .iter()
-            .fold(self.segment_abbreviation.to_string(), |mut acc, s| {
-                acc.push(element_delimiter);
-                acc.push_str(s);
-                acc
-            })
+        let mut buffer = String::new();
+        self.write_x12(&mut buffer, element_delimiter, None)
+            .expect("writing to a String cannot fail");
+        buffer
+    }
+
+    /// Writes this segment to `writer` the same way [to_x12_string](#method.to_x12_string) formats it. If
+    /// `escape` has a release character, delimiters in the elements are escaped with it, except in the data of a
+    /// binary segment, which is delimited by its length.
+    pub(crate) fn write_x12<W: fmt::Write>(
+        &self,
+        writer: &mut W,
+        element_delimiter: char,
+        escape: Option<&Delimiters>,
+    ) -> fmt::Result {
+        writer.write_str(&self.segment_abbreviation)?;
+        let binary_data = binary_data_position(&self.segment_abbreviation);
+        for (idx, element) in self.elements.iter().enumerate() {
+            writer.write_char(element_delimiter)?;
+            match escape {
+                Some(delimiters) if Some(idx + 1) != binary_data => {
+                    delimiters.write_escaped(writer, element)?
+                }
+                _ => writer.write_str(element)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for GenericSegment<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.segment_abbreviation)?;
+        #[cfg(feature = "std")]
+        if let Some(name) = self.segment_name() {
+            write!(f, " ({})", name)?;
+        }
+        let count = self.elements.len();
+        write!(
+            f,
+            " segment with {} element{}",
+            count,
+            if count == 1 { "" } else { "s" }
+        )
     }
 }
 
@@ -58,10 +294,11 @@ impl<'a> GenericSegment<'a> {
 fn convert_generic_segment_to_string() {
     let segment = GenericSegment {
         segment_abbreviation: Cow::from("BGN"),
-        elements: vec!["20", "TEST_ID", "200615", "0000"]
+        elements: ["20", "TEST_ID", "200615", "0000"]
             .iter()
             .map(|x| Cow::from(*x))
             .collect::<VecDeque<Cow<str>>>(),
+        raw: None,
     };
 
     assert_eq!(segment.to_x12_string('*'), "BGN*20*TEST_ID*200615*0000");
@@ -83,7 +320,7 @@ fn construct_generic_segment() {
 
     let expected_result = GenericSegment {
         segment_abbreviation: Cow::from("GS"),
-        elements: vec![
+        elements: [
             "PO",
             "SENDERGS",
             "007326879",
@@ -96,6 +333,7 @@ fn construct_generic_segment() {
         .iter()
         .map(|x| Cow::from(*x))
         .collect::<VecDeque<Cow<str>>>(),
+        raw: None,
     };
 
     assert_eq!(
@@ -103,3 +341,151 @@ fn construct_generic_segment() {
         expected_result
     );
 }
+
+#[test]
+fn construct_generic_segment_with_new() {
+    let owned_id = String::from("54321");
+    let segment = GenericSegment::new("REF", vec!["VR".to_string(), owned_id]);
+    assert_eq!(segment.to_x12_string('*'), "REF*VR*54321");
+
+    let segment = GenericSegment::new(String::from("N1"), ["ST", "", "92", "123"].iter().copied());
+    assert_eq!(segment.segment_abbreviation, "N1");
+    assert_eq!(segment.elements.len(), 4);
+    assert_eq!(segment.to_x12_string('*'), "N1*ST**92*123");
+}
+
+#[test]
+fn read_numeric_elements() {
+    let segment = GenericSegment::new("IT1", vec!["1", "4", "EA", "8.60", "", "UP", "30-", "-12"]);
+    assert_eq!(segment.element(0), Some("IT1"));
+    assert_eq!(segment.element(3), Some("EA"));
+    assert_eq!(segment.element(9), None);
+
+    assert_eq!(segment.element_as_integer(2).unwrap(), 4);
+    assert_eq!(segment.element_as_integer(7).unwrap(), -30);
+    assert_eq!(segment.element_as_integer(8).unwrap(), -12);
+    assert!(segment.element_as_integer(3).is_err()); // not numeric
+    assert!(segment.element_as_integer(4).is_err()); // has a decimal point
+    assert!(segment.element_as_integer(5).is_err()); // empty
+    assert!(segment.element_as_integer(9).is_err()); // missing
+}
+
+#[cfg(feature = "rust_decimal")]
+#[test]
+fn read_decimal_elements() {
+    let segment = GenericSegment::new("IT1", vec!["1", "4", "EA", "8.60", "-.5", "1.2.3"]);
+    assert_eq!(segment.element_as_decimal(4).unwrap(), Decimal::new(860, 2));
+    assert_eq!(segment.element_as_decimal(5).unwrap(), Decimal::new(-5, 1));
+    assert_eq!(segment.element_as_decimal(2).unwrap(), Decimal::new(4, 0));
+    assert!(segment.element_as_decimal(3).is_err());
+    assert!(segment.element_as_decimal(6).is_err());
+}
+
+#[cfg(feature = "rust_decimal")]
+#[test]
+fn read_amount_elements() {
+    let segment = GenericSegment::new(
+        "TDS",
+        vec!["2174000", "-2174000", "21740.5", "5", "", "1.2.3"],
+    );
+    assert_eq!(
+        segment.element_as_amount(1, 2).unwrap(),
+        Decimal::new(2174000, 2)
+    );
+    assert_eq!(
+        segment.element_as_amount(1, 2).unwrap().to_string(),
+        "21740.00"
+    );
+    assert_eq!(
+        segment.element_as_amount(2, 2).unwrap(),
+        Decimal::new(-2174000, 2)
+    );
+    assert_eq!(
+        segment.element_as_amount(3, 2).unwrap(),
+        Decimal::new(217405, 1)
+    );
+    assert_eq!(segment.element_as_amount(4, 2).unwrap(), Decimal::new(5, 2));
+    assert_eq!(segment.element_as_amount(4, 0).unwrap(), Decimal::new(5, 0));
+    assert!(segment.element_as_amount(4, 29).is_err()); // scale beyond what a Decimal can hold
+    assert!(segment.element_as_amount(5, 2).is_err());
+    assert!(segment.element_as_amount(6, 2).is_err());
+    assert!(segment.element_as_amount(7, 2).is_err());
+}
+
+#[test]
+fn empty_elements_round_trip() {
+    for input in ["REF*DP*099", "N1*ST**92*123", "PER*IC***", "BEG*00*SA*1*"].iter() {
+        let segment = GenericSegment::parse_from_tokens(input.split('*').collect()).unwrap();
+        assert_eq!(segment.elements.len(), input.matches('*').count());
+        assert_eq!(&segment.to_x12_string('*'), input);
+    }
+
+    let segment = GenericSegment::parse_from_tokens(vec!["N1", "ST", "", "92", "123"]).unwrap();
+    assert_eq!(segment.element(2), Some(""));
+    assert_eq!(segment.element(3), Some("92"));
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn look_up_segment_names() {
+    assert_eq!(segment_name("REF"), Some("Reference Identification"));
+    assert_eq!(
+        segment_name("SAC"),
+        Some("Service, Promotion, Allowance, or Charge Information")
+    );
+    assert_eq!(segment_name("not a segment"), None);
+
+    let segment = GenericSegment::new("N1", vec!["ST", "", "92", "123"]);
+    assert_eq!(segment.segment_name(), Some("Name"));
+    assert_eq!(GenericSegment::new("ZZZ", vec!["1"]).segment_name(), None);
+}
+
+#[test]
+fn display_generic_segment() {
+    #[cfg(feature = "std")]
+    assert_eq!(
+        GenericSegment::new("DTM", vec!["002", "19971219"]).to_string(),
+        "DTM (Date/Time Reference) segment with 2 elements"
+    );
+    assert_eq!(
+        GenericSegment::new("ZZZ", vec!["1"]).to_string(),
+        "ZZZ segment with 1 element"
+    );
+    assert_eq!(
+        GenericSegment::new("ZZZ", Vec::<&str>::new()).to_string(),
+        "ZZZ segment with 0 elements"
+    );
+}
+
+#[test]
+fn decode_coded_elements() {
+    let segment = GenericSegment::new("N1", vec!["ST", "", "92", "123"]);
+    assert_eq!(
+        segment.decode_element(1, &ENTITY_IDENTIFIER_CODES),
+        Some("Ship To")
+    );
+    assert_eq!(segment.decode_element(2, &ENTITY_IDENTIFIER_CODES), None);
+    assert_eq!(segment.decode_element(9, &ENTITY_IDENTIFIER_CODES), None);
+
+    let segment = GenericSegment::new("REF", vec!["IA", "54321"]);
+    assert_eq!(
+        segment.decode_element(1, &REFERENCE_IDENTIFICATION_QUALIFIERS),
+        Some("Internal Vendor Number")
+    );
+}
+
+#[test]
+fn set_elements() {
+    let mut segment = GenericSegment::new("N1", vec!["ST", "", "92", "123"]);
+    segment.set_element(4, "REDACTED");
+    segment.set_element(2, String::from("SHIP TO"));
+    assert_eq!(segment.to_x12_string('*'), "N1*ST*SHIP TO*92*REDACTED");
+
+    segment.set_element(6, "ZZ");
+    assert_eq!(segment.to_x12_string('*'), "N1*ST*SHIP TO*92*REDACTED**ZZ");
+    assert_eq!(segment.element(5), Some(""));
+
+    segment.set_element(0, "N2");
+    assert_eq!(segment.segment_abbreviation, "N2");
+    assert_eq!(segment.elements.len(), 6);
+}
@@ -0,0 +1,174 @@
+use crate::edi_parse_error::{EdiErrorKind, EdiParseError};
+use crate::generic_segment::GenericSegment;
+use alloc::format;
+use alloc::vec::Vec;
+
+/// A hierarchical level (HL) loop within a transaction, as built by
+/// [Transaction::build_hl_tree](struct.Transaction.html#method.build_hl_tree). This is a read-only view which
+/// borrows the segments of the transaction it was built from.
+#[derive(PartialEq, Debug)]
+pub struct HlNode<'s, 'a> {
+    /// The HL segment which opens this loop. `None` for the root node, which stands for the transaction itself.
+    pub hl_segment: Option<&'s GenericSegment<'a>>,
+    /// The segments which belong to this loop, in document order, not including the HL segment itself.
+    /// For the root node, these are the segments before the first HL segment.
+    pub segments: Vec<&'s GenericSegment<'a>>,
+    /// The loops whose parent (HL02) is this loop, in document order.
+    pub children: Vec<HlNode<'s, 'a>>,
+}
+
+impl<'s, 'a> HlNode<'s, 'a> {
+    /// The hierarchical ID number (HL01) of this loop. `None` for the root node.
+    pub fn id(&self) -> Option<&'s str> {
+        self.hl_segment.and_then(|segment| segment.element(1))
+    }
+
+    /// The hierarchical ID number of this loop's parent (HL02). `None` for top-level loops and the root node.
+    pub fn parent_id(&self) -> Option<&'s str> {
+        self.hl_segment
+            .and_then(|segment| segment.element(2))
+            .filter(|parent_id| !parent_id.is_empty())
+    }
+
+    /// The hierarchical level code (HL03) of this loop, e.g. "S" for shipment or "I" for item in an 856.
+    /// `None` for the root node.
+    pub fn level_code(&self) -> Option<&'s str> {
+        self.hl_segment.and_then(|segment| segment.element(3))
+    }
+
+    /// Whether the HL segment says this loop has children (HL04 is "1") or not ("0"). `None` for the root node
+    /// and for loops which omit HL04.
+    pub fn has_children(&self) -> Option<bool> {
+        match self.hl_segment.and_then(|segment| segment.element(4)) {
+            Some("1") => Some(true),
+            Some("0") => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Build the tree of HL loops out of the segments of a transaction. Every segment belongs to the loop
+    /// opened by the closest HL segment before it, so trailing summary segments (e.g. CTT) end up in the last loop.
+    pub(crate) fn build<I>(segments: I) -> Result<HlNode<'s, 'a>, EdiParseError>
+    where
+        I: IntoIterator<Item = &'s GenericSegment<'a>>,
+    {
+        let mut root = HlNode {
+            hl_segment: None,
+            segments: Vec::new(),
+            children: Vec::new(),
+        };
+        // The loops in document order, with the position of their parent loop. Parents have to appear before
+        // their children, so the tree can be assembled from the back afterwards.
+        let mut loops: Vec<(HlNode<'s, 'a>, Option<usize>)> = Vec::new();
+
+        for segment in segments {
+            if segment.segment_abbreviation != "HL" {
+                match loops.last_mut() {
+                    Some((current_loop, _)) => current_loop.segments.push(segment),
+                    None => root.segments.push(segment),
+                }
+                continue;
+            }
+            let node = HlNode {
+                hl_segment: Some(segment),
+                segments: Vec::new(),
+                children: Vec::new(),
+            };
+            let id = node.id().unwrap_or("");
+            edi_assert!(
+                EdiErrorKind::MissingElements,
+                !id.is_empty(),
+                "HL segment does not contain a hierarchical ID number",
+                "HL01",
+                segment.to_x12_string('*')
+            );
+            edi_assert!(
+                EdiErrorKind::InvalidHierarchy,
+                !loops.iter().any(|(other, _)| other.id() == Some(id)),
+                format!("duplicate hierarchical ID number {}", id).as_str()
+            );
+            let parent = match node.parent_id() {
+                Some(parent_id) => {
+                    let parent = loops
+                        .iter()
+                        .position(|(other, _)| other.id() == Some(parent_id));
+                    edi_assert!(
+                        EdiErrorKind::InvalidHierarchy,
+                        parent.is_some(),
+                        "HL segment refers to a parent which does not precede it",
+                        parent_id,
+                        segment.to_x12_string('*')
+                    );
+                    parent
+                }
+                None => None,
+            };
+            loops.push((node, parent));
+        }
+
+        while let Some((node, parent)) = loops.pop() {
+            match parent {
+                Some(parent) => loops[parent].0.children.insert(0, node),
+                None => root.children.insert(0, node),
+            }
+        }
+        Ok(root)
+    }
+}
+
+#[test]
+fn build_hl_tree() {
+    let segments = [
+        GenericSegment::new("BSN", vec!["14", "829716", "20111206", "142428", "0002"]),
+        GenericSegment::new("HL", vec!["1", "", "S"]),
+        GenericSegment::new("TD1", vec!["PCS", "2"]),
+        GenericSegment::new("HL", vec!["2", "1", "O", "1"]),
+        GenericSegment::new("PRF", vec!["99999817"]),
+        GenericSegment::new("HL", vec!["3", "2", "I", "0"]),
+        GenericSegment::new("LIN", vec!["1", "VP", "87787D"]),
+        GenericSegment::new("SN1", vec!["1", "24", "EA"]),
+        GenericSegment::new("HL", vec!["4", "2", "I", "0"]),
+        GenericSegment::new("LIN", vec!["2", "VP", "99887D"]),
+        GenericSegment::new("CTT", vec!["4", "30"]),
+    ];
+    let root = HlNode::build(segments.iter()).unwrap();
+    assert_eq!(root.id(), None);
+    assert_eq!(root.segments, vec![&segments[0]]);
+    assert_eq!(root.children.len(), 1);
+
+    let shipment = &root.children[0];
+    assert_eq!(shipment.id(), Some("1"));
+    assert_eq!(shipment.parent_id(), None);
+    assert_eq!(shipment.level_code(), Some("S"));
+    assert_eq!(shipment.has_children(), None);
+    assert_eq!(shipment.segments, vec![&segments[2]]);
+
+    let order = &shipment.children[0];
+    assert_eq!(order.parent_id(), Some("1"));
+    assert_eq!(order.has_children(), Some(true));
+    let items: Vec<Option<&str>> = order.children.iter().map(|item| item.id()).collect();
+    assert_eq!(items, vec![Some("3"), Some("4")]);
+    assert_eq!(order.children[0].segments, vec![&segments[6], &segments[7]]);
+    assert_eq!(
+        order.children[1].segments,
+        vec![&segments[9], &segments[10]]
+    );
+}
+
+#[test]
+fn fail_to_build_hl_tree() {
+    let missing_parent = [
+        GenericSegment::new("HL", vec!["1", "", "S"]),
+        GenericSegment::new("HL", vec!["2", "5", "O"]),
+    ];
+    assert!(HlNode::build(missing_parent.iter()).is_err());
+
+    let duplicate_id = [
+        GenericSegment::new("HL", vec!["1", "", "S"]),
+        GenericSegment::new("HL", vec!["1", "", "S"]),
+    ];
+    assert!(HlNode::build(duplicate_id.iter()).is_err());
+
+    let missing_id = [GenericSegment::new("HL", vec!["", "", "S"])];
+    assert!(HlNode::build(missing_id.iter()).is_err());
+}
@@ -1,14 +1,38 @@
-use crate::edi_parse_error::EdiParseError;
+use crate::delimiters::Delimiters;
+use crate::edi_parse_error::{parse_count, EdiErrorKind, EdiParseError};
 use crate::functional_group::FunctionalGroup;
+use crate::generic_segment::GenericSegment;
+use crate::parse_options::ParseOptions;
+use crate::standards_version::StandardsVersion;
+use crate::transaction::{owned_trailer, write_trailer};
 
 use crate::tokenizer::SegmentTokens;
 
+use alloc::borrow::Cow;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+#[cfg(feature = "chrono")]
+use chrono::NaiveDateTime;
+use core::fmt;
 use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
-use std::collections::VecDeque;
+
+/// The interchange ID qualifiers (ISA05 and ISA07) defined by X12, e.g. "01" for a DUNS number,
+/// "12" for a phone number, and "ZZ" for a mutually defined ID.
+const INTERCHANGE_ID_QUALIFIERS: [&str; 41] = [
+    "01", "02", "03", "04", "07", "08", "09", "10", "11", "12", "13", "14", "15", "16", "17", "18",
+    "19", "20", "21", "22", "23", "24", "25", "26", "27", "28", "29", "30", "31", "32", "33", "34",
+    "35", "36", "37", "38", "AM", "NR", "SA", "SN", "ZZ",
+];
+
+/// The fixed widths of ISA01 through ISA15, e.g. `ISA_ELEMENT_WIDTHS[5]` is the width of the sender ID (ISA06).
+/// ISA16 is the single-character sub-element separator.
+pub const ISA_ELEMENT_WIDTHS: [usize; 15] = [2, 10, 2, 10, 2, 15, 2, 15, 6, 4, 1, 5, 9, 1, 1];
 
 /// Represents the ISA/IEA header information commonly known as the "envelope" in X12 EDI.
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
 pub struct InterchangeControl<'a, 'b> {
     // I chose to use `Cow`s here because I don't know how the crate will be used --
     // given enough documents of sufficient size and a restrictive enough environment,
@@ -80,12 +104,92 @@ pub struct InterchangeControl<'a, 'b> {
     /// production ("P"), or information ("I").
     #[serde(borrow)]
     pub test_indicator: Cow<'a, str>, // P for production, T for test
+    /// Segments which appeared in this interchange before its first functional group. These are only
+    /// accepted when parsing with [ParseOptions::allow_segments_before_group](struct.ParseOptions.html#structfield.allow_segments_before_group).
+    #[serde(borrow, default)]
+    pub interchange_segments: VecDeque<GenericSegment<'a>>,
     /// The [FunctionalGroups](struct.FunctionalGroup.html) contained in this interchange.
     #[serde(borrow = "'a + 'b")]
     pub functional_groups: VecDeque<FunctionalGroup<'a, 'b>>,
+    /// The elements of the IEA segment which closed this interchange, after its ID, when parsing with
+    /// [ParseOptions::preserve_original_trailers](struct.ParseOptions.html#structfield.preserve_original_trailers).
+    /// As long as they still agree with the interchange, they are written out verbatim instead of a generated IEA.
+    #[serde(borrow, default)]
+    pub original_trailer: Option<Vec<Cow<'a, str>>>,
 }
 
 impl<'a, 'b> InterchangeControl<'a, 'b> {
+    /// An interchange with empty envelope fields, which holds a fragment parsed without its ISA. See
+    /// [ParseOptions::allow_missing_interchange](struct.ParseOptions.html#structfield.allow_missing_interchange).
+    pub(crate) fn placeholder() -> InterchangeControl<'a, 'b> {
+        InterchangeControl {
+            authorization_qualifier: Cow::from(""),
+            authorization_information: Cow::from(""),
+            security_qualifier: Cow::from(""),
+            security_information: Cow::from(""),
+            sender_qualifier: Cow::from(""),
+            sender_id: Cow::from(""),
+            receiver_qualifier: Cow::from(""),
+            receiver_id: Cow::from(""),
+            date: Cow::from(""),
+            time: Cow::from(""),
+            standards_id: Cow::from(""),
+            version: Cow::from(""),
+            interchange_control_number: Cow::from(""),
+            acknowledgement_requested: Cow::from(""),
+            test_indicator: Cow::from(""),
+            interchange_segments: VecDeque::new(),
+            functional_groups: VecDeque::new(),
+            original_trailer: None,
+        }
+    }
+
+    /// Construct an [InterchangeControl] from the text of an ISA segment by reading each element at its fixed
+    /// position (see [ISA_ELEMENT_WIDTHS]) instead of splitting on the element delimiter, so an element may
+    /// contain the element delimiter as data. The element delimiter is the character following "ISA", and it
+    /// must still separate the elements at their fixed positions. Anything after ISA16, such as the segment
+    /// delimiter, is ignored.
+    pub fn parse_isa_fixed_width(
+        raw: &'a str,
+    ) -> Result<InterchangeControl<'a, 'b>, EdiParseError> {
+        let raw = raw.trim_start();
+        edi_assert!(
+            EdiErrorKind::MalformedHeader,
+            raw.starts_with("ISA"),
+            "malformed ISA header: segment must begin with ISA",
+            "ISA",
+            raw.get(..3).unwrap_or(raw)
+        );
+        let element_delimiter = raw.as_bytes().get(3).copied();
+        let mut tokens = vec!["ISA"];
+        let mut start = 3;
+        // ISA16, the sub-element separator, is a single character.
+        for width in ISA_ELEMENT_WIDTHS.iter().chain(core::iter::once(&1)) {
+            let end = start + 1 + width;
+            edi_assert!(
+                EdiErrorKind::MalformedHeader,
+                raw.as_bytes().get(start).copied() == element_delimiter,
+                format!(
+                    "malformed ISA header: the element delimiter must appear at byte {} of the fixed-width ISA segment",
+                    start
+                )
+                .as_str()
+            );
+            match raw.get(start + 1..end) {
+                Some(element) => tokens.push(element),
+                None => {
+                    return Err(EdiParseError::new(
+                        EdiErrorKind::MalformedHeader,
+                        "malformed ISA header: segment is shorter than the fixed-width ISA layout",
+                        None,
+                    ))
+                }
+            }
+            start = end;
+        }
+        InterchangeControl::parse_from_tokens(tokens)
+    }
+
     /// Given [SegmentTokens](struct.SegmentTokens.html) (where the first token is "ISA"), construct an [InterchangeControl].
     pub(crate) fn parse_from_tokens(
         input: SegmentTokens<'a>,
@@ -94,11 +198,13 @@ impl<'a, 'b> InterchangeControl<'a, 'b> {
         // I always inject invariants wherever I can to ensure debugging is quick and painless,
         // and to check my assumptions.
         edi_assert!(
+            EdiErrorKind::InvalidArgument,
             elements[0] == "ISA",
             "attempted to parse ISA from non-ISA segment",
             input
         );
         edi_assert!(
+            EdiErrorKind::MissingElements,
             elements.len() >= 16,
             "ISA segment does not contain enough elements. At least 16 required",
             input
@@ -152,10 +258,80 @@ impl<'a, 'b> InterchangeControl<'a, 'b> {
             interchange_control_number,
             acknowledgement_requested,
             test_indicator,
+            interchange_segments: VecDeque::new(),
             functional_groups: VecDeque::new(),
+            original_trailer: None,
         })
     }
 
+    /// Copy every element into an owned string, so that the interchange no longer borrows from the input.
+    pub fn into_owned(self) -> InterchangeControl<'static, 'b> {
+        InterchangeControl {
+            authorization_qualifier: Cow::Owned(self.authorization_qualifier.into_owned()),
+            authorization_information: Cow::Owned(self.authorization_information.into_owned()),
+            security_qualifier: Cow::Owned(self.security_qualifier.into_owned()),
+            security_information: Cow::Owned(self.security_information.into_owned()),
+            sender_qualifier: Cow::Owned(self.sender_qualifier.into_owned()),
+            sender_id: Cow::Owned(self.sender_id.into_owned()),
+            receiver_qualifier: Cow::Owned(self.receiver_qualifier.into_owned()),
+            receiver_id: Cow::Owned(self.receiver_id.into_owned()),
+            date: Cow::Owned(self.date.into_owned()),
+            time: Cow::Owned(self.time.into_owned()),
+            standards_id: Cow::Owned(self.standards_id.into_owned()),
+            version: Cow::Owned(self.version.into_owned()),
+            interchange_control_number: Cow::Owned(self.interchange_control_number.into_owned()),
+            acknowledgement_requested: Cow::Owned(self.acknowledgement_requested.into_owned()),
+            test_indicator: Cow::Owned(self.test_indicator.into_owned()),
+            interchange_segments: self
+                .interchange_segments
+                .into_iter()
+                .map(GenericSegment::into_owned)
+                .collect(),
+            functional_groups: self
+                .functional_groups
+                .into_iter()
+                .map(FunctionalGroup::into_owned)
+                .collect(),
+            original_trailer: self.original_trailer.map(owned_trailer),
+        }
+    }
+
+    /// Clone the ISA header and any segments before the first functional group, but none of the functional groups.
+    pub(crate) fn clone_envelope(&self) -> InterchangeControl<'a, 'b> {
+        InterchangeControl {
+            authorization_qualifier: self.authorization_qualifier.clone(),
+            authorization_information: self.authorization_information.clone(),
+            security_qualifier: self.security_qualifier.clone(),
+            security_information: self.security_information.clone(),
+            sender_qualifier: self.sender_qualifier.clone(),
+            sender_id: self.sender_id.clone(),
+            receiver_qualifier: self.receiver_qualifier.clone(),
+            receiver_id: self.receiver_id.clone(),
+            date: self.date.clone(),
+            time: self.time.clone(),
+            standards_id: self.standards_id.clone(),
+            version: self.version.clone(),
+            interchange_control_number: self.interchange_control_number.clone(),
+            acknowledgement_requested: self.acknowledgement_requested.clone(),
+            test_indicator: self.test_indicator.clone(),
+            interchange_segments: self.interchange_segments.clone(),
+            functional_groups: VecDeque::new(),
+            original_trailer: None,
+        }
+    }
+
+    /// Enqueue a [GenericSegment] directly into the interchange, outside of any [FunctionalGroup].
+    pub(crate) fn add_interchange_segment(
+        &mut self,
+        tokens: SegmentTokens<'a>,
+        raw: Option<&'a str>,
+    ) -> Result<(), EdiParseError> {
+        let mut segment = GenericSegment::parse_from_tokens(tokens)?;
+        segment.raw = raw.map(Cow::from);
+        self.interchange_segments.push_back(segment);
+        Ok(())
+    }
+
     /// Enqueue a [FunctionalGroup] into the interchange. Subsequent [Transaction]s will be inserted into this functional group,
     /// until a new one is enqueued.
     pub(crate) fn add_functional_group(
@@ -167,6 +343,12 @@ impl<'a, 'b> InterchangeControl<'a, 'b> {
         Ok(())
     }
 
+    /// Append a [FunctionalGroup] to the end of this interchange. [to_x12_string](#method.to_x12_string)
+    /// recomputes the IEA group count, so it does not need to be updated.
+    pub fn push_functional_group(&mut self, functional_group: FunctionalGroup<'a, 'b>) {
+        self.functional_groups.push_back(functional_group);
+    }
+
     /// Enqueue a [Transaction] into the most recently enqueued [FunctionalGroup] in this interchange.
     pub(crate) fn add_transaction(
         &mut self,
@@ -176,7 +358,8 @@ impl<'a, 'b> InterchangeControl<'a, 'b> {
             functional_group.add_transaction(tokens)
         } else {
             Err(EdiParseError::new(
-                "unable to enqueue transaction when no functional groups have been added",
+                EdiErrorKind::OutOfOrder,
+                "EDI file out of order: received ST but no functional group (GS) is open",
                 Some(tokens),
             ))
         }
@@ -186,37 +369,179 @@ impl<'a, 'b> InterchangeControl<'a, 'b> {
     pub(crate) fn add_generic_segment(
         &mut self,
         tokens: SegmentTokens<'a>,
+        raw: Option<&'a str>,
     ) -> Result<(), EdiParseError> {
         if let Some(functional_group) = self.functional_groups.back_mut() {
-            functional_group.add_generic_segment(tokens)
+            functional_group.add_generic_segment(tokens, raw)
         } else {
             Err(EdiParseError::new(
-                "unable to enqueue generic segment when no functional groups have been added",
+                EdiErrorKind::OutOfOrder,
+                &format!(
+                    "EDI file out of order: received {} but no functional group (GS) is open",
+                    tokens[0]
+                ),
                 Some(tokens),
             ))
         }
     }
 
+    /// The number of functional groups in this interchange, as counted by the IEA segment (IEA01).
+    /// Segments kept outside of a functional group are not counted.
+    pub fn functional_group_count(&self) -> usize {
+        self.functional_groups.len()
+    }
+
+    /// The functional group at position `index` (starting from zero) within this interchange, or `None` if the
+    /// interchange contains fewer functional groups.
+    pub fn functional_group(&self, index: usize) -> Option<&FunctionalGroup<'a, 'b>> {
+        self.functional_groups.get(index)
+    }
+
+    /// The [version](#structfield.version) (ISA12) as a comparable [StandardsVersion], e.g. 5010 for "00501", so
+    /// that version-dependent rules can be chosen with e.g. `>= StandardsVersion::V5010`. `None` if it is not a
+    /// five digit version and release number.
+    pub fn standards_version(&self) -> Option<StandardsVersion> {
+        StandardsVersion::parse(&self.version).filter(|_| self.version.len() == 5)
+    }
+
+    /// The interchange date (ISA09, `YYMMDD`) and time (ISA10, `HHMM`) combined into one timestamp. Two-digit
+    /// years from 69 on are taken to be in the 1900s and earlier ones in the 2000s. Requires the `chrono` feature.
+    #[cfg(feature = "chrono")]
+    pub fn datetime(&self) -> Result<NaiveDateTime, EdiParseError> {
+        edi_assert!(
+            EdiErrorKind::InvalidElement,
+            self.date.len() == 6 && self.time.len() == 4,
+            "interchange date and time are not the expected length",
+            "YYMMDD HHMM",
+            format!("{} {}", self.date, self.time)
+        );
+        let mut timestamp = String::from(self.date.as_ref());
+        timestamp.push_str(&self.time);
+        NaiveDateTime::parse_from_str(&timestamp, "%y%m%d%H%M").map_err(|_| {
+            EdiParseError::new(
+                EdiErrorKind::InvalidElement,
+                &format!(
+                    "interchange date and time are not a valid timestamp  --  received: {} {}",
+                    self.date, self.time
+                ),
+                None,
+            )
+        })
+    }
+
+    /// Whether the [test_indicator](#structfield.test_indicator) marks this interchange as test data ("T").
+    /// `None` if the indicator is not one of "T", "P", or "I".
+    pub fn is_test(&self) -> Option<bool> {
+        match self.test_indicator.as_ref() {
+            "T" => Some(true),
+            "P" | "I" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Whether the [test_indicator](#structfield.test_indicator) marks this interchange as production data ("P").
+    /// `None` if the indicator is not one of "T", "P", or "I".
+    pub fn is_production(&self) -> Option<bool> {
+        match self.test_indicator.as_ref() {
+            "P" => Some(true),
+            "T" | "I" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Whether the sender requested an interchange acknowledgment (TA1), as given by the
+    /// [acknowledgement_requested](#structfield.acknowledgement_requested) field. `None` if it is not "0" or "1".
+    pub fn acknowledgement_requested(&self) -> Option<bool> {
+        match self.acknowledgement_requested.as_ref() {
+            "1" => Some(true),
+            "0" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Whether the sender requested an interchange acknowledgment, so that one should be sent back. Only "1" counts
+    /// as a request; anything else, including an unknown value, does not. The field is parsed trimmed, but may hold
+    /// padding if it was set by hand, e.g. "1 "; `trim_whitespace` decides whether such a value counts.
+    pub fn requests_acknowledgement(&self, trim_whitespace: bool) -> bool {
+        let flag = if trim_whitespace {
+            self.acknowledgement_requested.trim()
+        } else {
+            self.acknowledgement_requested.as_ref()
+        };
+        flag == "1"
+    }
+
+    /// Check that the acknowledgment requested flag (ISA14) is "0" or "1" and that the test indicator (ISA15)
+    /// is "T", "P", or "I". This is opt-in; parsing never calls it.
+    pub fn validate_indicators(&self) -> Result<(), EdiParseError> {
+        edi_assert!(
+            EdiErrorKind::InvalidElement,
+            self.acknowledgement_requested().is_some(),
+            "interchange validation failed: unknown acknowledgment requested flag",
+            "0 or 1",
+            self.acknowledgement_requested
+        );
+        edi_assert!(
+            EdiErrorKind::InvalidElement,
+            self.is_test().is_some(),
+            "interchange validation failed: unknown test indicator",
+            "T, P, or I",
+            self.test_indicator
+        );
+        Ok(())
+    }
+
+    /// Check that the sender and receiver qualifiers (ISA05 and ISA07) are interchange ID qualifiers
+    /// defined by X12. This catches transposed or made-up qualifiers before a document is sent.
+    /// This is opt-in; parsing never calls it.
+    pub fn validate_qualifiers(&self) -> Result<(), EdiParseError> {
+        edi_assert!(
+            EdiErrorKind::InvalidElement,
+            INTERCHANGE_ID_QUALIFIERS.contains(&self.sender_qualifier.as_ref()),
+            "interchange validation failed: unknown sender qualifier",
+            "an X12 interchange ID qualifier",
+            self.sender_qualifier
+        );
+        edi_assert!(
+            EdiErrorKind::InvalidElement,
+            INTERCHANGE_ID_QUALIFIERS.contains(&self.receiver_qualifier.as_ref()),
+            "interchange validation failed: unknown receiver qualifier",
+            "an X12 interchange ID qualifier",
+            self.receiver_qualifier
+        );
+        Ok(())
+    }
+
     /// Given the tokens of an IEA segment, or Interchange Control closer, verify that the correct
     /// number of control groups have been given.
     pub(crate) fn validate_interchange_control(
         &self,
         tokens: SegmentTokens<'a>,
+        options: &ParseOptions,
     ) -> Result<(), EdiParseError> {
         edi_assert!(
+            EdiErrorKind::InvalidArgument,
             tokens[0] == "IEA",
             "attempted to verify IEA on non-IEA segment",
             tokens
         );
         edi_assert!(
-            str::parse::<usize>(&tokens[1].to_string()).unwrap() == self.functional_groups.len(),
+            EdiErrorKind::MissingElements,
+            tokens.len() >= 3,
+            "IEA segment does not contain enough elements. At least 3 required",
+            tokens
+        );
+        edi_assert!(
+            EdiErrorKind::CountMismatch,
+            parse_count(tokens[1], &tokens)? == self.functional_group_count(),
             "interchange validation failed: incorrect number of functional groups",
             tokens[1].to_string(),
-            self.functional_groups.len(),
+            self.functional_group_count(),
             tokens
         );
         edi_assert!(
-            tokens[2] == self.interchange_control_number,
+            EdiErrorKind::ControlNumberMismatch,
+            options.control_numbers_match(&self.interchange_control_number, tokens[2]),
             "interchange validation failed: mismatched ID",
             tokens[2],
             self.interchange_control_number.clone(),
@@ -230,14 +555,16 @@ impl<'a, 'b> InterchangeControl<'a, 'b> {
     pub(crate) fn validate_functional_group(
         &self,
         tokens: SegmentTokens<'a>,
+        options: &ParseOptions,
     ) -> Result<(), EdiParseError> {
         if let Some(functional_group) = self.functional_groups.back() {
-            functional_group.validate_functional_group(tokens)
+            functional_group.validate_functional_group(tokens, options)
         } else {
-            return Err(EdiParseError::new(
-                "unable to verify nonexistent functional group",
+            Err(EdiParseError::new(
+                EdiErrorKind::OutOfOrder,
+                "EDI file out of order: received GE but no functional group (GS) is open",
                 Some(tokens),
-            ));
+            ))
         }
     }
 
@@ -245,74 +572,186 @@ impl<'a, 'b> InterchangeControl<'a, 'b> {
     pub(crate) fn validate_transaction(
         &self,
         tokens: SegmentTokens<'a>,
+        options: &ParseOptions,
     ) -> Result<(), EdiParseError> {
         if let Some(functional_group) = self.functional_groups.back() {
-            functional_group.validate_transaction(tokens)
+            functional_group.validate_transaction(tokens, options)
         } else {
-            return Err(EdiParseError::new(
-                "unable to verify transaction within nonexistent functional group",
+            Err(EdiParseError::new(
+                EdiErrorKind::OutOfOrder,
+                "EDI file out of order: received SE but no functional group (GS) is open",
                 Some(tokens),
-            ));
+            ))
         }
     }
+    /// ISA01 through ISA15, in order.
+    fn isa_elements(&self) -> [&str; 15] {
+        [
+            &self.authorization_qualifier,
+            &self.authorization_information,
+            &self.security_qualifier,
+            &self.security_information,
+            &self.sender_qualifier,
+            &self.sender_id,
+            &self.receiver_qualifier,
+            &self.receiver_id,
+            &self.date,
+            &self.time,
+            &self.standards_id,
+            &self.version,
+            &self.interchange_control_number,
+            &self.acknowledgement_requested,
+            &self.test_indicator,
+        ]
+    }
+
     /// Converts this [InterchangeControl] into an ANSI x12 string for use in an EDI document.
+    /// The ISA elements are padded with spaces to their fixed widths, and elements which are too long are
+    /// truncated so that the delimiters stay where a receiver expects them. Use
+    /// [try_to_x12_string](#method.try_to_x12_string) to reject over-length elements instead.
     pub fn to_x12_string(
         &self,
         segment_delimiter: char,
         element_delimiter: char,
         sub_element_separator: char,
     ) -> String {
-        let mut buffer = String::from("ISA");
-        let isa_01 = pad_right(&self.authorization_qualifier, 2);
-        let isa_02 = pad_right(&self.authorization_information, 10);
-        let isa_03 = pad_right(&self.security_qualifier, 2);
-        let isa_04 = pad_right(&self.security_information, 10);
-        let isa_05 = pad_right(&self.sender_qualifier, 2);
-        let isa_06 = pad_right(&self.sender_id, 15);
-        let isa_07 = pad_right(&self.receiver_qualifier, 2);
-        let isa_08 = pad_right(&self.receiver_id, 15);
-        let isa_09 = pad_right(&self.date, 6);
-        let isa_10 = pad_right(&self.time, 4);
-        let isa_11 = pad_right(&self.standards_id, 1);
-        let isa_12 = pad_right(&self.version, 5);
-        let isa_13 = pad_right(&self.interchange_control_number, 9);
-        let isa_14 = pad_right(&self.acknowledgement_requested, 1);
-        let isa_15 = pad_right(&self.test_indicator, 1);
-        let isa_16 = sub_element_separator.to_string();
-
-        [
-            isa_01, isa_02, isa_03, isa_04, isa_05, isa_06, isa_07, isa_08, isa_09, isa_10, isa_11,
-            isa_12, isa_13, isa_14, isa_15, isa_16,
-        ]
-        .iter()
-        .for_each(|part| {
-            buffer.push(element_delimiter);
-            buffer.push_str(part);
-        });
-
-        let functional_groups =
-            self.functional_groups
-                .iter()
-                .fold(String::new(), |mut acc, group| {
-                    acc.push(segment_delimiter);
-                    acc.push_str(&group.to_x12_string(segment_delimiter, element_delimiter));
-                    acc
-                });
-
-        buffer.push_str(&functional_groups);
-        buffer.push(segment_delimiter);
-        buffer.push_str("IEA");
-        buffer.push(element_delimiter);
-        buffer.push_str(&self.functional_groups.len().to_string());
-        buffer.push(element_delimiter);
-        buffer.push_str(&self.interchange_control_number);
+        let mut buffer = String::new();
+        self.write_x12(
+            &mut buffer,
+            segment_delimiter.encode_utf8(&mut [0; 4]),
+            element_delimiter,
+            sub_element_separator,
+            None,
+        )
+        .expect("writing to a String cannot fail");
         buffer
     }
+
+    /// Like [to_x12_string](#method.to_x12_string), but returns an error instead of truncating an ISA element
+    /// which is longer than its fixed width or writing one which is required but empty. The functional groups
+    /// and transactions are checked for empty required elements as well.
+    pub fn try_to_x12_string(
+        &self,
+        segment_delimiter: char,
+        element_delimiter: char,
+        sub_element_separator: char,
+    ) -> Result<String, EdiParseError> {
+        self.check_required_elements()?;
+        Ok(self.to_x12_string(segment_delimiter, element_delimiter, sub_element_separator))
+    }
+
+    /// Check that none of ISA01 through ISA15 is longer than its fixed width (see [ISA_ELEMENT_WIDTHS]), which
+    /// [to_x12_string](#method.to_x12_string) would otherwise truncate. Every over-long element is reported.
+    /// Shorter elements are fine, since they are padded with spaces.
+    pub fn validate_field_widths(&self) -> Result<(), Vec<EdiParseError>> {
+        let errors: Vec<EdiParseError> = self
+            .isa_elements()
+            .iter()
+            .zip(ISA_ELEMENT_WIDTHS.iter())
+            .enumerate()
+            .filter(|(_, (element, width))| element.chars().count() > **width)
+            .map(|(idx, (element, width))| {
+                EdiParseError::new(
+                    EdiErrorKind::InvalidElement,
+                    &format!(
+                        "ISA{:02} is longer than its fixed width  --  expected: at most {} characters  received: {}",
+                        idx + 1,
+                        width,
+                        element
+                    ),
+                    None,
+                )
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Check that every ISA element fits its fixed width and that the required ones are not empty. Only the
+    /// authorization and security information (ISA02 and ISA04) may be blank. The functional groups are
+    /// checked too.
+    pub(crate) fn check_required_elements(&self) -> Result<(), EdiParseError> {
+        if let Err(mut errors) = self.validate_field_widths() {
+            return Err(errors.remove(0));
+        }
+        for (idx, element) in self.isa_elements().iter().enumerate() {
+            edi_assert!(
+                EdiErrorKind::MissingElements,
+                idx == 1 || idx == 3 || !element.trim().is_empty(),
+                format!("ISA{:02} is required but empty", idx + 1).as_str()
+            );
+        }
+        for functional_group in self.functional_groups.iter() {
+            functional_group.check_required_elements()?;
+        }
+        Ok(())
+    }
+
+    /// Writes this interchange to `writer` segment by segment, formatted like
+    /// [to_x12_string](#method.to_x12_string). Segments are separated by `segment_separator`, which is the segment
+    /// delimiter, optionally followed by a line break.
+    pub(crate) fn write_x12<W: fmt::Write>(
+        &self,
+        writer: &mut W,
+        segment_separator: &str,
+        element_delimiter: char,
+        sub_element_separator: char,
+        escape: Option<&Delimiters>,
+    ) -> fmt::Result {
+        writer.write_str("ISA")?;
+        for (element, width) in self.isa_elements().iter().zip(ISA_ELEMENT_WIDTHS.iter()) {
+            writer.write_char(element_delimiter)?;
+            writer.write_str(&pad_right(element, *width))?;
+        }
+        writer.write_char(element_delimiter)?;
+        writer.write_char(sub_element_separator)?;
+
+        for segment in self.interchange_segments.iter() {
+            writer.write_str(segment_separator)?;
+            segment.write_x12(writer, element_delimiter, escape)?;
+        }
+
+        for group in self.functional_groups.iter() {
+            writer.write_str(segment_separator)?;
+            group.write_x12(writer, segment_separator, element_delimiter, escape)?;
+        }
+
+        writer.write_str(segment_separator)?;
+        write_trailer(
+            writer,
+            "IEA",
+            element_delimiter,
+            self.original_trailer.as_deref(),
+            self.functional_group_count(),
+            &self.interchange_control_number,
+        )
+    }
 }
 
-fn pad_right(input: &str, desired_length: u8) -> String {
-    let mut buffer = input.to_string();
-    for _ in buffer.len() as u8..desired_length {
+impl fmt::Display for InterchangeControl<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = self.functional_groups.len();
+        write!(
+            f,
+            "Interchange #{} from {}:{} to {}:{} with {} functional group{}",
+            self.interchange_control_number,
+            self.sender_qualifier,
+            self.sender_id,
+            self.receiver_qualifier,
+            self.receiver_id,
+            count,
+            if count == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// Pad `input` with spaces, or truncate it, so that it is exactly `desired_length` characters long.
+fn pad_right(input: &str, desired_length: usize) -> String {
+    let mut buffer: String = input.chars().take(desired_length).collect();
+    for _ in buffer.chars().count()..desired_length {
         buffer.push(' ');
     }
     buffer
@@ -322,31 +761,31 @@ fn pad_right(input: &str, desired_length: u8) -> String {
 fn test_isa_to_string() {
     use crate::{GenericSegment, Transaction};
     use std::iter::FromIterator;
-    let segments = VecDeque::from_iter(
-        vec![
-            GenericSegment {
-                segment_abbreviation: Cow::from("BGN"),
-                elements: vec!["20", "TEST_ID", "200615", "0000"]
-                    .iter()
-                    .map(|x| Cow::from(*x))
-                    .collect::<VecDeque<Cow<str>>>(),
-            },
-            GenericSegment {
-                segment_abbreviation: Cow::from("BGN"),
-                elements: vec!["15", "OTHER_TEST_ID", "", "", "END"]
-                    .iter()
-                    .map(|x| Cow::from(*x))
-                    .collect::<VecDeque<Cow<str>>>(),
-            },
-        ]
-        .into_iter(),
-    );
+    let segments = VecDeque::from_iter(vec![
+        GenericSegment {
+            segment_abbreviation: Cow::from("BGN"),
+            elements: ["20", "TEST_ID", "200615", "0000"]
+                .iter()
+                .map(|x| Cow::from(*x))
+                .collect::<VecDeque<Cow<str>>>(),
+            raw: None,
+        },
+        GenericSegment {
+            segment_abbreviation: Cow::from("BGN"),
+            elements: ["15", "OTHER_TEST_ID", "", "", "END"]
+                .iter()
+                .map(|x| Cow::from(*x))
+                .collect::<VecDeque<Cow<str>>>(),
+            raw: None,
+        },
+    ]);
     let transaction = Transaction {
         transaction_code: Cow::from("140"),
         transaction_name: "",
         transaction_set_control_number: Cow::from("100000001"),
         implementation_convention_reference: None,
-        segments: segments,
+        segments,
+        original_trailer: None,
     };
 
     let functional_group = FunctionalGroup {
@@ -358,7 +797,8 @@ fn test_isa_to_string() {
         group_control_number: Cow::from("1"),
         responsible_agency_code: Cow::from("X"),
         version: Cow::from("004010"),
-        transactions: VecDeque::from_iter(vec![transaction].into_iter()),
+        transactions: VecDeque::from_iter(vec![transaction]),
+        original_trailer: None,
     };
 
     let interchange = InterchangeControl {
@@ -377,10 +817,12 @@ fn test_isa_to_string() {
         interchange_control_number: Cow::from("000000001"),
         acknowledgement_requested: Cow::from("0"),
         test_indicator: Cow::from("T"),
-        functional_groups: VecDeque::from_iter(vec![functional_group].into_iter()),
+        interchange_segments: VecDeque::new(),
+        functional_groups: VecDeque::from_iter(vec![functional_group]),
+        original_trailer: None,
     };
 
-    assert_eq!(interchange.to_x12_string('~', '*', '>'), "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*140*100000001*~BGN*20*TEST_ID*200615*0000~BGN*15*OTHER_TEST_ID***END~SE*4*100000001~GE*1*1~IEA*1*000000001");
+    assert_eq!(interchange.to_x12_string('~', '*', '>'), "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*140*100000001~BGN*20*TEST_ID*200615*0000~BGN*15*OTHER_TEST_ID***END~SE*4*100000001~GE*1*1~IEA*1*000000001");
 }
 
 #[test]
@@ -397,6 +839,33 @@ fn test_pad_right_2() {
     assert_eq!(output.len(), 10);
 }
 
+#[test]
+fn test_pad_right_truncates() {
+    assert_eq!(pad_right("SENDERISA-TOO-LONG", 15), "SENDERISA-TOO-L");
+}
+
+#[cfg(test)]
+fn sample_isa_tokens() -> Vec<&'static str> {
+    vec![
+        "ISA",
+        "00",
+        "",
+        "00",
+        "",
+        "ZZ",
+        "SENDERISA",
+        "14",
+        "0073268795005",
+        "020226",
+        "1534",
+        "U",
+        "00401",
+        "000000001",
+        "0",
+        "T",
+    ]
+}
+
 #[test]
 fn construct_interchange_control() {
     let expected_result = InterchangeControl {
@@ -415,29 +884,198 @@ fn construct_interchange_control() {
         interchange_control_number: Cow::from("000000001"),
         acknowledgement_requested: Cow::from("0"),
         test_indicator: Cow::from("T"),
+        interchange_segments: VecDeque::new(),
         functional_groups: VecDeque::new(),
+        original_trailer: None,
     };
 
-    let test_input = vec![
-        "ISA",
-        "00",
-        "",
-        "00",
-        "",
-        "ZZ",
-        "SENDERISA",
-        "14",
-        "0073268795005",
-        "020226",
-        "1534",
-        "U",
-        "00401",
-        "000000001",
-        "0",
-        "T",
-    ];
     assert_eq!(
-        InterchangeControl::parse_from_tokens(test_input,).unwrap(),
+        InterchangeControl::parse_from_tokens(sample_isa_tokens()).unwrap(),
         expected_result
     );
 }
+
+#[test]
+fn validate_interchange_qualifiers() {
+    let mut interchange = InterchangeControl::parse_from_tokens(sample_isa_tokens()).unwrap();
+    assert!(interchange.validate_qualifiers().is_ok());
+
+    interchange.receiver_qualifier = Cow::from("Z1");
+    let error = interchange.validate_qualifiers().err().unwrap();
+    assert!(error.reason().contains("receiver qualifier"));
+}
+
+#[test]
+fn over_length_isa_elements() {
+    let mut interchange = InterchangeControl::parse_from_tokens(sample_isa_tokens()).unwrap();
+    assert!(interchange.try_to_x12_string('~', '*', '>').is_ok());
+
+    interchange.sender_id = Cow::from("A-SENDER-ID-THAT-IS-TOO-LONG");
+    let error = interchange.try_to_x12_string('~', '*', '>').err().unwrap();
+    assert!(error.reason().contains("ISA06"));
+
+    // the truncated output still has the delimiters at their fixed offsets
+    let output = interchange.to_x12_string('~', '*', '>');
+    assert_eq!(&output[50..51], "*");
+    assert_eq!(&output[103..106], "*>~");
+    assert!(output.contains("*A-SENDER-ID-THA*"));
+
+    interchange.date = Cow::from("20020226");
+    let errors = interchange.validate_field_widths().err().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(
+        errors[1].reason(),
+        "ISA09 is longer than its fixed width  --  expected: at most 6 characters  received: 20020226"
+    );
+    interchange.sender_id = Cow::from("SENDERISA");
+    interchange.date = Cow::from("020226");
+    assert!(interchange.validate_field_widths().is_ok());
+}
+
+#[test]
+fn empty_required_envelope_elements() {
+    let mut interchange = InterchangeControl::parse_from_tokens(sample_isa_tokens()).unwrap();
+    interchange
+        .add_functional_group(vec![
+            "GS",
+            "PO",
+            "SENDERGS",
+            "007326879",
+            "20020226",
+            "1534",
+            "1",
+            "X",
+            "004010",
+        ])
+        .unwrap();
+    interchange
+        .add_transaction(vec!["ST", "850", "000000001"])
+        .unwrap();
+    assert!(interchange.try_to_x12_string('~', '*', '>').is_ok());
+
+    interchange.receiver_id = Cow::from("");
+    let error = interchange.try_to_x12_string('~', '*', '>').err().unwrap();
+    assert_eq!(error.reason(), "ISA08 is required but empty");
+    interchange.receiver_id = Cow::from("0073268795005");
+
+    interchange.functional_groups[0].group_control_number = Cow::from("");
+    let error = interchange.try_to_x12_string('~', '*', '>').err().unwrap();
+    assert_eq!(error.reason(), "GS06 is required but empty");
+    interchange.functional_groups[0].group_control_number = Cow::from("1");
+
+    interchange.functional_groups[0].transactions[0].transaction_set_control_number = Cow::from("");
+    let error = interchange.try_to_x12_string('~', '*', '>').err().unwrap();
+    assert_eq!(
+        error.reason(),
+        "ST02 (transaction set control number) is required but empty"
+    );
+    assert!(interchange.functional_groups[0]
+        .try_to_x12_string('~', '*')
+        .is_err());
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn interchange_datetime() {
+    use chrono::NaiveDate;
+    let mut interchange = InterchangeControl::parse_from_tokens(sample_isa_tokens()).unwrap();
+    assert_eq!(
+        interchange.datetime().unwrap(),
+        NaiveDate::from_ymd_opt(2002, 2, 26)
+            .unwrap()
+            .and_hms_opt(15, 34, 0)
+            .unwrap()
+    );
+
+    interchange.date = Cow::from("991231");
+    assert_eq!(
+        interchange.datetime().unwrap().date(),
+        NaiveDate::from_ymd_opt(1999, 12, 31).unwrap()
+    );
+
+    interchange.date = Cow::from("990231");
+    assert!(interchange.datetime().is_err());
+    interchange.date = Cow::from("9912310");
+    assert!(interchange.datetime().is_err());
+}
+
+#[test]
+fn interpret_interchange_indicators() {
+    let mut interchange = InterchangeControl::parse_from_tokens(sample_isa_tokens()).unwrap();
+    assert_eq!(interchange.is_test(), Some(true));
+    assert_eq!(interchange.is_production(), Some(false));
+    assert_eq!(interchange.acknowledgement_requested(), Some(false));
+    assert!(interchange.validate_indicators().is_ok());
+
+    interchange.test_indicator = Cow::from("I");
+    interchange.acknowledgement_requested = Cow::from("1");
+    assert_eq!(interchange.is_test(), Some(false));
+    assert_eq!(interchange.is_production(), Some(false));
+    assert_eq!(interchange.acknowledgement_requested(), Some(true));
+
+    interchange.test_indicator = Cow::from("X");
+    assert_eq!(interchange.is_test(), None);
+    assert_eq!(interchange.is_production(), None);
+    assert!(interchange
+        .validate_indicators()
+        .err()
+        .unwrap()
+        .reason()
+        .contains("test indicator"));
+
+    assert!(interchange.requests_acknowledgement(false));
+    interchange.acknowledgement_requested = Cow::from("1 ");
+    assert!(!interchange.requests_acknowledgement(false));
+    assert!(interchange.requests_acknowledgement(true));
+
+    interchange.acknowledgement_requested = Cow::from("Y");
+    assert_eq!(interchange.acknowledgement_requested(), None);
+    assert!(!interchange.requests_acknowledgement(true));
+    assert!(interchange
+        .validate_indicators()
+        .err()
+        .unwrap()
+        .reason()
+        .contains("acknowledgment requested"));
+}
+
+#[test]
+fn parse_fixed_width_isa() {
+    let raw = "ISA*00*          *00*          *ZZ*SENDER*ISA     *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~";
+    let interchange = InterchangeControl::parse_isa_fixed_width(raw).unwrap();
+    assert_eq!(interchange.sender_id, "SENDER*ISA");
+    assert_eq!(interchange.receiver_id, "0073268795005");
+    assert_eq!(interchange.test_indicator, "T");
+
+    let tokens = raw.trim_end_matches('~').split('*').collect::<Vec<&str>>();
+    assert_eq!(tokens.len(), 18);
+    let split = InterchangeControl::parse_from_tokens(tokens).unwrap();
+    assert_eq!(split.sender_id, "SENDER");
+    assert_eq!(split.receiver_qualifier, "ISA");
+
+    assert!(InterchangeControl::parse_isa_fixed_width(&raw[..100]).is_err());
+    assert!(InterchangeControl::parse_isa_fixed_width(&raw.replacen("00*", "0*", 1)).is_err());
+    assert!(InterchangeControl::parse_isa_fixed_width(&raw.replace("ISA*", "GS*")).is_err());
+}
+
+#[test]
+fn interchange_standards_version() {
+    let raw = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*^*00501*000000001*0*T*>~";
+    let mut interchange = InterchangeControl::parse_isa_fixed_width(raw).unwrap();
+    assert_eq!(
+        interchange.standards_version(),
+        Some(StandardsVersion::V5010)
+    );
+    assert!(interchange.standards_version().unwrap() > StandardsVersion::V4010);
+
+    interchange.version = Cow::from("00401");
+    assert_eq!(
+        interchange.standards_version(),
+        Some(StandardsVersion::V4010)
+    );
+    // ISA12 has no subrelease or industry identifier
+    interchange.version = Cow::from("004010");
+    assert_eq!(interchange.standards_version(), None);
+    interchange.version = Cow::from("");
+    assert_eq!(interchange.standards_version(), None);
+}
@@ -3,7 +3,7 @@
 //! `loose_parse()` and `parse()`. The strictest parsing mode is `parse()`, which parses a string and constructs
 //! an [EdiDocument] in a zero-copy (almost -- more on that later) way. `loose_parse()` does the same thing, but
 //! does not check and validate that segment closers (IEA, GE, SE) match their openers' ID or have the correct amount
-//! of records.
+//! of records. For finer control over parsing, build a [ParseOptions] and pass it to `parse_with_options()`.
 //!
 //! ## Zero Copy
 //! Under the hood, this crate uses `std::borrow::Cow<&str>` for processing EDI documents. This means that if you are
@@ -20,23 +20,69 @@
 //! * TOML
 //! * [and more...](https://serde.rs/#data-formats)
 //!
+//! Deserialized documents borrow their strings from the serialized input where the format allows it, and copy them
+//! otherwise. Because of the borrowing, an [EdiDocument] can't be deserialized from a reader; read the serialized
+//! document into a str or byte slice first. Deserializers which never lend strings, such as an owned
+//! `serde_json::Value` or the deserializers of language bindings, produce an `EdiDocument<'static, 'static>`. A
+//! transaction name such a deserializer hands over which is not one of the bundled names, e.g. a custom
+//! `unidentified_transaction_name`, is replaced by the bundled name of the transaction's code.
+//!
+//! ## `no_std`
+//! The `std` feature is enabled by default. Without it, the crate only depends on `core` and `alloc`, so
+//! tokenizing and parsing documents is available in constrained environments. Looking up transaction set names
+//! requires the bundled schema file, so it is only available with `std`; without it, every transaction is
+//! "unidentified".
+//!
 //! # Getting Started
 //! There are examples in the [examples directory](https://github.com/sezna/edi/tree/master/examples).
 
 #![deny(missing_docs)]
-pub use edi_document::EdiDocument;
-pub use edi_document::{loose_parse, parse};
-pub use edi_parse_error::EdiParseError;
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+extern crate alloc;
+
+pub use acknowledgement::AckError;
+pub use code_list::{
+    CodeList, ENTITY_IDENTIFIER_CODES, FUNCTIONAL_IDENTIFIER_CODES,
+    REFERENCE_IDENTIFICATION_QUALIFIERS,
+};
+pub use delimiters::Delimiters;
+#[cfg(feature = "encoding")]
+pub use edi_document::decode_bytes;
+pub use edi_document::{
+    is_interchange_complete, loose_parse, loose_parse_with_report, parse, parse_bytes,
+    parse_collecting, parse_from_tokens, parse_with_options, parse_with_progress, parse_with_stats,
+    parse_with_validation, peek_transaction_codes,
+};
+pub use edi_document::{EdiDocument, EDI_SCHEMA_VERSION};
+pub use edi_parse_error::{EdiErrorKind, EdiParseError};
+pub use element_spec::{ElementSpec, ElementType};
 pub use functional_group::FunctionalGroup;
+#[cfg(feature = "std")]
+pub use generic_segment::segment_name;
 pub use generic_segment::GenericSegment;
-pub use interchange_control::InterchangeControl;
+pub use hl_node::HlNode;
+pub use interchange_control::{InterchangeControl, ISA_ELEMENT_WIDTHS};
+pub use parse_options::ParseOptions;
+pub use parse_stats::ParseStats;
+pub use standards_version::StandardsVersion;
+pub use tokenizer::{DocumentTokens, SegmentTokens};
+#[cfg(feature = "std")]
+pub use transaction::transaction_set_name;
 pub use transaction::Transaction;
 
 #[macro_use]
 mod edi_parse_error;
+mod acknowledgement;
+mod code_list;
+mod delimiters;
 mod edi_document;
+mod element_spec;
 mod functional_group;
 mod generic_segment;
+mod hl_node;
 mod interchange_control;
+mod parse_options;
+mod parse_stats;
+mod standards_version;
 mod tokenizer;
 mod transaction;
@@ -0,0 +1,180 @@
+use crate::transaction::UNIDENTIFIED_TRANSACTION_NAME;
+
+/// Configures how an input string is parsed into an [EdiDocument](struct.EdiDocument.html).
+/// Pass it to [parse_with_options](fn.parse_with_options.html). The [Default] options are the
+/// same as the ones used by [parse](fn.parse.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseOptions {
+    /// Verify that the closing segments (IEA, GE, SE) match their openers' IDs and contain the correct
+    /// number of records. Disabling this is what [loose_parse](fn.loose_parse.html) does.
+    pub validate_control_counts: bool,
+    /// Keep the whitespace surrounding the elements of generic segments instead of trimming it.
+    /// Line breaks between segments are still removed, and the envelope segments (ISA, GS, ST, and
+    /// their closers) are always trimmed.
+    pub preserve_whitespace: bool,
+    /// Accept generic segments which appear inside an interchange before its first functional group.
+    /// These are kept in [InterchangeControl::interchange_segments](struct.InterchangeControl.html#structfield.interchange_segments)
+    /// instead of causing an error.
+    pub allow_segments_before_group: bool,
+    /// Record the byte range each segment occupies in the original input. The ranges are stored in
+    /// [EdiDocument::segment_offsets](struct.EdiDocument.html#structfield.segment_offsets).
+    pub record_segment_offsets: bool,
+    /// Recognize the envelope segments (ISA, GS, ST, SE, GE, IEA) regardless of case and surrounding
+    /// whitespace, so "isa " is treated as an ISA segment. The abbreviations of generic segments are
+    /// stored as they were sent.
+    pub case_insensitive_segment_ids: bool,
+    /// Skip empty segments (two segment delimiters with only whitespace between them) in the middle of the
+    /// document instead of rejecting the document. A trailing segment delimiter and line breaks between
+    /// segments are always accepted. [loose_parse](fn.loose_parse.html) allows empty segments.
+    pub allow_empty_segments: bool,
+    /// The `(segment, element, sub-element)` delimiters to split the input with. When set, the delimiters are
+    /// not read from the fixed offsets of the ISA segment and its layout is not checked, which allows parsing
+    /// documents from partners whose ISA elements are not padded to the standard widths.
+    pub delimiters: Option<(char, char, char)>,
+    /// Accept an ISA segment with 15 elements, i.e. one without the sub-element separator (ISA16). Such a segment
+    /// is recognized by a character other than the element delimiter at byte 103, where the delimiter preceding
+    /// ISA16 would be; that character is taken to be the segment delimiter. The rest of the ISA must still have the
+    /// standard fixed-width layout. The sub-element separator then defaults to ':', or to '>' if ':' is already
+    /// the element or segment delimiter.
+    pub allow_isa_without_sub_element_separator: bool,
+    /// Compare the control numbers of closing segments (IEA02, GE02, SE02) with their openers' numerically rather
+    /// than as strings, so that "1" matches "000000001". Only has an effect when
+    /// [validate_control_counts](#structfield.validate_control_counts) is set.
+    pub numeric_control_numbers: bool,
+    /// Check that the envelope segments (ISA, GS, ST, SE, GE, IEA) have the number of elements the standard
+    /// defines for them. A segment with more or fewer elements usually means that a data element contains an
+    /// unescaped delimiter, which otherwise shifts the elements silently.
+    /// [loose_parse_with_report](fn.loose_parse_with_report.html) reports these anomalies.
+    pub check_envelope_element_counts: bool,
+    /// The [transaction_name](struct.Transaction.html#structfield.transaction_name) given to transactions whose
+    /// code is not one of the bundled transaction set codes. Defaults to "unidentified". The code itself is always
+    /// kept in [transaction_code](struct.Transaction.html#structfield.transaction_code), and
+    /// [Transaction::is_recognized](struct.Transaction.html#method.is_recognized) tells the two cases apart.
+    pub unidentified_transaction_name: &'static str,
+    /// Reject delimiters which are not ASCII punctuation or control characters. Letters, digits, and spaces are
+    /// technically allowed as delimiters, but in practice they mean that the ISA segment is malformed and the
+    /// delimiters were read from the wrong bytes, which otherwise splits the document into nonsense.
+    pub strict_delimiters: bool,
+    /// Accept a document which starts with a functional group (GS) or a transaction (ST) instead of an interchange,
+    /// e.g. a fragment whose ISA was stripped upstream. The fragment is placed in a placeholder
+    /// [InterchangeControl](struct.InterchangeControl.html) whose envelope fields are empty, and a transaction
+    /// without a GS in a placeholder [FunctionalGroup](struct.FunctionalGroup.html). Since there is no ISA header to
+    /// read the delimiters from, they are taken from [delimiters](#structfield.delimiters) if set. Otherwise, the
+    /// element delimiter is the character following the first segment ID, the segment delimiter is the first '~' or
+    /// line break after the elements the first segment requires (eight for GS, two for ST), and the sub-element
+    /// delimiter is ':'.
+    /// [loose_parse](fn.loose_parse.html) allows a missing interchange.
+    pub allow_missing_interchange: bool,
+    /// Keep the text each generic segment was parsed from in
+    /// [GenericSegment::raw](struct.GenericSegment.html#structfield.raw), including any whitespace around its
+    /// elements. The text borrows from the input and excludes the segment delimiter and line breaks between
+    /// segments.
+    pub keep_raw: bool,
+    /// The IDs of free-text segments, such as `&["NTE"]`, whose text may contain the element delimiter. For these
+    /// segments, everything after the first element (the qualifier) is kept as a single element instead of being
+    /// split on the element delimiter, so a note like "NTE*GEN*SIZE 3*4" keeps "SIZE 3*4" intact. Empty by default,
+    /// because a segment with more than one element after its qualifier, like MSG, would be misread.
+    pub free_text_segments: &'static [&'static str],
+    /// Check that the delimiters read from the fixed offsets of the ISA segment actually split it into its 16
+    /// elements, and if they don't, or the ISA is not fixed-width, detect them from its elements instead: the
+    /// element delimiter follows the segment ID, ISA16 (the sub-element separator) is the character after the 16th
+    /// element delimiter, and the segment delimiter is the character after that. This rescues headers whose
+    /// elements are not padded to the standard widths. [loose_parse](fn.loose_parse.html) detects the delimiters.
+    pub detect_isa_delimiters: bool,
+    /// The most segments a document may contain. A document with more is rejected with an
+    /// [EdiErrorKind::LimitExceeded](enum.EdiErrorKind.html#variant.LimitExceeded) error as soon as the limit is
+    /// passed, before its segments are split into elements, which bounds the memory spent on untrusted input.
+    /// Unlimited by default.
+    pub max_segments: Option<usize>,
+    /// The most interchanges (ISA segments) a document may contain. A document with more is rejected with an
+    /// [EdiErrorKind::LimitExceeded](enum.EdiErrorKind.html#variant.LimitExceeded) error. Unlimited by default.
+    pub max_interchanges: Option<usize>,
+    /// A release character, such as EDIFACT's '?', which escapes a delimiter appearing in element data. An escaped
+    /// segment or element delimiter does not split the document, and the release character is removed from the
+    /// element values of generic segments; escaped sub-element delimiters and release characters are kept, since
+    /// composite elements are not split into their components. The release character is kept in
+    /// [EdiDocument::release_character](struct.EdiDocument.html#structfield.release_character), so that
+    /// [to_x12_string](struct.EdiDocument.html#method.to_x12_string) escapes delimiters in element data again.
+    /// The data of binary segments (BIN, BDS) is delimited by its length and never escaped. X12 has no release
+    /// character, so there is none by default.
+    pub release_character: Option<char>,
+    /// Keep the elements of the closing segments (SE, GE, IEA) in the `original_trailer` of the transaction,
+    /// functional group, or interchange they close, so that
+    /// [to_x12_string](struct.EdiDocument.html#method.to_x12_string) writes them exactly as they were sent, e.g.
+    /// with a zero-padded count, instead of generating them. A trailer which no longer agrees with what it closes,
+    /// because the document has been modified since, is still generated. This matters for systems which hash or
+    /// sign the exact bytes of a document.
+    pub preserve_original_trailers: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            validate_control_counts: true,
+            preserve_whitespace: false,
+            allow_segments_before_group: false,
+            record_segment_offsets: false,
+            case_insensitive_segment_ids: false,
+            allow_empty_segments: false,
+            delimiters: None,
+            allow_isa_without_sub_element_separator: false,
+            numeric_control_numbers: false,
+            check_envelope_element_counts: false,
+            unidentified_transaction_name: UNIDENTIFIED_TRANSACTION_NAME,
+            strict_delimiters: false,
+            allow_missing_interchange: false,
+            keep_raw: false,
+            free_text_segments: &[],
+            detect_isa_delimiters: false,
+            max_segments: None,
+            max_interchanges: None,
+            release_character: None,
+            preserve_original_trailers: false,
+        }
+    }
+}
+
+impl ParseOptions {
+    /// The options used by [loose_parse](fn.loose_parse.html): identical to the defaults, except that
+    /// closing segments are not validated, empty segments are skipped, a document may start without an ISA, and
+    /// the delimiters are detected from the ISA's elements if they are not at its fixed offsets.
+    pub fn loose() -> ParseOptions {
+        ParseOptions {
+            validate_control_counts: false,
+            allow_empty_segments: true,
+            allow_missing_interchange: true,
+            detect_isa_delimiters: true,
+            ..ParseOptions::default()
+        }
+    }
+    /// Whether the control number of a closing segment matches its opener's, honoring
+    /// [numeric_control_numbers](#structfield.numeric_control_numbers).
+    pub(crate) fn control_numbers_match(&self, opener: &str, closer: &str) -> bool {
+        opener == closer || (self.numeric_control_numbers && same_number(opener, closer))
+    }
+}
+
+/// Whether two control numbers or counts are equal, or are both numbers which differ only in zero padding.
+pub(crate) fn same_number(a: &str, b: &str) -> bool {
+    let is_number = |x: &str| !x.is_empty() && x.bytes().all(|x| x.is_ascii_digit());
+    a == b
+        || (is_number(a) && is_number(b) && a.trim_start_matches('0') == b.trim_start_matches('0'))
+}
+
+#[test]
+fn compare_control_numbers() {
+    let options = ParseOptions::default();
+    assert!(options.control_numbers_match("000000001", "000000001"));
+    assert!(!options.control_numbers_match("000000001", "1"));
+
+    let options = ParseOptions {
+        numeric_control_numbers: true,
+        ..ParseOptions::default()
+    };
+    assert!(options.control_numbers_match("000000001", "1"));
+    assert!(options.control_numbers_match("0", "000"));
+    assert!(options.control_numbers_match("A1", "A1"));
+    assert!(!options.control_numbers_match("A1", "A01"));
+    assert!(!options.control_numbers_match("000000001", "10"));
+    assert!(!options.control_numbers_match("", "0"));
+}
@@ -0,0 +1,21 @@
+/// Counts of what a parse produced and what it let through, for gauging how clean a received document was.
+/// Returned by [parse_with_stats](fn.parse_with_stats.html).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseStats {
+    /// The number of interchanges (ISA/IEA) in the document.
+    pub interchanges: usize,
+    /// The number of functional groups (GS/GE) in the document.
+    pub functional_groups: usize,
+    /// The number of transactions (ST/SE) in the document.
+    pub transactions: usize,
+    /// The number of segments which are not part of the envelope, including ones kept outside of a
+    /// functional group.
+    pub generic_segments: usize,
+    /// The number of closing segments (SE, GE, IEA) which were not checked against their openers, because
+    /// [ParseOptions::validate_control_counts](struct.ParseOptions.html#structfield.validate_control_counts) was not set.
+    pub skipped_validations: usize,
+    /// The number of transactions which were not closed by an SE segment before the next ST, GE, IEA, or ISA
+    /// segment or the end of the document, e.g. because the SE was dropped or two transactions were merged. When
+    /// closing segments are validated, each of these is an error as well.
+    pub unclosed_transactions: usize,
+}
@@ -0,0 +1,90 @@
+use core::fmt;
+
+/// An X12 version, release, and subrelease, e.g. 004010, parsed from the version of an interchange (ISA12) or a
+/// functional group (GS08) so that version-dependent rules can be chosen by comparing versions rather than strings.
+/// Versions are ordered by version, then release, then subrelease.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct StandardsVersion {
+    /// The version, e.g. 4 for 004010.
+    pub version: u16,
+    /// The release within the version, e.g. 1 for 004010.
+    pub release: u8,
+    /// The subrelease within the release, e.g. 0 for 004010. ISA12 has no subrelease, so it is 0 there.
+    pub subrelease: u8,
+}
+
+impl StandardsVersion {
+    /// Version 004010, which introduced the sub-element separator in ISA16.
+    pub const V4010: StandardsVersion = StandardsVersion::new(4, 1, 0);
+    /// Version 005010, the version HIPAA transactions use, which introduced the repetition separator in ISA11.
+    pub const V5010: StandardsVersion = StandardsVersion::new(5, 1, 0);
+
+    /// Construct a [StandardsVersion] from its parts.
+    pub const fn new(version: u16, release: u8, subrelease: u8) -> StandardsVersion {
+        StandardsVersion {
+            version,
+            release,
+            subrelease,
+        }
+    }
+
+    /// Parse a version code: three digits of version and two of release, as in ISA12 ("00501"), optionally
+    /// followed by a digit of subrelease and an industry identifier, as in GS08 ("005010X222A1"). `None` if the
+    /// code does not start with five digits.
+    pub fn parse(code: &str) -> Option<StandardsVersion> {
+        let digits = |range: core::ops::Range<usize>| {
+            code.get(range)
+                .filter(|digits| digits.bytes().all(|x| x.is_ascii_digit()))
+        };
+        let version = digits(0..3)?.parse().ok()?;
+        let release = digits(3..5)?.parse().ok()?;
+        let subrelease = digits(5..6)
+            .and_then(|digit| digit.parse().ok())
+            .unwrap_or(0);
+        Some(StandardsVersion::new(version, release, subrelease))
+    }
+}
+
+/// Formats the version the way GS08 holds it, e.g. "004010".
+impl fmt::Display for StandardsVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:03}{:02}{}",
+            self.version, self.release, self.subrelease
+        )
+    }
+}
+
+#[test]
+fn parse_standards_versions() {
+    assert_eq!(
+        StandardsVersion::parse("00401"),
+        Some(StandardsVersion::V4010)
+    );
+    assert_eq!(
+        StandardsVersion::parse("005010X222A1"),
+        Some(StandardsVersion::V5010)
+    );
+    assert_eq!(
+        StandardsVersion::parse("004030VICS"),
+        Some(StandardsVersion::new(4, 3, 0))
+    );
+    assert_eq!(
+        StandardsVersion::parse("003072"),
+        Some(StandardsVersion::new(3, 7, 2))
+    );
+    // without a subrelease digit, the industry identifier may follow the release directly
+    assert_eq!(
+        StandardsVersion::parse("00401X"),
+        Some(StandardsVersion::V4010)
+    );
+    assert_eq!(StandardsVersion::parse("4010"), None);
+    assert_eq!(StandardsVersion::parse("0040A"), None);
+    assert_eq!(StandardsVersion::parse(""), None);
+    assert_eq!(StandardsVersion::parse("00é01"), None);
+
+    assert!(StandardsVersion::parse("00401").unwrap() < StandardsVersion::V5010);
+    assert!(StandardsVersion::new(4, 1, 1) > StandardsVersion::V4010);
+    assert_eq!(StandardsVersion::V5010.to_string(), "005010");
+}
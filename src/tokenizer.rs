@@ -1,65 +1,570 @@
-use crate::edi_parse_error::EdiParseError;
+use crate::delimiters::Delimiters;
+use crate::edi_parse_error::{EdiErrorKind, EdiParseError};
+use crate::parse_options::ParseOptions;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
 /// The type that represents a 2d vec of tokens representing EDI segments and their elements.
 pub type DocumentTokens<'a> = Vec<SegmentTokens<'a>>;
 /// The type that represents an individual segment's tokens.
 pub type SegmentTokens<'a> = Vec<&'a str>;
 
+/// The byte offsets of the element delimiters within a canonical, fixed-width ISA segment. The last one
+/// precedes the sub-element delimiter (ISA16), which is in turn followed by the segment delimiter.
+const ISA_ELEMENT_DELIMITER_OFFSETS: [usize; 16] = [
+    3, 6, 17, 20, 31, 34, 50, 53, 69, 76, 81, 83, 89, 99, 101, 103,
+];
+
+/// The segments which carry binary data, with the position of the element declaring the length of the data.
+/// The data itself is the element after it and runs for exactly that many bytes, delimiters included.
+const BINARY_SEGMENTS: [(&str, usize); 2] = [("BIN", 1), ("BDS", 2)];
+
 pub(crate) struct TokenizeResult<'a> {
     pub(crate) element_delimiter: char,
     pub(crate) sub_element_delimiter: char,
     pub(crate) segment_delimiter: char,
+    /// The character escaping delimiters in element data, if any. The tokens still contain it.
+    pub(crate) release_character: Option<char>,
     pub(crate) tokens: DocumentTokens<'a>,
+    /// The `(start, end)` byte range of each segment in `tokens` within the input, if requested.
+    pub(crate) segment_offsets: Option<Vec<(usize, usize)>>,
+    /// The text of each segment in `tokens` without surrounding line breaks, if requested.
+    pub(crate) raw_segments: Option<Vec<&'a str>>,
 }
 
 /// The input is the entire EDI document string, and the output is a 2d array of edi segments and their elements.
 /// If an element has subelements, they are not separated into separate tokens. It also performs some basic
 /// sanity checks to see if the input is of the format we are expecting and validates that all ISA/GS openers
-/// are closed.
-pub(crate) fn tokenize<'a>(input: &'a str) -> Result<TokenizeResult<'a>, EdiParseError> {
+/// are closed. Unless [ParseOptions::preserve_whitespace] is set, whitespace surrounding each segment is trimmed.
+/// If [ParseOptions::delimiters] is set, this is [tokenize_with_delimiters] with those delimiters.
+pub(crate) fn tokenize<'a>(
+    input: &'a str,
+    options: &ParseOptions,
+) -> Result<TokenizeResult<'a>, EdiParseError> {
+    if let Some((segment_delimiter, element_delimiter, sub_element_delimiter)) = options.delimiters
+    {
+        return tokenize_with_delimiters(
+            input,
+            segment_delimiter,
+            element_delimiter,
+            sub_element_delimiter,
+            options,
+        );
+    }
+    // A byte order mark would shift the fixed ISA offsets the delimiters are read from.
+    let original_input = input;
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    if let Some((segment_delimiter, element_delimiter, sub_element_delimiter)) = options
+        .allow_missing_interchange
+        .then(|| fragment_delimiters(input))
+        .flatten()
+    {
+        return tokenize_with_delimiters(
+            original_input,
+            segment_delimiter,
+            element_delimiter,
+            sub_element_delimiter,
+            options,
+        );
+    }
+    if options.allow_isa_without_sub_element_separator
+        && input.len() >= 104
+        && input.as_bytes()[103].is_ascii()
+        && input.as_bytes()[103] != input.as_bytes()[ISA_ELEMENT_DELIMITER_OFFSETS[0]]
+    {
+        // Without ISA16, the segment delimiter takes the place of the element delimiter which would precede it.
+        validate_isa_layout(input, options, &ISA_ELEMENT_DELIMITER_OFFSETS[..15])?;
+        let element_delimiter = input.as_bytes()[ISA_ELEMENT_DELIMITER_OFFSETS[0]] as char;
+        let segment_delimiter = input.as_bytes()[103] as char;
+        let sub_element_delimiter = if element_delimiter != ':' && segment_delimiter != ':' {
+            ':'
+        } else {
+            '>'
+        };
+        return tokenize_with_delimiters(
+            original_input,
+            segment_delimiter,
+            element_delimiter,
+            sub_element_delimiter,
+            options,
+        );
+    }
+    let delimiters = match fixed_width_delimiters(input, options) {
+        Ok(delimiters)
+            if !options.detect_isa_delimiters || splits_isa_into_elements(input, delimiters) =>
+        {
+            delimiters
+        }
+        Err(error) if !options.detect_isa_delimiters => return Err(error),
+        _ => detect_isa_delimiters(input, options).ok_or_else(|| {
+            EdiParseError::new(
+                EdiErrorKind::MalformedHeader,
+                "malformed ISA header: neither the delimiters at its fixed offsets nor the ones following its elements split the ISA into 16 elements",
+                None,
+            )
+        })?,
+    };
+    let (element_delimiter, sub_element_delimiter, segment_delimiter) = delimiters;
+    tokenize_with_delimiters(
+        original_input,
+        segment_delimiter,
+        element_delimiter,
+        sub_element_delimiter,
+        options,
+    )
+}
+
+/// Read the `(element, sub-element, segment)` delimiters from their fixed offsets in the standard 106 byte ISA
+/// segment.
+fn fixed_width_delimiters(
+    input: &str,
+    options: &ParseOptions,
+) -> Result<(char, char, char), EdiParseError> {
     edi_assert!(
+        EdiErrorKind::MalformedHeader,
         input.len() >= 106,
         "input not long enough to contain ISA header delimiters"
     );
-    let delimiters_str: Vec<char> = input[103..106].chars().collect();
-    let (element_delimiter, sub_element_delimiter, segment_delimiter) =
-        (delimiters_str[0], delimiters_str[1], delimiters_str[2]);
-    edi_assert!(
-        element_delimiter != sub_element_delimiter,
-        "element and subelement delimiters cannot be the same",
-        element_delimiter,
-        sub_element_delimiter
-    );
+    validate_isa_layout(input, options, &ISA_ELEMENT_DELIMITER_OFFSETS)?;
+    // The length is counted in bytes, so a multibyte character may still straddle the delimiters.
+    let delimiters_str: Vec<char> = input
+        .get(103..106)
+        .map(|delimiters| delimiters.chars().collect())
+        .unwrap_or_default();
     edi_assert!(
-        sub_element_delimiter != segment_delimiter,
-        "subelement and segment delimiters cannot be the same",
-        sub_element_delimiter,
-        segment_delimiter
-    );
-    edi_assert!(
-        element_delimiter != segment_delimiter,
-        "element and segment delimiters cannot be the same",
-        element_delimiter,
-        segment_delimiter
+        EdiErrorKind::MalformedHeader,
+        delimiters_str.len() == 3,
+        "malformed ISA header: the delimiters at bytes 103 to 105 must be single-byte characters"
     );
-    // Filter out any empty segments caused by newlines.
-    let segments: SegmentTokens = input
+    Ok((delimiters_str[0], delimiters_str[1], delimiters_str[2]))
+}
+
+/// Read the `(element, sub-element, segment)` delimiters of the ISA segment at the start of `input`, from their
+/// fixed offsets or, if the ISA is not fixed-width, by walking its elements, without tokenizing the rest of the
+/// input. `None` if the input does not start with an ISA segment whose delimiters can be found either way.
+pub(crate) fn isa_delimiters(input: &str) -> Option<(char, char, char)> {
+    let options = ParseOptions::default();
+    fixed_width_delimiters(input, &options)
+        .ok()
+        .filter(|delimiters| splits_isa_into_elements(input, *delimiters))
+        .or_else(|| detect_isa_delimiters(input, &options))
+}
+
+/// Find the `(element, sub-element, segment)` delimiters of an ISA segment whose elements may not have their
+/// fixed widths, by walking its elements instead. See [ParseOptions::detect_isa_delimiters].
+fn detect_isa_delimiters(input: &str, options: &ParseOptions) -> Option<(char, char, char)> {
+    let segment_id = input.get(..3)?;
+    if segment_id != "ISA"
+        && !(options.case_insensitive_segment_ids && segment_id.eq_ignore_ascii_case("ISA"))
+    {
+        return None;
+    }
+    let mut rest = input[3..].chars();
+    let element_delimiter = rest.next()?;
+    // ISA02 to ISA16 are each preceded by another element delimiter.
+    for _ in 0..15 {
+        rest.find(|x| *x == element_delimiter)?;
+    }
+    let sub_element_delimiter = rest.next()?;
+    let segment_delimiter = rest.next()?;
+    let delimiters = (element_delimiter, sub_element_delimiter, segment_delimiter);
+    let distinct = element_delimiter != sub_element_delimiter
+        && element_delimiter != segment_delimiter
+        && sub_element_delimiter != segment_delimiter;
+    (distinct && splits_isa_into_elements(input, delimiters)).then_some(delimiters)
+}
+
+/// Whether splitting the ISA segment (up to the first segment delimiter) on the element delimiter yields its ID
+/// and 16 elements.
+fn splits_isa_into_elements(
+    input: &str,
+    (element_delimiter, _, segment_delimiter): (char, char, char),
+) -> bool {
+    input
         .split(segment_delimiter)
-        .map(|x| x.trim())
-        .filter(|x| *x != "")
+        .next()
+        .is_some_and(|isa| isa.split(element_delimiter).count() == 17)
+}
+
+/// Guess the delimiters of a fragment which starts with a GS or ST segment, since there is no ISA header to read
+/// them from. The segment delimiter is the first '~' or line break after the elements the first segment requires
+/// (eight for GS, two for ST), so either may still appear in the data of later segments. See
+/// [ParseOptions::allow_missing_interchange].
+fn fragment_delimiters(input: &str) -> Option<(char, char, char)> {
+    let input = input.trim_start();
+    let required_elements = if input.starts_with("GS") {
+        8
+    } else if input.starts_with("ST") {
+        2
+    } else {
+        return None;
+    };
+    let element_delimiter = input[2..].chars().next()?;
+    let last_element = input
+        .match_indices(element_delimiter)
+        .nth(required_elements - 1)
+        .map_or(0, |(index, _)| index);
+    let segment_delimiter = input[last_element..]
+        .chars()
+        .find(|x| *x == '~' || *x == '\n')
+        .unwrap_or('\n');
+    let sub_element_delimiter = if element_delimiter != ':' && segment_delimiter != ':' {
+        ':'
+    } else {
+        '>'
+    };
+    Some((segment_delimiter, element_delimiter, sub_element_delimiter))
+}
+
+/// Like [tokenize], but with delimiters supplied by the caller instead of read from the fixed offsets of the
+/// ISA segment, so the ISA header does not have to be the standard 106 bytes wide.
+pub(crate) fn tokenize_with_delimiters<'a>(
+    input: &'a str,
+    segment_delimiter: char,
+    element_delimiter: char,
+    sub_element_delimiter: char,
+    options: &ParseOptions,
+) -> Result<TokenizeResult<'a>, EdiParseError> {
+    let original_input = input;
+    let input = input.strip_prefix('\u{feff}').unwrap_or(input);
+    let release_character = options.release_character;
+    let delimiters = Delimiters::new(segment_delimiter, element_delimiter, sub_element_delimiter);
+    match release_character {
+        Some(release) => delimiters.with_release(release),
+        None => delimiters,
+    }
+    .check_distinct(EdiErrorKind::MalformedHeader)?;
+    if options.strict_delimiters {
+        for (name, delimiter) in [
+            ("segment", segment_delimiter),
+            ("element", element_delimiter),
+            ("subelement", sub_element_delimiter),
+        ]
+        .iter()
+        {
+            edi_assert!(
+                EdiErrorKind::MalformedHeader,
+                delimiter.is_ascii_punctuation() || delimiter.is_ascii_control(),
+                format!(
+                    "unconventional {} delimiter; the ISA segment may be malformed",
+                    name
+                )
+                .as_str(),
+                "an ASCII punctuation or control character",
+                delimiter.escape_debug()
+            );
+        }
+    }
+    // When the segment delimiter is a carriage return that is followed by a line feed, the segments are really
+    // terminated by CRLF, so split on both rather than leaving a line feed at the start of every segment.
+    let crlf_terminated = segment_delimiter == '\r'
+        && input
+            .find('\r')
+            .is_some_and(|position| input[position + 1..].starts_with('\n'));
+    let segment_terminator = if crlf_terminated {
+        String::from("\r\n")
+    } else {
+        String::from(segment_delimiter)
+    };
+    let raw_segments = split_segments(
+        input,
+        &segment_terminator,
+        element_delimiter,
+        release_character,
+        options.max_segments,
+    )?;
+    // The untrimmed segment is kept alongside so that its position in the input can be recorded. The end of
+    // a binary segment is its data, which is never trimmed.
+    let segments: Vec<(&str, &str)> = raw_segments
+        .into_iter()
+        .map(|x| {
+            let binary = binary_data_position(x.trim_start()).is_some();
+            match (options.preserve_whitespace, binary) {
+                (true, false) => (x, x.trim_matches(|c| c == '\r' || c == '\n')),
+                (true, true) => (x, x.trim_start_matches(['\r', '\n'])),
+                (false, false) => (x, x.trim()),
+                (false, true) => (x, x.trim_start()),
+            }
+        })
         .collect();
+    // Anything after the last real segment (a trailing delimiter, line breaks) is always ignored, but two
+    // delimiters with nothing but whitespace between them in the middle of the document are only accepted
+    // with `allow_empty_segments`.
+    let is_empty = |(_, x): &(&str, &str)| x.trim().is_empty();
+    let last_segment = segments.iter().rposition(|x| !is_empty(x)).unwrap_or(0);
+    if !options.allow_empty_segments {
+        if let Some((raw, _)) = segments[..last_segment].iter().find(|x| is_empty(x)) {
+            return Err(EdiParseError::new(
+                EdiErrorKind::MalformedSegment,
+                &format!(
+                    "empty segment found at byte {} of the input",
+                    raw.as_ptr() as usize - original_input.as_ptr() as usize
+                ),
+                None,
+            ));
+        }
+    }
+    let segments: Vec<(&str, &str)> = segments.into_iter().filter(|x| !is_empty(x)).collect();
+    let segment_offsets = if options.record_segment_offsets {
+        Some(
+            segments
+                .iter()
+                .map(|(raw, _)| {
+                    let start = raw.as_ptr() as usize - original_input.as_ptr() as usize;
+                    (start, start + raw.len())
+                })
+                .collect(),
+        )
+    } else {
+        None
+    };
+    let raw_segments = if options.keep_raw {
+        Some(
+            segments
+                .iter()
+                .map(|(raw, _)| match binary_data_position(raw.trim_start()) {
+                    Some(_) => raw.trim_start_matches(['\r', '\n']),
+                    None => raw.trim_matches(['\r', '\n']),
+                })
+                .collect(),
+        )
+    } else {
+        None
+    };
+    let is_free_text = |segment: &str| {
+        let segment_id = segment.split(element_delimiter).next().unwrap_or("").trim();
+        options.free_text_segments.contains(&segment_id)
+    };
     let tokens: DocumentTokens = segments
         .iter()
-        .map(|x| x.split(element_delimiter).collect::<Vec<&str>>())
+        .map(|(_, x)| match binary_data_position(x) {
+            Some(data_position) => x
+                .splitn(data_position + 1, element_delimiter)
+                .collect::<Vec<&str>>(),
+            // The segment ID, the qualifier, and the text
+            None if is_free_text(x) => {
+                split_unescaped(x, element_delimiter, release_character, Some(3))
+            }
+            None => split_unescaped(x, element_delimiter, release_character, None),
+        })
         .collect();
 
     Ok(TokenizeResult {
         tokens,
+        segment_offsets,
+        raw_segments,
         element_delimiter,
         sub_element_delimiter,
         segment_delimiter,
+        release_character,
     })
 }
 
+/// The position of the binary data element of `segment` (a segment ID, or a whole segment starting with its ID),
+/// if it is one of the [BINARY_SEGMENTS].
+pub(crate) fn binary_data_position(segment: &str) -> Option<usize> {
+    BINARY_SEGMENTS
+        .iter()
+        .find(|(id, _)| {
+            segment.starts_with(id)
+                && !segment[id.len()..].starts_with(|x: char| x.is_ascii_alphanumeric())
+        })
+        .map(|(_, length_position)| length_position + 1)
+}
+
+/// Split the input on the segment terminator, like `str::split`, except that the data of binary segments
+/// (see [BINARY_SEGMENTS]) is consumed by its declared length so that delimiters within it don't split it.
+/// A segment terminator escaped with the release character does not end a segment. Fails as soon as there are
+/// more than `max_segments` non-empty segments, before the rest of the input is split.
+fn split_segments<'a>(
+    input: &'a str,
+    segment_terminator: &str,
+    element_delimiter: char,
+    release_character: Option<char>,
+    max_segments: Option<usize>,
+) -> Result<Vec<&'a str>, EdiParseError> {
+    let mut segments = Vec::new();
+    let mut segment_count = 0;
+    let mut rest = input;
+    loop {
+        let leading_whitespace = rest.len() - rest.trim_start().len();
+        let search_from = match binary_data_end(&rest[leading_whitespace..], element_delimiter)? {
+            Some(data_end) => {
+                let data_end = leading_whitespace + data_end;
+                edi_assert!(
+                    EdiErrorKind::MalformedSegment,
+                    rest[data_end..].starts_with(segment_terminator)
+                        || rest[data_end..].trim().is_empty(),
+                    "binary segment data is not followed by the segment delimiter, so it is longer than its declared length",
+                    segment_terminator.escape_debug(),
+                    rest[data_end..].chars().take(20).collect::<String>()
+                );
+                data_end
+            }
+            None => 0,
+        };
+        let (segment, next) =
+            match find_unescaped(&rest[search_from..], segment_terminator, release_character) {
+                Some(position) => (
+                    &rest[..search_from + position],
+                    Some(&rest[search_from + position + segment_terminator.len()..]),
+                ),
+                None => (rest, None),
+            };
+        if !segment.trim().is_empty() {
+            segment_count += 1;
+            if let Some(max_segments) = max_segments {
+                edi_assert!(
+                    EdiErrorKind::LimitExceeded,
+                    segment_count <= max_segments,
+                    "document contains more segments than allowed",
+                    format!("at most {} segments", max_segments),
+                    format!("more than {}", max_segments)
+                );
+            }
+        }
+        segments.push(segment);
+        match next {
+            Some(next) => rest = next,
+            None => return Ok(segments),
+        }
+    }
+}
+
+/// The byte offset of the first occurrence of `pattern` in `text` which is not escaped with the release character.
+fn find_unescaped(text: &str, pattern: &str, release_character: Option<char>) -> Option<usize> {
+    let release = match release_character {
+        Some(release) => release,
+        None => return text.find(pattern),
+    };
+    let mut chars = text.char_indices();
+    while let Some((position, x)) = chars.next() {
+        if x == release {
+            chars.next();
+        } else if text[position..].starts_with(pattern) {
+            return Some(position);
+        }
+    }
+    None
+}
+
+/// Split a segment on the element delimiter into at most `limit` elements, like `str::splitn`, except that a
+/// delimiter escaped with the release character does not split it. The escapes are kept in the elements.
+fn split_unescaped(
+    segment: &str,
+    element_delimiter: char,
+    release_character: Option<char>,
+    limit: Option<usize>,
+) -> Vec<&str> {
+    let limit = limit.unwrap_or(usize::MAX);
+    let release = match release_character {
+        Some(release) => release,
+        None => return segment.splitn(limit, element_delimiter).collect(),
+    };
+    let mut elements = Vec::new();
+    let mut start = 0;
+    let mut chars = segment.char_indices();
+    while let Some((position, x)) = chars.next() {
+        if elements.len() + 1 == limit {
+            break;
+        }
+        if x == release {
+            chars.next();
+        } else if x == element_delimiter {
+            elements.push(&segment[start..position]);
+            start = position + x.len_utf8();
+        }
+    }
+    elements.push(&segment[start..]);
+    elements
+}
+
+/// If `rest` starts with a binary segment, the byte offset within it at which the segment's data ends.
+fn binary_data_end(rest: &str, element_delimiter: char) -> Result<Option<usize>, EdiParseError> {
+    let data_position = match binary_data_position(rest) {
+        Some(data_position) => data_position,
+        None => return Ok(None),
+    };
+    let mut elements = rest.splitn(data_position + 1, element_delimiter);
+    let (length, data) = match (elements.nth(data_position - 1), elements.next()) {
+        (Some(length), Some(data)) => (length, data),
+        _ => return Ok(None),
+    };
+    let length = match str::parse::<usize>(length.trim()) {
+        Ok(length) => length,
+        Err(_) => {
+            return Err(EdiParseError::new(
+                EdiErrorKind::MalformedSegment,
+                &format!(
+                    "binary segment does not declare a valid length  --  expected: a number  received: {}",
+                    length
+                ),
+                None,
+            ))
+        }
+    };
+    edi_assert!(
+        EdiErrorKind::MalformedSegment,
+        data.len() >= length,
+        "binary segment data is shorter than its declared length",
+        length,
+        data.len()
+    );
+    edi_assert!(
+        EdiErrorKind::MalformedSegment,
+        data.is_char_boundary(length),
+        "binary segment data ends in the middle of a character",
+        length,
+        data.len()
+    );
+    Ok(Some(
+        data.as_ptr() as usize - rest.as_ptr() as usize + length,
+    ))
+}
+
+/// The delimiters are read from fixed offsets in the ISA segment, so make sure the header actually has the
+/// fixed-width layout before trusting them. Every element delimiter in the ISA must be where it belongs, i.e. at
+/// each of the given `offsets`.
+fn validate_isa_layout(
+    input: &str,
+    options: &ParseOptions,
+    offsets: &[usize],
+) -> Result<(), EdiParseError> {
+    let bytes = input.as_bytes();
+    edi_assert!(
+        EdiErrorKind::MalformedHeader,
+        &bytes[0..3] == b"ISA"
+            || (options.case_insensitive_segment_ids && bytes[0..3].eq_ignore_ascii_case(b"ISA")),
+        "malformed ISA header: input must begin with an ISA segment",
+        "ISA",
+        String::from_utf8_lossy(&bytes[0..3])
+    );
+    let element_delimiter = bytes[ISA_ELEMENT_DELIMITER_OFFSETS[0]];
+    edi_assert!(
+        EdiErrorKind::MalformedHeader,
+        element_delimiter.is_ascii(),
+        "malformed ISA header: the element delimiter must be a single-byte character",
+        "an ASCII character",
+        input[ISA_ELEMENT_DELIMITER_OFFSETS[0]..]
+            .chars()
+            .next()
+            .unwrap_or_default()
+    );
+    for offset in offsets.iter() {
+        edi_assert!(
+            EdiErrorKind::MalformedHeader,
+            bytes[*offset] == element_delimiter,
+            format!(
+                "malformed ISA header: the element delimiter must appear at byte {} of the fixed-width ISA segment",
+                offset
+            ),
+            element_delimiter as char,
+            String::from_utf8_lossy(&bytes[*offset..=*offset])
+        );
+    }
+    Ok(())
+}
+
 // I tend to put individual unit tests inside the file they belong to, and E2E/integration tests in the tests directory.
 #[test]
 fn basic_segment_tokenize() {
@@ -75,7 +580,7 @@ SE*35*000000001~
 GE*1*1~
 IEA*1*000000001~";
 
-    let res = tokenize(test_input).unwrap();
+    let res = tokenize(test_input, &ParseOptions::default()).unwrap();
     assert_eq!(res.tokens.len(), 11);
     assert_eq!(res.tokens[0].len(), 17);
     assert_eq!(res.element_delimiter, '*');
@@ -87,7 +592,7 @@ IEA*1*000000001~";
 fn fail_to_tokenize_no_header() {
     let test_input =
         "00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T";
-    assert!(tokenize(test_input).is_err());
+    assert!(tokenize(test_input, &ParseOptions::default()).is_err());
 }
 
 #[test]
@@ -95,5 +600,315 @@ fn fail_same_delimiters() {
     let test_input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T~~~
 GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
 ST*850*000000001~";
-    assert!(tokenize(test_input).is_err());
+    assert!(tokenize(test_input, &ParseOptions::default()).is_err());
+}
+
+#[test]
+fn record_segment_offsets() {
+    let test_input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~~";
+    let options = ParseOptions {
+        record_segment_offsets: true,
+        ..ParseOptions::default()
+    };
+
+    let res = tokenize(test_input, &options).unwrap();
+    let offsets = res.segment_offsets.unwrap();
+    assert_eq!(offsets.len(), 3);
+    assert_eq!(offsets[0], (0, 105));
+    assert_eq!(
+        &test_input[offsets[1].0..offsets[1].1],
+        "\nGS*PO*SENDERGS*007326879*20020226*1534*1*X*004010"
+    );
+    assert_eq!(
+        &test_input[offsets[2].0..offsets[2].1],
+        "\nST*850*000000001"
+    );
+
+    let res = tokenize(test_input, &ParseOptions::default()).unwrap();
+    assert!(res.segment_offsets.is_none());
+}
+
+#[test]
+fn tokenize_with_byte_order_mark() {
+    let test_input = "\u{feff}ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~";
+    let options = ParseOptions {
+        record_segment_offsets: true,
+        ..ParseOptions::default()
+    };
+
+    let res = tokenize(test_input, &options).unwrap();
+    assert_eq!(res.tokens[0][0], "ISA");
+    assert_eq!(res.element_delimiter, '*');
+    assert_eq!(res.sub_element_delimiter, '>');
+    assert_eq!(res.segment_delimiter, '~');
+    // offsets are relative to the input as given, byte order mark included
+    assert_eq!(res.segment_offsets.unwrap()[0], (3, 108));
+}
+
+#[test]
+fn fail_to_tokenize_misaligned_header() {
+    // ISA06 is one character short, so every following delimiter is shifted
+    let test_input = "ISA*00*          *00*          *ZZ*SENDERISA     *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~";
+    let error = tokenize(test_input, &ParseOptions::default())
+        .err()
+        .unwrap();
+    assert!(error.reason().contains("byte 50"));
+}
+
+#[test]
+fn fail_to_tokenize_non_isa_header() {
+    let test_input = "GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*000000001~BEG*00*SA*A99999-01**19970214~SE*3*000000001~";
+    assert!(tokenize(test_input, &ParseOptions::default()).is_err());
+}
+
+#[test]
+fn tokenize_trailing_and_empty_segments() {
+    let header = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~";
+
+    // a trailing delimiter, trailing whitespace, and mixed line endings are all fine
+    let test_input = format!(
+        "{}\r\nGS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~\nST*850*000000001~\r\n \r\n",
+        header
+    );
+    let res = tokenize(&test_input, &ParseOptions::default()).unwrap();
+    assert_eq!(res.tokens.len(), 3);
+    assert_eq!(res.tokens[2], vec!["ST", "850", "000000001"]);
+
+    // an empty segment in the middle is rejected unless empty segments are allowed
+    let test_input = format!(
+        "{}\nGS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~\r\n~\nST*850*000000001~",
+        header
+    );
+    assert!(tokenize(&test_input, &ParseOptions::default()).is_err());
+    let res = tokenize(&test_input, &ParseOptions::loose()).unwrap();
+    assert_eq!(res.tokens.len(), 3);
+}
+
+#[test]
+fn tokenize_non_standard_isa_with_explicit_delimiters() {
+    // ISA06 is padded to a different width than the standard requires
+    let test_input = "ISA|00|          |00|          |ZZ|SENDER|14|0073268795005  |020226|1534|U|00401|000000001|0|T|^!
+GS|PO|SENDERGS|007326879|20020226|1534|1|X|004010!
+ST|850|000000001!";
+    assert!(tokenize(test_input, &ParseOptions::default()).is_err());
+
+    let res =
+        tokenize_with_delimiters(test_input, '!', '|', '^', &ParseOptions::default()).unwrap();
+    assert_eq!(res.tokens.len(), 3);
+    assert_eq!(res.tokens[0].len(), 17);
+    assert_eq!(res.tokens[0][6], "SENDER");
+    assert_eq!(res.sub_element_delimiter, '^');
+
+    let options = ParseOptions {
+        delimiters: Some(('!', '|', '^')),
+        ..ParseOptions::default()
+    };
+    assert_eq!(tokenize(test_input, &options).unwrap().tokens, res.tokens);
+
+    assert!(tokenize_with_delimiters(test_input, '!', '|', '|', &ParseOptions::default()).is_err());
+}
+
+#[test]
+fn tokenize_escaped_delimiters() {
+    let test_input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+MSG*SIZE 3?*4?~ 10??*1~
+MSG*A?>B?";
+    let options = ParseOptions {
+        release_character: Some('?'),
+        ..ParseOptions::default()
+    };
+    let res = tokenize(test_input, &options).unwrap();
+    assert_eq!(res.tokens.len(), 5);
+    assert_eq!(res.tokens[3], vec!["MSG", "SIZE 3?*4?~ 10??", "1"]);
+    // an escape at the end of the input has nothing to escape
+    assert_eq!(res.tokens[4], vec!["MSG", "A?>B?"]);
+    assert_eq!(res.release_character, Some('?'));
+
+    // without a release character, the same input is split on every delimiter
+    let res = tokenize(test_input, &ParseOptions::default()).unwrap();
+    assert_eq!(res.tokens[3], vec!["MSG", "SIZE 3?", "4?"]);
+
+    let options = ParseOptions {
+        release_character: Some('*'),
+        ..ParseOptions::default()
+    };
+    assert!(tokenize(test_input, &options).is_err());
+}
+
+#[test]
+fn tokenize_crlf_terminated_segments() {
+    let test_input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>\r\nGS*PO*SENDERGS*007326879*20020226*1534*1*X*004010 \r\nST*850*000000001\r\n";
+    let options = ParseOptions {
+        preserve_whitespace: true,
+        record_segment_offsets: true,
+        ..ParseOptions::default()
+    };
+
+    let res = tokenize(test_input, &options).unwrap();
+    assert_eq!(res.segment_delimiter, '\r');
+    assert_eq!(res.tokens.len(), 3);
+    assert_eq!(res.tokens[1][8], "004010 ");
+    let offsets = res.segment_offsets.unwrap();
+    assert_eq!(
+        &test_input[offsets[1].0..offsets[1].1],
+        "GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010 "
+    );
+    assert_eq!(&test_input[offsets[2].0..offsets[2].1], "ST*850*000000001");
+}
+
+#[test]
+fn tokenize_isa_without_sub_element_separator() {
+    let test_input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~";
+    assert!(tokenize(test_input, &ParseOptions::default()).is_err());
+
+    let options = ParseOptions {
+        allow_isa_without_sub_element_separator: true,
+        ..ParseOptions::default()
+    };
+    let res = tokenize(test_input, &options).unwrap();
+    assert_eq!(res.element_delimiter, '*');
+    assert_eq!(res.segment_delimiter, '~');
+    assert_eq!(res.sub_element_delimiter, ':');
+    assert_eq!(res.tokens[0].len(), 16);
+    assert_eq!(res.tokens[0][15], "T");
+
+    // the usual layout is still read as usual
+    let test_input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~";
+    let res = tokenize(test_input, &options).unwrap();
+    assert_eq!(res.sub_element_delimiter, '>');
+}
+
+#[test]
+fn tokenize_binary_segments() {
+    let test_input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+BIN*11*a*b~c\r\n d *~
+BDS*B64*4*QQ==~
+BIN*0*~
+REF*DP*099~";
+    let res = tokenize(test_input, &ParseOptions::default()).unwrap();
+    assert_eq!(res.tokens[2], vec!["BIN", "11", "a*b~c\r\n d *"]);
+    assert_eq!(res.tokens[3], vec!["BDS", "B64", "4", "QQ=="]);
+    assert_eq!(res.tokens[4], vec!["BIN", "0", ""]);
+    assert_eq!(res.tokens[5], vec!["REF", "DP", "099"]);
+
+    let too_short = test_input.replace("BIN*11*", "BIN*10*");
+    assert!(tokenize(&too_short, &ParseOptions::default()).is_err());
+    let too_long = test_input.replace("BIN*11*", "BIN*12*");
+    assert!(tokenize(&too_long, &ParseOptions::default()).is_err());
+    let no_length = test_input.replace("BIN*11*", "BIN*eleven*");
+    assert!(tokenize(&no_length, &ParseOptions::default()).is_err());
+
+    // segments which merely start with the same letters are split as usual
+    let not_binary = test_input.replace("BIN*11*", "BINX*11*");
+    let res = tokenize(&not_binary, &ParseOptions::default()).unwrap();
+    assert_eq!(res.tokens[2], vec!["BINX", "11", "a", "b"]);
+}
+
+#[test]
+fn reject_unconventional_delimiters() {
+    let test_input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~";
+    let options = ParseOptions {
+        strict_delimiters: true,
+        ..ParseOptions::default()
+    };
+    assert!(tokenize(test_input, &options).is_ok());
+    let newline_delimited = test_input.replace("~\n", "\n");
+    assert!(tokenize(&newline_delimited, &options).is_ok());
+
+    // the ISA is missing its segment terminator, so the next segment's ID is taken for it
+    let malformed = test_input.replace(">~\n", ">");
+    assert!(tokenize(&malformed, &ParseOptions::default()).is_ok());
+    let error = tokenize(&malformed, &options).err().unwrap();
+    assert_eq!(
+        error.reason(),
+        "unconventional segment delimiter; the ISA segment may be malformed  --  expected: an ASCII punctuation or control character  received: G"
+    );
+
+    let options = ParseOptions {
+        delimiters: Some(('~', 'x', ':')),
+        ..options
+    };
+    let error = tokenize(test_input, &options).err().unwrap();
+    assert!(error
+        .reason()
+        .starts_with("unconventional element delimiter"));
+}
+
+#[test]
+fn tokenize_multibyte_characters_in_isa_header() {
+    let header = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~";
+    let options = [
+        ParseOptions::default(),
+        ParseOptions {
+            allow_isa_without_sub_element_separator: true,
+            ..ParseOptions::default()
+        },
+    ];
+    // a multibyte character anywhere around the delimiters must produce an error rather than a panic
+    for character in ['é', '€', '😀'].iter() {
+        for offset in 95..header.len() {
+            let mut input = String::from(&header[..offset]);
+            input.push(*character);
+            input.push_str(&header[offset + 1..]);
+            for options in options.iter() {
+                let _ = tokenize(&input, options);
+            }
+        }
+    }
+    for delimiter in ['é', '€', '😀'].iter() {
+        let input = header.replace('*', &delimiter.to_string());
+        for options in options.iter() {
+            assert!(tokenize(&input, options).is_err());
+        }
+    }
+}
+
+#[test]
+fn detect_delimiters_of_unpadded_isa() {
+    let test_input =
+        "ISA*00**00**ZZ*SENDERISA*14*0073268795005*020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~";
+    assert!(tokenize(test_input, &ParseOptions::default()).is_err());
+
+    let options = ParseOptions {
+        detect_isa_delimiters: true,
+        ..ParseOptions::default()
+    };
+    let res = tokenize(test_input, &options).unwrap();
+    assert_eq!(res.element_delimiter, '*');
+    assert_eq!(res.sub_element_delimiter, '>');
+    assert_eq!(res.segment_delimiter, '~');
+    assert_eq!(res.tokens[0].len(), 17);
+    assert_eq!(res.tokens[1][0], "GS");
+
+    // the fixed offsets are still used when they split the ISA correctly
+    let test_input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~";
+    let res = tokenize(test_input, &options).unwrap();
+    assert_eq!(res.segment_delimiter, '~');
+    assert_eq!(res.tokens[0][6].trim(), "SENDERISA");
+
+    // a segment delimiter within an element cuts the ISA short, whichever way the delimiters are read
+    let segment_delimiter_in_element = test_input.replacen("SENDERISA ", "SENDER~ISA", 1);
+    assert!(tokenize(&segment_delimiter_in_element, &ParseOptions::default()).is_ok());
+    let error = tokenize(&segment_delimiter_in_element, &options)
+        .err()
+        .unwrap();
+    assert!(error.reason().starts_with("malformed ISA header: neither"));
+    assert!(tokenize(
+        "ISA*00**00**ZZ*SENDERISA*14*0073268795005*020226*1534*U*00401*000000001*0*T",
+        &options
+    )
+    .is_err());
 }
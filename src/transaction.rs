@@ -1,53 +1,249 @@
-use crate::edi_parse_error::EdiParseError;
+use crate::delimiters::Delimiters;
+use crate::edi_parse_error::{parse_count, EdiErrorKind, EdiParseError};
 use crate::generic_segment::GenericSegment;
+use crate::hl_node::HlNode;
+use crate::parse_options::{same_number, ParseOptions};
 use crate::tokenizer::SegmentTokens;
+use alloc::borrow::Cow;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
 use csv::ReaderBuilder;
+#[cfg(feature = "std")]
 use lazy_static::lazy_static;
-use serde::{Deserialize, Serialize};
-use std::borrow::Cow;
-use std::collections::{HashMap, VecDeque};
+#[cfg(feature = "rust_decimal")]
+use rust_decimal::Decimal;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+#[cfg(feature = "std")]
+use std::collections::HashMap;
 
 /// Represents a transaction in an EDI document. A transaction is initialized with an ST segment
 /// and ended with an SE segment.
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Serialize)]
 pub struct Transaction<'a, 'b> {
     /// The numeric code which represents the type of transaction.
     #[serde(borrow)]
     pub transaction_code: Cow<'a, str>,
     /// The name of the transaction type in human-readable form.
-    #[serde(borrow)]
     pub transaction_name: &'b str, // not a Cow because it is a reference to a HashMap value
     /// Each transaction within a functional group also has a control number.
     /// Typically, trading partners use a number relative to the functional group in which they are contained.
     #[serde(borrow)]
     pub transaction_set_control_number: Cow<'a, str>,
-    /// Identifier of the implementation convention reference. Valid value is up to 35 standard characters. Optional.
+    /// Identifier of the implementation convention reference (ST03). Valid value is up to 35 standard characters. Optional.
+    /// When it is `None`, the ST segment is written without an ST03 element; `Some("")` writes an empty ST03,
+    /// i.e. a trailing element delimiter, for partners which require one.
     #[serde(borrow)]
     pub implementation_convention_reference: Option<Cow<'a, str>>,
     /// The [GenericSegment]s contained within this transaction.
     #[serde(borrow)]
     pub segments: VecDeque<GenericSegment<'a>>,
+    /// The elements of the SE segment which closed this transaction, after its ID, when parsing with
+    /// [ParseOptions::preserve_original_trailers](struct.ParseOptions.html#structfield.preserve_original_trailers).
+    /// As long as they still agree with the transaction, they are written out verbatim instead of a generated SE.
+    #[serde(borrow, default)]
+    pub original_trailer: Option<Vec<Cow<'a, str>>>,
 }
 
+/// The hash total (CTT02) holds at most 10 digits; larger sums are truncated on the left.
+const HASH_TOTAL_MODULUS: u64 = 10_000_000_000;
+
+/// The [transaction_name](struct.Transaction.html#structfield.transaction_name) of transactions whose code is not
+/// in the bundled schema names, unless [ParseOptions::unidentified_transaction_name] says otherwise.
+pub(crate) const UNIDENTIFIED_TRANSACTION_NAME: &str = "unidentified";
+
 // Load the potential transaction schema names from a csv
 // source: scraped from https://www.arcesb.com/edi/standards/x12/
+#[cfg(feature = "std")]
 lazy_static! {
-    static ref SCHEMAS: HashMap<String, String> = {
-        let mut map = HashMap::new();
-        let schemas_path = format!("{}/resources/schemas.csv", env!("CARGO_MANIFEST_DIR"));
-        let mut schemas_csv = ReaderBuilder::new()
-            .has_headers(false)
-            .from_path(schemas_path)
-            .expect("Failed to open schemas.csv. Does edi/resources/schemas.csv exist?");
-        for record in schemas_csv.records() {
-            let record = record.unwrap();
-            map.insert(record[0].to_string(), record[1].to_string());
+    static ref SCHEMAS: Result<HashMap<String, String>, EdiParseError> = load_names("schemas.csv");
+}
+
+/// Read a csv of codes and their names from the resources directory into a map from code to name.
+#[cfg(feature = "std")]
+pub(crate) fn load_names(file_name: &str) -> Result<HashMap<String, String>, EdiParseError> {
+    let path = format!("{}/resources/{}", env!("CARGO_MANIFEST_DIR"), file_name);
+    let mut names_csv = ReaderBuilder::new().has_headers(false).from_path(path)?;
+    let mut map = HashMap::new();
+    for record in names_csv.records() {
+        let record = record?;
+        match (record.get(0), record.get(1)) {
+            (Some(code), Some(name)) => {
+                map.insert(code.to_string(), name.to_string());
+            }
+            _ => {
+                return Err(EdiParseError::new(
+                    EdiErrorKind::SchemaLoad,
+                    &format!("{} must have a code and a name on every line", file_name),
+                    None,
+                ))
+            }
         }
-        map
-    };
+    }
+    Ok(map)
+}
+
+/// Look up the human-readable name of a transaction set code, e.g. "850" is a "Purchase Order".
+/// The bundled names cover the standard X12 transaction sets. Returns `None` for every code if the bundled
+/// schema file could not be read. Requires the `std` feature.
+#[cfg(feature = "std")]
+pub fn transaction_set_name(code: &str) -> Option<&'static str> {
+    SCHEMAS.as_ref().ok()?.get(code).map(|name| name.as_str())
+}
+
+/// The value of a numeric element as a hash total counts it: its digits, ignoring any decimal point or sign,
+/// truncated on the left to the digits a hash total holds.
+fn hash_total_value(segment: &GenericSegment, index: usize) -> Result<u64, EdiParseError> {
+    let (_, digits) = segment.numeric_element(index, true)?;
+    Ok(digits
+        .bytes()
+        .filter(u8::is_ascii_digit)
+        .fold(0, |value, digit| {
+            (value * 10 + u64::from(digit - b'0')) % HASH_TOTAL_MODULUS
+        }))
+}
+
+/// Copy the elements of an original trailer into owned strings, so that it no longer borrows from the input.
+pub(crate) fn owned_trailer(trailer: Vec<Cow<str>>) -> Vec<Cow<'static, str>> {
+    trailer
+        .into_iter()
+        .map(|element| Cow::Owned(element.into_owned()))
+        .collect()
+}
+
+/// Write a trailer (SE, GE, or IEA) holding `count` and `control_number`. The original trailer is written
+/// verbatim instead if it still holds the same count and control number, which may differ from them only in
+/// zero padding, so that a document which was not modified keeps the exact trailers it was sent with.
+pub(crate) fn write_trailer<W: fmt::Write>(
+    writer: &mut W,
+    segment_id: &str,
+    element_delimiter: char,
+    original_trailer: Option<&[Cow<str>]>,
+    count: usize,
+    control_number: &str,
+) -> fmt::Result {
+    writer.write_str(segment_id)?;
+    match original_trailer {
+        Some(original_trailer)
+            if original_trailer.len() >= 2
+                && same_number(&original_trailer[0], &count.to_string())
+                && same_number(&original_trailer[1], control_number) =>
+        {
+            for element in original_trailer.iter() {
+                writer.write_char(element_delimiter)?;
+                writer.write_str(element)?;
+            }
+            Ok(())
+        }
+        _ => write!(
+            writer,
+            "{element_delimiter}{}{element_delimiter}{}",
+            count, control_number
+        ),
+    }
+}
+
+/// The name of the transaction set `code` among the bundled names, or the fallback for unknown codes.
+#[cfg_attr(not(feature = "std"), allow(unused_variables))]
+fn bundled_transaction_name(code: &str) -> &'static str {
+    // If the schema file could not be read, the name degrades to "unidentified" rather than failing the parse.
+    #[cfg(feature = "std")]
+    return transaction_set_name(code).unwrap_or(UNIDENTIFIED_TRANSACTION_NAME);
+    // without `std` there is no schema file to look names up in
+    #[cfg(not(feature = "std"))]
+    return UNIDENTIFIED_TRANSACTION_NAME;
+}
+
+/// The serde representation of a [Transaction], whose name may not survive deserialization and is then looked up
+/// again from its code.
+#[derive(Deserialize)]
+#[serde(rename = "Transaction")]
+struct StoredTransaction<'a, 'b> {
+    #[serde(borrow)]
+    transaction_code: Cow<'a, str>,
+    #[serde(borrow, deserialize_with = "deserialize_transaction_name")]
+    transaction_name: Option<&'b str>,
+    #[serde(borrow)]
+    transaction_set_control_number: Cow<'a, str>,
+    #[serde(borrow)]
+    implementation_convention_reference: Option<Cow<'a, str>>,
+    #[serde(borrow)]
+    segments: VecDeque<GenericSegment<'a>>,
+    #[serde(borrow, default)]
+    original_trailer: Option<Vec<Cow<'a, str>>>,
+}
+
+impl<'de: 'a + 'b, 'a, 'b> Deserialize<'de> for Transaction<'a, 'b> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let stored = StoredTransaction::deserialize(deserializer)?;
+        Ok(Transaction {
+            transaction_name: stored
+                .transaction_name
+                .unwrap_or_else(|| bundled_transaction_name(&stored.transaction_code)),
+            transaction_code: stored.transaction_code,
+            transaction_set_control_number: stored.transaction_set_control_number,
+            implementation_convention_reference: stored.implementation_convention_reference,
+            segments: stored.segments,
+            original_trailer: stored.original_trailer,
+        })
+    }
+}
+
+/// Deserialize a [Transaction::transaction_name]. Formats which can't lend strings from their input, such as JSON
+/// with escape sequences in a string, hand over owned strings instead; those are resolved to the bundled names
+/// (and the fallback for unknown codes), which live as long as the program. Any other owned name, e.g. a custom
+/// [ParseOptions::unidentified_transaction_name], is `None`, and the transaction gets the bundled name of its code.
+fn deserialize_transaction_name<'de: 'b, 'b, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<&'b str>, D::Error> {
+    struct TransactionNameVisitor;
+
+    impl<'de> Visitor<'de> for TransactionNameVisitor {
+        type Value = Option<&'de str>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a transaction set name")
+        }
+
+        fn visit_borrowed_str<E: de::Error>(self, name: &'de str) -> Result<Option<&'de str>, E> {
+            Ok(Some(name))
+        }
+
+        fn visit_str<E: de::Error>(self, name: &str) -> Result<Option<&'de str>, E> {
+            if name == UNIDENTIFIED_TRANSACTION_NAME {
+                return Ok(Some(UNIDENTIFIED_TRANSACTION_NAME));
+            }
+            #[cfg(feature = "std")]
+            if let Some(bundled_name) = SCHEMAS
+                .as_ref()
+                .ok()
+                .and_then(|schemas| schemas.values().find(|bundled_name| *bundled_name == name))
+            {
+                return Ok(Some(bundled_name));
+            }
+            Ok(None)
+        }
+    }
+
+    deserializer.deserialize_str(TransactionNameVisitor)
 }
 
 impl<'a, 'b> Transaction<'a, 'b> {
+    /// Whether the [transaction_code](#structfield.transaction_code) is one of the bundled transaction set codes,
+    /// i.e. whether [transaction_name](#structfield.transaction_name) is a real name rather than the fallback for
+    /// unknown codes. Always `false` without the `std` feature, since the names are not bundled then.
+    pub fn is_recognized(&self) -> bool {
+        #[cfg(feature = "std")]
+        return transaction_set_name(&self.transaction_code).is_some();
+        #[cfg(not(feature = "std"))]
+        return false;
+    }
+
     /// Given [SegmentTokens] (where the first token is "ST"), construct a [Transaction].
     pub(crate) fn parse_from_tokens(
         input: SegmentTokens<'a>,
@@ -56,11 +252,13 @@ impl<'a, 'b> Transaction<'a, 'b> {
         // I always inject invariants wherever I can to ensure debugging is quick and painless,
         // and to check my assumptions.
         edi_assert!(
+            EdiErrorKind::InvalidArgument,
             elements[0] == "ST",
             "attempted to parse ST from non-ST segment",
             input
         );
         edi_assert!(
+            EdiErrorKind::MissingElements,
             elements.len() >= 3,
             "ST segment does not contain enough elements. At least 3 required",
             input
@@ -73,11 +271,7 @@ impl<'a, 'b> Transaction<'a, 'b> {
         } else {
             None
         };
-        let transaction_name = if let Some(name) = SCHEMAS.get(&transaction_code.to_string()) {
-            name
-        } else {
-            "unidentified"
-        };
+        let transaction_name = bundled_transaction_name(&transaction_code);
 
         Ok(Transaction {
             transaction_code,
@@ -85,39 +279,254 @@ impl<'a, 'b> Transaction<'a, 'b> {
             transaction_set_control_number,
             implementation_convention_reference,
             segments: VecDeque::new(),
+            original_trailer: None,
         })
     }
 
-    /// Enqueue a [GenericSegment](struct.GenericSegment.html) into the transaction.
+    /// Copy every element into an owned string, so that the transaction no longer borrows from the input.
+    pub fn into_owned(self) -> Transaction<'static, 'b> {
+        Transaction {
+            transaction_code: Cow::Owned(self.transaction_code.into_owned()),
+            transaction_name: self.transaction_name,
+            transaction_set_control_number: Cow::Owned(
+                self.transaction_set_control_number.into_owned(),
+            ),
+            implementation_convention_reference: self
+                .implementation_convention_reference
+                .map(|reference| Cow::Owned(reference.into_owned())),
+            segments: self
+                .segments
+                .into_iter()
+                .map(GenericSegment::into_owned)
+                .collect(),
+            original_trailer: self.original_trailer.map(owned_trailer),
+        }
+    }
+
+    /// Enqueue a [GenericSegment](struct.GenericSegment.html) into the transaction, along with the text it was
+    /// parsed from if it was kept.
     pub(crate) fn add_generic_segment(
         &mut self,
         tokens: SegmentTokens<'a>,
+        raw: Option<&'a str>,
+    ) -> Result<(), EdiParseError> {
+        let mut segment = GenericSegment::parse_from_tokens(tokens)?;
+        segment.raw = raw.map(Cow::from);
+        self.segments.push_back(segment);
+        Ok(())
+    }
+
+    /// Append a [GenericSegment] to the end of this transaction. Useful for enriching a parsed document
+    /// before re-serializing it. Note that [to_x12_string](#method.to_x12_string) recomputes the SE segment count,
+    /// but if you are keeping track of trailer counts elsewhere, updating them is your responsibility.
+    pub fn push_segment(&mut self, segment: GenericSegment<'a>) {
+        self.segments.push_back(segment);
+    }
+
+    /// The number of segments in this transaction as counted by the SE segment (SE01). This is the number of
+    /// [GenericSegment]s plus two, because the count includes the ST and SE segments themselves.
+    pub fn segment_count_including_envelope(&self) -> usize {
+        self.segments.len() + 2
+    }
+
+    /// The [GenericSegment] at position `index` (starting from zero) within this transaction, not counting the
+    /// ST segment, or `None` if the transaction contains fewer segments.
+    pub fn segment(&self, index: usize) -> Option<&GenericSegment<'a>> {
+        self.segments.get(index)
+    }
+
+    /// The first segment with the given abbreviation whose element at the X12 position `element_index` (see
+    /// [GenericSegment::element](struct.GenericSegment.html#method.element)) is `value`, e.g.
+    /// `find_segment_where("REF", 1, "IA")` for the REF segment qualified as an internal vendor number.
+    pub fn find_segment_where(
+        &self,
+        abbreviation: &str,
+        element_index: usize,
+        value: &str,
+    ) -> Option<&GenericSegment<'a>> {
+        self.segments.iter().find(|segment| {
+            segment.segment_abbreviation == abbreviation
+                && segment.element(element_index) == Some(value)
+        })
+    }
+
+    /// Sum the element at the X12 position `element_index` (see
+    /// [GenericSegment::element_as_decimal](struct.GenericSegment.html#method.element_as_decimal)) of every segment
+    /// with the given abbreviation, e.g. `sum_element("IT1", 2)` for the total quantity invoiced by an 810. The sum
+    /// is zero if there are no such segments. Fails if any of them is missing the element or its value is not a
+    /// number. Requires the `rust_decimal` feature.
+    #[cfg(feature = "rust_decimal")]
+    pub fn sum_element(
+        &self,
+        abbreviation: &str,
+        element_index: usize,
+    ) -> Result<Decimal, EdiParseError> {
+        let mut sum = Decimal::ZERO;
+        for segment in self
+            .segments
+            .iter()
+            .filter(|segment| segment.segment_abbreviation == abbreviation)
+        {
+            let value = segment.element_as_decimal(element_index)?;
+            sum = match sum.checked_add(value) {
+                Some(sum) => sum,
+                None => {
+                    return Err(EdiParseError::new(
+                        EdiErrorKind::InvalidElement,
+                        &format!(
+                            "unable to sum {}{:02}: sum out of range",
+                            abbreviation, element_index
+                        ),
+                        None,
+                    ))
+                }
+            };
+        }
+        Ok(sum)
+    }
+
+    /// Count how many times each segment abbreviation appears in this transaction, e.g. to check that an 810
+    /// has exactly one BIG segment. The ST and SE segments are not counted. Requires the `std` feature.
+    #[cfg(feature = "std")]
+    pub fn segment_histogram(&self) -> HashMap<&str, usize> {
+        let mut histogram = HashMap::new();
+        for segment in self.segments.iter() {
+            *histogram
+                .entry(segment.segment_abbreviation.as_ref())
+                .or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// Partition the segments of this transaction into groups which each begin with a `trigger` segment, e.g. one
+    /// group per `IT1` line item of an 810. The first group always holds the segments before the first trigger
+    /// segment (the header), and is empty if the transaction begins with one.
+    pub fn group_by_trigger(&self, trigger: &str) -> Vec<Vec<&GenericSegment<'a>>> {
+        let mut groups = vec![Vec::new()];
+        for segment in self.segments.iter() {
+            if segment.segment_abbreviation == trigger {
+                groups.push(Vec::new());
+            }
+            if let Some(group) = groups.last_mut() {
+                group.push(segment);
+            }
+        }
+        groups
+    }
+
+    /// Check the number of line items (CTT01) of the transaction totals segment against the number of `line_trigger`
+    /// segments which begin a line item, e.g. "IT1" for an 810 invoice or "PO1" for an 850 purchase order. Fails
+    /// if there is no CTT segment, CTT01 is not a number, or the counts differ.
+    pub fn validate_ctt(&self, line_trigger: &str) -> Result<(), EdiParseError> {
+        let ctt = self.ctt_segment()?;
+        let line_items = self
+            .segments
+            .iter()
+            .filter(|segment| segment.segment_abbreviation == line_trigger)
+            .count();
+        let reported_line_items = ctt.element_as_integer(1)?;
+        edi_assert!(
+            EdiErrorKind::CountMismatch,
+            reported_line_items == line_items as i64,
+            format!(
+                "CTT validation failed: incorrect number of {} line items",
+                line_trigger
+            )
+            .as_str(),
+            line_items,
+            reported_line_items
+        );
+        Ok(())
+    }
+
+    /// Check the hash total (CTT02) of the transaction totals segment against the sum of the element at the X12
+    /// position `element_index` (see [GenericSegment::element](struct.GenericSegment.html#method.element)) of every
+    /// `line_trigger` segment, e.g. `validate_ctt_hash_total("IT1", 2)` for the invoiced quantities of an 810. As
+    /// the standard defines it, values are summed without regard to decimal points or signs, and only the lowest 10
+    /// digits of the sum are kept.
+    pub fn validate_ctt_hash_total(
+        &self,
+        line_trigger: &str,
+        element_index: usize,
     ) -> Result<(), EdiParseError> {
-        self.segments
-            .push_back(GenericSegment::parse_from_tokens(tokens)?);
+        let ctt = self.ctt_segment()?;
+        let mut hash_total = 0;
+        for segment in self
+            .segments
+            .iter()
+            .filter(|segment| segment.segment_abbreviation == line_trigger)
+        {
+            hash_total =
+                (hash_total + hash_total_value(segment, element_index)?) % HASH_TOTAL_MODULUS;
+        }
+        let reported_hash_total = hash_total_value(ctt, 2)?;
+        edi_assert!(
+            EdiErrorKind::CountMismatch,
+            reported_hash_total == hash_total,
+            format!(
+                "CTT validation failed: incorrect hash total of {}{:02}",
+                line_trigger, element_index
+            )
+            .as_str(),
+            hash_total,
+            reported_hash_total
+        );
         Ok(())
     }
 
+    /// The transaction totals (CTT) segment of this transaction.
+    fn ctt_segment(&self) -> Result<&GenericSegment<'a>, EdiParseError> {
+        match self
+            .segments
+            .iter()
+            .find(|segment| segment.segment_abbreviation == "CTT")
+        {
+            Some(ctt) => Ok(ctt),
+            None => Err(EdiParseError::new(
+                EdiErrorKind::MissingSegment,
+                "CTT validation failed: transaction does not contain a CTT segment",
+                None,
+            )),
+        }
+    }
+
+    /// Arrange the segments of this transaction into the tree of hierarchical level (HL) loops described by their
+    /// HL segments, as used by e.g. 856 ship notices and 837 claims. The root node stands for the transaction and
+    /// holds the segments before the first HL segment; its children are the loops without a parent (HL02).
+    /// Fails if an HL segment has no ID, reuses one, or refers to a parent which does not precede it.
+    pub fn build_hl_tree(&self) -> Result<HlNode<'_, 'a>, EdiParseError> {
+        HlNode::build(self.segments.iter())
+    }
+
     /// Validate this transaction with an SE segment.
     pub(crate) fn validate_transaction(
         &self,
         tokens: SegmentTokens<'a>,
+        options: &ParseOptions,
     ) -> Result<(), EdiParseError> {
         edi_assert!(
+            EdiErrorKind::InvalidArgument,
             tokens[0] == "SE",
             "attempted to validate transaction with non-SE segment",
             tokens
         );
-        // we have to add two here because transaction counts include ST and SE
         edi_assert!(
-            str::parse::<usize>(tokens[1]).unwrap() == self.segments.len() + 2,
+            EdiErrorKind::MissingElements,
+            tokens.len() >= 3,
+            "SE segment does not contain enough elements. At least 3 required",
+            tokens
+        );
+        edi_assert!(
+            EdiErrorKind::CountMismatch,
+            parse_count(tokens[1], &tokens)? == self.segment_count_including_envelope(),
             "transaction validation failed: incorrect number of segments",
             tokens[1],
-            self.segments.len() + 2,
+            self.segment_count_including_envelope(),
             tokens
         );
         edi_assert!(
-            tokens[2] == self.transaction_set_control_number,
+            EdiErrorKind::ControlNumberMismatch,
+            options.control_numbers_match(&self.transaction_set_control_number, tokens[2]),
             "transaction validation failed: incorrect transaction ID",
             tokens[2],
             self.transaction_set_control_number,
@@ -128,82 +537,152 @@ impl<'a, 'b> Transaction<'a, 'b> {
 
     /// Converts this [Transaction] into an ANSI x12 string to be used in an EDI document.
     pub fn to_x12_string(&self, segment_delimiter: char, element_delimiter: char) -> String {
-        let mut header = "ST".to_string();
-        header.push(element_delimiter);
-        header.push_str(&self.transaction_code);
-        header.push(element_delimiter);
-        header.push_str(&self.transaction_set_control_number);
-        header.push(element_delimiter);
-        header.push_str(
-            &self
-                .implementation_convention_reference
-                .clone()
-                .unwrap_or(Cow::Borrowed("")),
+        let mut buffer = String::new();
+        self.write_x12(
+            &mut buffer,
+            segment_delimiter.encode_utf8(&mut [0; 4]),
+            element_delimiter,
+            None,
+        )
+        .expect("writing to a String cannot fail");
+        buffer
+    }
+
+    /// Like [to_x12_string](#method.to_x12_string), but returns an error instead of writing an ST segment without
+    /// its transaction set code or control number, or a segment without an ID.
+    pub fn try_to_x12_string(
+        &self,
+        segment_delimiter: char,
+        element_delimiter: char,
+    ) -> Result<String, EdiParseError> {
+        self.check_required_elements()?;
+        Ok(self.to_x12_string(segment_delimiter, element_delimiter))
+    }
+
+    /// Check that the elements the standard requires are not empty, so that serializing does not produce
+    /// segments a receiver will reject.
+    pub(crate) fn check_required_elements(&self) -> Result<(), EdiParseError> {
+        edi_assert!(
+            EdiErrorKind::MissingElements,
+            !self.transaction_code.is_empty(),
+            "ST01 (transaction set identifier code) is required but empty"
+        );
+        edi_assert!(
+            EdiErrorKind::MissingElements,
+            !self.transaction_set_control_number.is_empty(),
+            "ST02 (transaction set control number) is required but empty"
         );
+        edi_assert!(
+            EdiErrorKind::MissingElements,
+            self.segments
+                .iter()
+                .all(|segment| !segment.segment_abbreviation.is_empty()),
+            format!(
+                "a segment in transaction {} has an empty segment ID",
+                self.transaction_set_control_number
+            )
+            .as_str()
+        );
+        Ok(())
+    }
 
-        let mut final_string = self.segments.iter().fold(header, |mut acc, segment| {
-            acc.push(segment_delimiter);
-            acc.push_str(&segment.to_x12_string(element_delimiter));
-            acc
-        });
+    /// Writes this transaction to `writer` segment by segment, formatted like [to_x12_string](#method.to_x12_string).
+    pub(crate) fn write_x12<W: fmt::Write>(
+        &self,
+        writer: &mut W,
+        segment_separator: &str,
+        element_delimiter: char,
+        escape: Option<&Delimiters>,
+    ) -> fmt::Result {
+        write!(
+            writer,
+            "ST{element_delimiter}{}{element_delimiter}{}",
+            self.transaction_code, self.transaction_set_control_number
+        )?;
+        if let Some(implementation_convention_reference) = &self.implementation_convention_reference
+        {
+            writer.write_char(element_delimiter)?;
+            writer.write_str(implementation_convention_reference)?;
+        }
 
-        let mut closer = "SE".to_string();
-        closer.push(element_delimiter);
-        closer.push_str(&(self.segments.len() + 2).to_string()); // +2 because the count includes the ST and SE segments
-        closer.push(element_delimiter);
-        closer.push_str(&self.transaction_set_control_number.clone());
+        for segment in self.segments.iter() {
+            writer.write_str(segment_separator)?;
+            segment.write_x12(writer, element_delimiter, escape)?;
+        }
 
-        final_string.push(segment_delimiter);
-        final_string.push_str(&closer);
+        writer.write_str(segment_separator)?;
+        write_trailer(
+            writer,
+            "SE",
+            element_delimiter,
+            self.original_trailer.as_deref(),
+            self.segment_count_including_envelope(),
+            &self.transaction_set_control_number,
+        )
+    }
+}
 
-        final_string
+impl fmt::Display for Transaction<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let count = self.segments.len();
+        write!(
+            f,
+            "Transaction {} ({}) #{} with {} segment{}",
+            self.transaction_code,
+            self.transaction_name,
+            self.transaction_set_control_number,
+            count,
+            if count == 1 { "" } else { "s" }
+        )
     }
 }
 
 #[test]
 fn transaction_to_string() {
     use std::iter::FromIterator;
-    let segments = VecDeque::from_iter(
-        vec![
-            GenericSegment {
-                segment_abbreviation: Cow::from("BGN"),
-                elements: vec!["20", "TEST_ID", "200615", "0000"]
-                    .iter()
-                    .map(|x| Cow::from(*x))
-                    .collect::<VecDeque<Cow<str>>>(),
-            },
-            GenericSegment {
-                segment_abbreviation: Cow::from("BGN"),
-                elements: vec!["15", "OTHER_TEST_ID", "", "", "END"]
-                    .iter()
-                    .map(|x| Cow::from(*x))
-                    .collect::<VecDeque<Cow<str>>>(),
-            },
-        ]
-        .into_iter(),
-    );
+    let segments = VecDeque::from_iter(vec![
+        GenericSegment {
+            segment_abbreviation: Cow::from("BGN"),
+            elements: ["20", "TEST_ID", "200615", "0000"]
+                .iter()
+                .map(|x| Cow::from(*x))
+                .collect::<VecDeque<Cow<str>>>(),
+            raw: None,
+        },
+        GenericSegment {
+            segment_abbreviation: Cow::from("BGN"),
+            elements: ["15", "OTHER_TEST_ID", "", "", "END"]
+                .iter()
+                .map(|x| Cow::from(*x))
+                .collect::<VecDeque<Cow<str>>>(),
+            raw: None,
+        },
+    ]);
     let transaction = Transaction {
         transaction_code: Cow::from("140"),
         transaction_name: "",
         transaction_set_control_number: Cow::from("100000001"),
         implementation_convention_reference: None,
-        segments: segments,
+        segments,
+        original_trailer: None,
     };
 
     assert_eq!(
         transaction.to_x12_string('~', '*'),
-        "ST*140*100000001*~BGN*20*TEST_ID*200615*0000~BGN*15*OTHER_TEST_ID***END~SE*4*100000001"
+        "ST*140*100000001~BGN*20*TEST_ID*200615*0000~BGN*15*OTHER_TEST_ID***END~SE*4*100000001"
     );
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn construct_transaction() {
     let expected_result = Transaction {
         transaction_code: Cow::from("850"),
-        transaction_name: SCHEMAS.get(&"850".to_string()).unwrap(), // should be "Purchase Order"
+        transaction_name: SCHEMAS.as_ref().unwrap().get(&"850".to_string()).unwrap(), // should be "Purchase Order"
         transaction_set_control_number: Cow::from("000000001"),
         implementation_convention_reference: None,
         segments: VecDeque::new(),
+        original_trailer: None,
     };
     let test_input = vec!["ST", "850", "000000001"];
 
@@ -213,15 +692,282 @@ fn construct_transaction() {
     );
 }
 
+#[cfg(feature = "std")]
 #[test]
 fn spot_check_schemas() {
-    assert_eq!(SCHEMAS.get(&"850".to_string()).unwrap(), "Purchase Order");
     assert_eq!(
-        SCHEMAS.get(&"100".to_string()).unwrap(),
+        SCHEMAS.as_ref().unwrap().get(&"850".to_string()).unwrap(),
+        "Purchase Order"
+    );
+    assert_eq!(
+        SCHEMAS.as_ref().unwrap().get(&"100".to_string()).unwrap(),
         "Insurance Plan Description"
     );
     assert_eq!(
-        SCHEMAS.get(&"999".to_string()).unwrap(),
+        SCHEMAS.as_ref().unwrap().get(&"999".to_string()).unwrap(),
         "Implementation Acknowledgment"
     );
 }
+
+#[test]
+fn push_segment_onto_transaction() {
+    let mut transaction = Transaction::parse_from_tokens(vec!["ST", "850", "000000001"]).unwrap();
+    transaction.push_segment(GenericSegment {
+        segment_abbreviation: Cow::from("REF"),
+        elements: VecDeque::from(vec![Cow::from("VR"), Cow::Owned(String::from("54321"))]),
+        raw: None,
+    });
+
+    assert_eq!(transaction.segments.len(), 1);
+    assert_eq!(transaction.segment_count_including_envelope(), 3);
+    assert_eq!(
+        transaction.to_x12_string('~', '*'),
+        "ST*850*000000001~REF*VR*54321~SE*3*000000001"
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn look_up_transaction_set_name() {
+    assert_eq!(transaction_set_name("850"), Some("Purchase Order"));
+    assert_eq!(transaction_set_name("not a code"), None);
+
+    let transaction = Transaction::parse_from_tokens(vec!["ST", "850", "000000001"]).unwrap();
+    assert!(transaction.is_recognized());
+    let transaction = Transaction::parse_from_tokens(vec!["ST", "001", "000000001"]).unwrap();
+    assert_eq!(transaction.transaction_name, "unidentified");
+    assert!(!transaction.is_recognized());
+}
+
+#[cfg(not(feature = "std"))]
+#[test]
+fn transactions_are_unidentified_without_std() {
+    let transaction = Transaction::parse_from_tokens(vec!["ST", "850", "000000001"]).unwrap();
+    assert_eq!(transaction.transaction_name, "unidentified");
+    assert!(!transaction.is_recognized());
+}
+
+#[test]
+fn transaction_to_string_with_implementation_convention_reference() {
+    let mut transaction =
+        Transaction::parse_from_tokens(vec!["ST", "834", "0001", "005010X220A1"]).unwrap();
+    assert_eq!(
+        transaction.to_x12_string('~', '*'),
+        "ST*834*0001*005010X220A1~SE*2*0001"
+    );
+
+    // an empty reference keeps the trailing delimiter, as it was received
+    let transaction_with_empty_reference =
+        Transaction::parse_from_tokens(vec!["ST", "834", "0001", ""]).unwrap();
+    assert_eq!(
+        transaction_with_empty_reference.to_x12_string('~', '*'),
+        "ST*834*0001*~SE*2*0001"
+    );
+
+    transaction.implementation_convention_reference = None;
+    assert_eq!(transaction.to_x12_string('~', '*'), "ST*834*0001~SE*2*0001");
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn count_segment_types() {
+    let mut transaction = Transaction::parse_from_tokens(vec!["ST", "810", "0001"]).unwrap();
+    assert!(transaction.segment_histogram().is_empty());
+
+    transaction.push_segment(GenericSegment::new("BIG", vec!["20101204", "217224"]));
+    transaction.push_segment(GenericSegment::new("IT1", vec!["1", "4", "EA"]));
+    transaction.push_segment(GenericSegment::new("IT1", vec!["2", "6", "EA"]));
+    let histogram = transaction.segment_histogram();
+    assert_eq!(histogram.len(), 2);
+    assert_eq!(histogram["BIG"], 1);
+    assert_eq!(histogram["IT1"], 2);
+    assert_eq!(histogram.get("ST"), None);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn fail_to_load_missing_names() {
+    use std::error::Error;
+    let error = load_names("does_not_exist.csv").err().unwrap();
+    assert!(error.reason().starts_with("failed to read csv"));
+    assert!(error.source().is_some());
+}
+
+#[test]
+fn group_segments_by_trigger() {
+    let mut transaction = Transaction::parse_from_tokens(vec!["ST", "810", "0001"]).unwrap();
+    transaction.push_segment(GenericSegment::new("BIG", vec!["20101204", "217224"]));
+    transaction.push_segment(GenericSegment::new("IT1", vec!["1", "4", "EA"]));
+    transaction.push_segment(GenericSegment::new(
+        "PID",
+        vec!["F", "", "", "", "BLUE WIDGET"],
+    ));
+    transaction.push_segment(GenericSegment::new("IT1", vec!["2", "6", "EA"]));
+    transaction.push_segment(GenericSegment::new("TDS", vec!["6000"]));
+
+    let groups = transaction.group_by_trigger("IT1");
+    let abbreviations: Vec<Vec<&str>> = groups
+        .iter()
+        .map(|group| {
+            group
+                .iter()
+                .map(|segment| segment.segment_abbreviation.as_ref())
+                .collect()
+        })
+        .collect();
+    assert_eq!(
+        abbreviations,
+        vec![vec!["BIG"], vec!["IT1", "PID"], vec!["IT1", "TDS"]]
+    );
+
+    let groups = transaction.group_by_trigger("BIG");
+    assert!(groups[0].is_empty());
+    assert_eq!(groups[1].len(), 5);
+
+    assert_eq!(transaction.group_by_trigger("LIN").len(), 1);
+}
+
+#[test]
+fn find_segments_by_qualifier() {
+    let mut transaction = Transaction::parse_from_tokens(vec!["ST", "810", "1004"]).unwrap();
+    transaction.push_segment(GenericSegment::new("REF", vec!["DP", "099"]));
+    transaction.push_segment(GenericSegment::new("REF", vec!["IA", "99999"]));
+    transaction.push_segment(GenericSegment::new("N1", vec!["ST", "", "92", "123"]));
+
+    let segment = transaction.find_segment_where("REF", 1, "IA").unwrap();
+    assert_eq!(segment.element(2), Some("99999"));
+    assert_eq!(
+        transaction
+            .find_segment_where("REF", 1, "DP")
+            .unwrap()
+            .element(2),
+        Some("099")
+    );
+    assert_eq!(
+        transaction
+            .find_segment_where("N1", 2, "")
+            .unwrap()
+            .element(4),
+        Some("123")
+    );
+    assert!(transaction.find_segment_where("REF", 1, "ST").is_none());
+    assert!(transaction.find_segment_where("REF", 3, "").is_none());
+}
+
+#[cfg(test)]
+fn sample_invoice() -> Transaction<'static, 'static> {
+    let mut transaction = Transaction::parse_from_tokens(vec!["ST", "810", "1004"]).unwrap();
+    let segments = "BIG*20101204*217224*20101204*P792940
+IT1*1*4*EA*8.60**UP*999999330023
+IT1*2*2*EA*15.00**UP*999999330115
+IT1*3*2*EA*7.30**UP*999999330146
+IT1*4*4*EA*17.20**UP*999999330184
+IT1*5*8*EA*4.30**UP*999999330320
+IT1*6*4*EA*4.30**UP*999999330337
+IT1*7*6*EA*1.50**UP*999999330634
+IT1*8*6*EA*1.50**UP*999999330641
+TDS*21740";
+    for segment in segments.lines() {
+        transaction
+            .add_generic_segment(segment.split('*').collect(), None)
+            .unwrap();
+    }
+    transaction
+}
+
+#[test]
+fn validate_segment_count_of_sample_invoice() {
+    let mut transaction = sample_invoice();
+    transaction.push_segment(GenericSegment::new("REF", vec!["DP", "099"]));
+    transaction.push_segment(GenericSegment::new("REF", vec!["IA", "99999"]));
+    transaction.push_segment(GenericSegment::new("N1", vec!["ST", "", "92", "123"]));
+    transaction.push_segment(GenericSegment::new(
+        "ITD",
+        vec!["01", "3", "", "", "0", "", "60"],
+    ));
+    transaction.push_segment(GenericSegment::new(
+        "CAD",
+        vec!["", "", "", "", "GTCT", "", "BM", "99999"],
+    ));
+    transaction.push_segment(GenericSegment::new("CTT", vec!["8"]));
+    assert_eq!(transaction.segments.len(), 16);
+    assert_eq!(transaction.segment_count_including_envelope(), 18);
+
+    let options = ParseOptions::default();
+    assert!(transaction
+        .validate_transaction(vec!["SE", "18", "1004"], &options)
+        .is_ok());
+    for miscount in ["16", "17", "19"].iter() {
+        let error = transaction
+            .validate_transaction(vec!["SE", miscount, "1004"], &options)
+            .unwrap_err();
+        assert_eq!(error.kind(), EdiErrorKind::CountMismatch);
+    }
+    assert!(transaction
+        .to_x12_string('~', '*')
+        .ends_with("~CTT*8~SE*18*1004"));
+}
+
+#[test]
+fn validate_ctt_of_sample_invoice() {
+    let mut transaction = sample_invoice();
+    transaction.push_segment(GenericSegment::new("CTT", vec!["8", "36"]));
+    assert!(transaction.validate_ctt("IT1").is_ok());
+    assert!(transaction.validate_ctt_hash_total("IT1", 2).is_ok());
+
+    let error = transaction.validate_ctt("PO1").unwrap_err();
+    assert_eq!(error.kind(), EdiErrorKind::CountMismatch);
+    assert_eq!(
+        error.reason(),
+        "CTT validation failed: incorrect number of PO1 line items  --  expected: 0  received: 8"
+    );
+    // unit prices are summed without their decimal points: 860 + 1500 + 730 + ...
+    assert_eq!(
+        transaction
+            .validate_ctt_hash_total("IT1", 4)
+            .unwrap_err()
+            .reason(),
+        "CTT validation failed: incorrect hash total of IT104  --  expected: 5970  received: 36"
+    );
+    assert_eq!(
+        transaction
+            .validate_ctt_hash_total("IT1", 3)
+            .unwrap_err()
+            .kind(),
+        EdiErrorKind::InvalidElement
+    );
+
+    transaction.segments.pop_back();
+    assert_eq!(
+        transaction.validate_ctt("IT1").unwrap_err().kind(),
+        EdiErrorKind::MissingSegment
+    );
+    transaction.push_segment(GenericSegment::new("CTT", vec!["8"]));
+    assert!(transaction.validate_ctt("IT1").is_ok());
+    assert!(transaction.validate_ctt_hash_total("IT1", 2).is_err());
+}
+
+#[cfg(feature = "rust_decimal")]
+#[test]
+fn sum_elements_of_sample_invoice() {
+    let transaction = sample_invoice();
+    assert_eq!(
+        transaction.sum_element("IT1", 2).unwrap(),
+        Decimal::new(36, 0)
+    );
+    assert_eq!(
+        transaction.sum_element("IT1", 4).unwrap(),
+        Decimal::new(5970, 2)
+    );
+    assert_eq!(transaction.sum_element("PO1", 2).unwrap(), Decimal::ZERO);
+
+    let error = transaction.sum_element("IT1", 3).unwrap_err();
+    assert_eq!(error.kind(), EdiErrorKind::InvalidElement);
+    assert_eq!(
+        error.reason(),
+        "unable to read IT103 as a number: element is not numeric"
+    );
+    assert!(transaction.sum_element("TDS", 2).is_err());
+    // an empty element is not a number either
+    assert!(transaction.sum_element("IT1", 5).is_err());
+}
@@ -3,7 +3,10 @@
 // matches up with their opener segments and the amount of segments received.
 // Note that SE segments count themselves as part of the count for whatever reason, effectively adding two
 // to the count.
-use edi::{loose_parse, parse};
+use edi::{
+    loose_parse, loose_parse_with_report, parse, parse_collecting, parse_with_options,
+    parse_with_stats, parse_with_validation, EdiErrorKind, ParseOptions, ParseStats,
+};
 #[test]
 fn parse_empty_document() {
     assert!(parse("").is_err());
@@ -26,6 +29,22 @@ GE*1*1~";
     assert!(parse(input).is_err());
 }
 
+#[test]
+fn compare_parse_errors() {
+    let input = "GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+BEG*00*SA*A99999-01**19970214~
+SE*3*000000001~
+GE*1*1~";
+    let error = parse(input).unwrap_err();
+    assert_eq!(
+        error,
+        parse_with_options(input, &ParseOptions::default()).unwrap_err()
+    );
+    assert!(error.reason().starts_with("malformed ISA header"));
+    assert_ne!(error, parse("").unwrap_err());
+}
+
 #[test]
 fn incorrect_number_of_segments() {
     let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
@@ -175,3 +194,740 @@ GE*1*1~
 IEA*1*000000001~"; // (SE is 11 when it should be 10)
     loose_parse(input).unwrap();
 }
+
+#[test]
+fn missing_interchange_loose_parse() {
+    let input = "GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+BEG*00*SA*A99999-01**19970214~
+SE*3*000000001~
+GE*1*1~";
+    let document = loose_parse(input).unwrap();
+    let interchange = &document.interchanges[0];
+    assert_eq!(interchange.interchange_control_number, "");
+    assert_eq!(
+        interchange.functional_groups[0].application_sender_code,
+        "SENDERGS"
+    );
+    assert_eq!(
+        document.segments().next().unwrap().element(5),
+        Some("19970214")
+    );
+    assert_eq!(document.element_delimiter, '*');
+    assert_eq!(document.segment_delimiter, '~');
+
+    // a lone transaction, separated by line breaks
+    let document = loose_parse("ST|850|0001\nBEG|00|SA|A99999-01\nSE|3|0001").unwrap();
+    let functional_group = &document.interchanges[0].functional_groups[0];
+    assert_eq!(functional_group.group_control_number, "");
+    assert_eq!(functional_group.transactions[0].transaction_code, "850");
+    assert_eq!(document.segments().count(), 1);
+
+    // a '~' in the data of a fragment separated by line breaks does not make it the segment delimiter
+    let document =
+        loose_parse("ST*850*0001\nBEG*00*SA*A99999-01\nNTE*GEN*SHIP ~ 2 PALLETS\nSE*4*0001")
+            .unwrap();
+    assert_eq!(document.segment_delimiter, '\n');
+    assert_eq!(
+        document.segments().nth(1).unwrap().element(2),
+        Some("SHIP ~ 2 PALLETS")
+    );
+
+    assert!(parse_with_options(input, &ParseOptions::default()).is_err());
+    assert!(loose_parse(&input.replace("GS*", "BEG*")).is_err());
+}
+
+#[test]
+fn reserializing_repairs_closing_segments() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+BEG*****~
+SE*7*000000002~
+GE*3*9~
+IEA*0*000000009~";
+    assert!(parse(input).is_err());
+
+    let repaired = loose_parse(input).unwrap().to_x12_string();
+    assert!(repaired.ends_with("~SE*3*000000001~GE*1*1~IEA*1*000000001"));
+    assert_eq!(parse(&repaired).unwrap(), loose_parse(input).unwrap());
+
+    // kept trailers which still match their envelopes, only differently padded, are dropped by the repair
+    let input = input
+        .replace("SE*7*000000002", "SE*3*1")
+        .replace("GE*3*9", "GE*1*001")
+        .replace("IEA*0*000000009", "IEA*1*1");
+    let options = ParseOptions {
+        preserve_original_trailers: true,
+        ..ParseOptions::loose()
+    };
+    let mut document = parse_with_options(&input, &options).unwrap();
+    assert!(parse(&document.to_x12_string()).is_err());
+    document.repair_control_counts();
+    assert!(document
+        .transactions()
+        .all(|x| x.original_trailer.is_none()));
+    let repaired = document.to_x12_string();
+    assert!(repaired.ends_with("~SE*3*000000001~GE*1*1~IEA*1*000000001"));
+    assert!(parse(&repaired).is_ok());
+}
+
+// Collecting parses report every validation problem instead of stopping at the first.
+#[test]
+fn collect_all_validation_errors() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+BEG*****~
+SE*4*000000001~
+GE*1*2~
+IEA*1*000000002~"; // SE should be 3, GE should close group 1 and IEA should close interchange 000000001
+
+    let errors = parse_collecting(input).err().unwrap();
+    assert_eq!(errors.len(), 3);
+    assert_eq!(errors[0].error_segment().unwrap()[0], "SE");
+    assert_eq!(errors[1].error_segment().unwrap()[0], "GE");
+    assert_eq!(errors[2].error_segment().unwrap()[0], "IEA");
+}
+
+#[test]
+fn collect_validation_errors_then_structural_error() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+BEG*****~
+SE*4*000000001~
+GE*1*1~
+GS*PO*SENDERGS*007326879*20020226*1534*2*X*004010~
+BEG*****~
+IEA*2*000000001~"; // the second BEG is not inside of any transaction
+
+    let errors = parse_collecting(input).err().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].error_segment().unwrap()[0], "SE");
+    assert_eq!(errors[1].error_segment().unwrap()[0], "BEG");
+}
+
+#[test]
+fn collecting_parse_of_valid_document() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+BEG*****~
+SE*3*000000001~
+GE*1*1~
+IEA*1*000000001~";
+    assert!(parse_collecting(input).is_ok());
+}
+
+#[test]
+fn duplicate_control_numbers() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*****~
+SE*3*0001~
+ST*850*0001~
+BEG*****~
+SE*3*0001~
+GE*2*1~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*****~
+SE*3*0001~
+GE*1*1~
+IEA*2*000000001~
+ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*****~
+SE*3*0001~
+GE*1*1~
+IEA*1*000000001~";
+
+    let edi_document = parse(input).unwrap();
+    let errors = edi_document
+        .check_control_number_uniqueness()
+        .err()
+        .unwrap();
+    // the repeated ST in the first group, the repeated GS in the first interchange, and the repeated ISA
+    assert_eq!(errors.len(), 3);
+}
+
+#[test]
+fn unique_control_numbers() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*****~
+SE*3*0001~
+ST*850*0002~
+BEG*****~
+SE*3*0002~
+GE*2*1~
+IEA*1*000000001~";
+
+    let edi_document = parse(input).unwrap();
+    assert!(edi_document.check_control_number_uniqueness().is_ok());
+}
+
+#[test]
+fn multiple_interchanges() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*****~
+SE*3*0001~
+GE*1*1~
+IEA*1*000000001~
+ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000002*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*****~
+SE*3*0001~
+GE*1*1~
+IEA*1*000000002~";
+
+    let edi_document = parse(input).unwrap();
+    assert_eq!(edi_document.interchanges.len(), 2);
+}
+
+#[test]
+fn interchange_opened_before_previous_closed() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*****~
+SE*3*0001~
+GE*1*1~
+ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000002*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*****~
+SE*3*0001~
+GE*1*1~
+IEA*1*000000002~"; // the first interchange is missing its IEA
+
+    assert!(parse(input).is_err());
+    assert_eq!(loose_parse(input).unwrap().interchanges.len(), 2);
+}
+
+#[test]
+fn interchange_closed_twice() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*****~
+SE*3*0001~
+GE*1*1~
+IEA*1*000000001~
+IEA*1*000000001~";
+
+    assert!(parse(input).is_err());
+    assert!(loose_parse(input).is_ok());
+}
+
+// Malformed counts in closing segments must produce errors, not panics.
+#[test]
+fn non_numeric_closing_counts() {
+    let segments = [
+        ("SE*abc*0001~", "GE*1*1~", "IEA*1*000000001~"),
+        ("SE*3*0001~", "GE*-1*1~", "IEA*1*000000001~"),
+        ("SE*3*0001~", "GE*1*1~", "IEA*one*000000001~"),
+    ];
+    for (se, ge, iea) in segments.iter() {
+        let input = format!("ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*****~
+{}
+{}
+{}", se, ge, iea);
+        assert!(parse(&input).is_err());
+        assert!(loose_parse(&input).is_ok());
+    }
+}
+
+// Truncated closing segments must produce errors, not panics.
+#[test]
+fn truncated_closing_segments() {
+    let segments = [
+        ("SE~", "GE*1*1~", "IEA*1*000000001~"),
+        ("SE*3*0001~", "GE*1~", "IEA*1*000000001~"),
+        ("SE*3*0001~", "GE*1*1~", "IEA~"),
+    ];
+    for (se, ge, iea) in segments.iter() {
+        let input = format!("ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*****~
+{}
+{}
+{}", se, ge, iea);
+        assert!(parse(&input).is_err());
+    }
+}
+
+#[test]
+fn interchange_count_excludes_segments_outside_groups() {
+    // IEA01 counts functional groups only, so the TA1 segments kept at the interchange level must not
+    // change it, either when validating or when serializing.
+    let input = "ISA*01*0000000000*01*0000000000*ZZ*ABCDEFGHIJKLMNO*ZZ*123456789012345*101127*1719*U*00400*000003438*0*P*>
+TA1*000003437*101127*1719*A*000
+TA1*000003436*101127*1719*A*000
+GS*PO*4405197800*999999999*20101127*1719*1421*X*004010VICS
+ST*850*0001
+BEG*00*SA*1*
+SE*3*0001
+GE*1*1421
+GS*IN*4405197800*999999999*20101205*1710*1320*X*004010VICS
+ST*810*1004
+BIG*20101204*217224*20101204*P792940
+SE*3*1004
+GE*1*1320
+IEA*2*000003438";
+    let options = ParseOptions {
+        allow_segments_before_group: true,
+        ..ParseOptions::default()
+    };
+    let document = parse_with_options(input, &options).unwrap();
+    let interchange = &document.interchanges[0];
+    assert_eq!(interchange.interchange_segments.len(), 2);
+    assert_eq!(interchange.functional_group_count(), 2);
+    assert!(document.to_x12_string().ends_with("\nIEA*2*000003438"));
+
+    let miscounted = input.replace("IEA*2*", "IEA*4*");
+    assert!(parse_with_options(&miscounted, &options).is_err());
+}
+
+#[test]
+fn loose_parse_reports_skipped_validations() {
+    let input = "ISA*01*0000000000*01*0000000000*ZZ*ABCDEFGHIJKLMNO*ZZ*123456789012345*101127*1719*U*00400*000003438*0*P*>
+GS*PO*4405197800*999999999*20101127*1719*1421*X*004010VICS
+ST*850*0001
+BEG*00*SA*1*
+
+SE*9*0001
+GE*1*9999
+IEA*1*000003438";
+    assert!(parse(input).is_err());
+    assert!(loose_parse(input).is_ok());
+
+    let (document, report) = loose_parse_with_report(input).unwrap();
+    assert_eq!(document.transactions().count(), 1);
+    assert_eq!(report.len(), 2);
+    assert!(report[0].reason().contains("incorrect number of segments"));
+    assert!(report[1]
+        .reason()
+        .contains("functional group validation failed"));
+
+    let (_, report) = loose_parse_with_report(
+        &input
+            .replace("SE*9*", "SE*3*")
+            .replace("GE*1*9999", "GE*1*1421"),
+    )
+    .unwrap();
+    assert!(report.is_empty());
+}
+
+#[test]
+fn out_of_order_errors_name_the_segment() {
+    let header = "ISA*01*0000000000*01*0000000000*ZZ*ABCDEFGHIJKLMNO*ZZ*123456789012345*101127*1719*U*00400*000003438*0*P*>";
+    let reason = |input: &str| parse(input).err().unwrap().reason().to_string();
+
+    assert_eq!(
+        reason(&format!("{}\nST*850*0001\nSE*2*0001", header)),
+        "EDI file out of order: received ST but no functional group (GS) is open"
+    );
+    assert_eq!(
+        reason(&format!(
+            "{}\nGS*PO*4405197800*999999999*20101127*1719*1421*X*004010VICS\nBEG*00*SA*1*",
+            header
+        )),
+        "EDI file out of order: received BEG but no transaction (ST) is open"
+    );
+
+    // the delimiters are given, so the document does not have to start with an ISA
+    let options = ParseOptions {
+        delimiters: Some(('\n', '*', '>')),
+        ..ParseOptions::default()
+    };
+    let error = parse_with_options(
+        "GS*PO*4405197800*999999999*20101127*1719*1421*X*004010VICS",
+        &options,
+    )
+    .err()
+    .unwrap();
+    assert!(error
+        .reason()
+        .starts_with("EDI file out of order: received GS but no interchange (ISA) is open"));
+}
+
+#[test]
+fn numeric_control_numbers() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*0001*X*004010~
+ST*850*0001~
+BEG*****~
+SE*3*1~
+GE*1*1~
+IEA*1*1~";
+
+    assert!(parse(input).is_err());
+
+    let options = ParseOptions {
+        numeric_control_numbers: true,
+        ..ParseOptions::default()
+    };
+    assert!(parse_with_options(input, &options).is_ok());
+
+    let mismatched = input.replace("IEA*1*1~", "IEA*1*10~");
+    let error = parse_with_options(&mismatched, &options).err().unwrap();
+    assert!(error
+        .reason()
+        .starts_with("interchange validation failed: mismatched ID"));
+}
+
+#[test]
+fn error_kinds() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*00*SA*A99-01~
+SE*3*0001~
+GE*1*1~
+IEA*1*000000001~";
+    let kind = |input: &str| parse(input).unwrap_err().kind();
+
+    assert!(parse(input).is_ok());
+    assert_eq!(kind(&input[1..]), EdiErrorKind::MalformedHeader);
+    assert_eq!(
+        kind(&input.replace("SE*3*", "SE*4*")),
+        EdiErrorKind::CountMismatch
+    );
+    assert_eq!(
+        kind(&input.replace("GE*1*1~", "GE*2*1~")),
+        EdiErrorKind::CountMismatch
+    );
+    assert_eq!(
+        kind(&input.replace("SE*3*0001", "SE*3*0002")),
+        EdiErrorKind::ControlNumberMismatch
+    );
+    assert_eq!(
+        kind(&input.replace("IEA*1*000000001", "IEA*1*000000002")),
+        EdiErrorKind::ControlNumberMismatch
+    );
+    assert_eq!(
+        kind(&input.replace("ST*850*0001~", "")),
+        EdiErrorKind::OutOfOrder
+    );
+    assert_eq!(
+        kind(&input.replace("GE*1*1~", "GE*1~")),
+        EdiErrorKind::MissingElements
+    );
+    let unknown_test_indicator = input.replace("*T*>", "*X*>");
+    let document = parse(&unknown_test_indicator).unwrap();
+    assert_eq!(
+        document.interchanges[0]
+            .validate_indicators()
+            .unwrap_err()
+            .kind(),
+        EdiErrorKind::InvalidElement
+    );
+    assert_eq!(
+        kind(&input.replace("BEG*", "~BEG*")),
+        EdiErrorKind::MalformedSegment
+    );
+}
+
+#[test]
+fn envelope_element_counts() {
+    // the implementation convention reference (ST03) contains the element delimiter
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001*005010*X222A1~
+BEG*00*SA*A99-01~
+SE*3*0001~
+GE*1*1~
+IEA*1*000000001~";
+
+    assert!(parse(input).is_ok());
+
+    let options = ParseOptions {
+        check_envelope_element_counts: true,
+        ..ParseOptions::default()
+    };
+    let error = parse_with_options(input, &options).err().unwrap();
+    assert_eq!(
+        error.reason(),
+        "envelope validation failed: ST segment has 4 elements but 2 to 3 are expected; an element may contain the element delimiter (*) or the segment delimiter (~)"
+    );
+    assert_eq!(error.error_segment().unwrap()[3], "005010");
+    assert!(parse_with_options(&input.replace("005010*X222A1", "005010X222A1"), &options).is_ok());
+
+    // the application sender's code (GS02) contains the element delimiter, which shifts the group control number
+    let (_, report) = loose_parse_with_report(&input.replace("SENDERGS", "SENDER*GS")).unwrap();
+    assert_eq!(report.len(), 3);
+    assert!(report[0]
+        .reason()
+        .contains("GS segment has 9 elements but 8 are expected"));
+    assert!(report[1].reason().contains("ST segment has 4 elements"));
+    assert!(report[2]
+        .reason()
+        .contains("functional group validation failed: mismatched ID"));
+}
+
+#[test]
+fn loose_parse_statistics() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*****~
+REF*VR*54321~
+SE*9*0001~
+ST*850*0002~
+BEG*****~
+SE*3*0002~
+GE*5*1~
+IEA*1*000000001~";
+
+    let (_, stats) = parse_with_stats(input, &ParseOptions::loose()).unwrap();
+    assert_eq!(
+        stats,
+        ParseStats {
+            interchanges: 1,
+            functional_groups: 1,
+            transactions: 2,
+            generic_segments: 3,
+            skipped_validations: 4,
+            unclosed_transactions: 0,
+        }
+    );
+
+    assert!(parse_with_stats(input, &ParseOptions::default()).is_err());
+    let (_, stats) = parse_with_stats(
+        &input.replace("SE*9*", "SE*4*").replace("GE*5*", "GE*2*"),
+        &ParseOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(stats.skipped_validations, 0);
+    assert_eq!(stats.generic_segments, 3);
+}
+
+#[test]
+fn parse_and_report_verdict() {
+    let input = "ISA*  *          *  *          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+BEG*****~
+SE*4*000000001~
+GE*1*2~
+IEA*1*000000001~";
+
+    let (document, verdict) = parse_with_validation(input).unwrap();
+    assert_eq!(document.transactions().count(), 1);
+    let errors = verdict.err().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert!(errors[0]
+        .reason()
+        .contains("transaction validation failed: incorrect number of segments"));
+    assert!(errors[1]
+        .reason()
+        .contains("functional group validation failed: mismatched ID"));
+    assert_eq!(document, loose_parse(input).unwrap());
+
+    let valid = input.replace("SE*4*", "SE*3*").replace("GE*1*2", "GE*1*1");
+    let (document, verdict) = parse_with_validation(&valid).unwrap();
+    assert!(verdict.is_ok());
+    assert_eq!(document, parse(&valid).unwrap());
+
+    // the document cannot be built if a segment is out of order
+    assert!(parse_with_validation(&input.replace("ST*850*000000001~\n", "")).is_err());
+}
+
+#[test]
+fn multibyte_characters_in_isa_header() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*0001~SE*2*0001~GE*1*1~IEA*1*000000001";
+
+    // an accented character taking up two bytes keeps the fixed-width layout intact
+    let accented = input.replacen("*00*          *", "*00*CAFé     *", 1);
+    let document = parse(&accented).unwrap();
+    assert_eq!(document.interchanges[0].authorization_information, "CAFé");
+
+    // multibyte characters which shift the delimiters are rejected rather than sliced through
+    for malformed in [
+        input.replacen("*>~", "*€~", 1),
+        input.replacen("*>~", "*é", 1),
+        input.replacen("*T*>~", "*T€>~", 1),
+        input.replacen("*00*          *", "*00*€         *", 1),
+    ]
+    .iter()
+    {
+        assert_eq!(
+            parse(malformed).unwrap_err().kind(),
+            EdiErrorKind::MalformedHeader
+        );
+    }
+}
+
+#[test]
+fn loose_parse_unpadded_isa() {
+    let input = "ISA*00**00**ZZ*SENDERISA*14*0073268795005*020226*1534*U*00401*000000001*0*T*:~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*00*SA*A99-01~
+SE*3*0001~
+GE*1*1~
+IEA*1*000000001~";
+    assert_eq!(
+        parse(input).unwrap_err().kind(),
+        EdiErrorKind::MalformedHeader
+    );
+    let document = loose_parse(input).unwrap();
+    assert_eq!(document.sub_element_delimiter, ':');
+    assert_eq!(document.interchanges[0].sender_id, "SENDERISA");
+    assert_eq!(document.transactions().count(), 1);
+}
+
+#[test]
+fn transaction_missing_se() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*00*SA*A99-01~
+ST*850*0002~
+BEG*00*SA*A99-02~
+SE*3*0002~
+GE*2*1~
+IEA*1*000000001~";
+    let error = parse(input).unwrap_err();
+    assert_eq!(error.kind(), EdiErrorKind::OutOfOrder);
+    assert_eq!(
+        error.reason(),
+        "transaction validation failed: ST received before the previous transaction was closed by an SE"
+    );
+
+    let (document, stats) = parse_with_stats(input, &ParseOptions::loose()).unwrap();
+    assert_eq!(document.transactions().count(), 2);
+    assert_eq!(stats.unclosed_transactions, 1);
+    let (_, report) = loose_parse_with_report(input).unwrap();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].kind(), EdiErrorKind::OutOfOrder);
+
+    // the SE of the last transaction is missing too
+    let input = input.replace("SE*3*0002~\n", "");
+    let (_, stats) = parse_with_stats(&input, &ParseOptions::loose()).unwrap();
+    assert_eq!(stats.unclosed_transactions, 2);
+    assert!(parse(&input)
+        .unwrap_err()
+        .reason()
+        .starts_with("transaction validation failed: ST received"));
+    let (_, report) = loose_parse_with_report(&input).unwrap();
+    assert!(report[1]
+        .reason()
+        .starts_with("transaction validation failed: GE received"));
+}
+
+/// An interchange whose only transaction lost its SE and GE.
+const UNCLOSED_TRANSACTION: &str = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*00*SA*A99-01";
+
+/// Check that `input` has one unclosed transaction, which strict parsing rejects with `reason` and loose parsing
+/// reports once.
+fn assert_one_unclosed_transaction(input: &str, reason: &str) {
+    let (_, stats) = parse_with_stats(input, &ParseOptions::loose()).unwrap();
+    assert_eq!(stats.unclosed_transactions, 1);
+    assert_eq!(parse(input).unwrap_err().reason(), reason);
+    let (_, report) = loose_parse_with_report(input).unwrap();
+    assert_eq!(
+        report
+            .iter()
+            .filter(|error| error.reason() == reason)
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn transaction_closed_by_iea() {
+    assert_one_unclosed_transaction(
+        &format!("{}~\nIEA*1*000000001", UNCLOSED_TRANSACTION),
+        "transaction validation failed: IEA received before the previous transaction was closed by an SE",
+    );
+}
+
+#[test]
+fn transaction_closed_by_isa() {
+    let next_interchange = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000002*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*2*X*004010~
+ST*850*0001~
+SE*2*0001~
+GE*1*2~
+IEA*1*000000002";
+    assert_one_unclosed_transaction(
+        &format!("{}~\n{}", UNCLOSED_TRANSACTION, next_interchange),
+        "transaction validation failed: ISA received before the previous transaction was closed by an SE",
+    );
+}
+
+#[test]
+fn transaction_closed_by_end_of_document() {
+    assert_one_unclosed_transaction(
+        UNCLOSED_TRANSACTION,
+        "transaction validation failed: the document ended before the last transaction was closed by an SE",
+    );
+}
+
+#[test]
+fn parse_with_limits() {
+    let interchange = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*00*SA*A99-01~
+SE*3*0001~
+GE*1*1~
+IEA*1*000000001~
+";
+    let options = ParseOptions {
+        max_segments: Some(7),
+        ..ParseOptions::default()
+    };
+    assert!(parse_with_options(interchange, &options).is_ok());
+    let options = ParseOptions {
+        max_segments: Some(6),
+        ..ParseOptions::default()
+    };
+    let error = parse_with_options(interchange, &options).unwrap_err();
+    assert_eq!(error.kind(), EdiErrorKind::LimitExceeded);
+    assert_eq!(
+        error.reason(),
+        "document contains more segments than allowed  --  expected: at most 6 segments  received: more than 6"
+    );
+
+    let input = interchange.repeat(3);
+    let options = ParseOptions {
+        max_interchanges: Some(3),
+        ..ParseOptions::default()
+    };
+    assert_eq!(
+        parse_with_options(&input, &options)
+            .unwrap()
+            .interchanges
+            .len(),
+        3
+    );
+    let options = ParseOptions {
+        max_interchanges: Some(2),
+        ..ParseOptions::default()
+    };
+    let error = parse_with_options(&input, &options).unwrap_err();
+    assert_eq!(error.kind(), EdiErrorKind::LimitExceeded);
+    // the limits hold even when control counts are not validated
+    let options = ParseOptions {
+        max_interchanges: Some(2),
+        ..ParseOptions::loose()
+    };
+    assert!(parse_with_options(&input, &options).is_err());
+}
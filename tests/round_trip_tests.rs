@@ -0,0 +1,310 @@
+// This file contains the tests which check that parsing a document and serializing it again reproduces the
+// original. Documents are compared after normalizing away the differences the serializer is allowed to make:
+// whitespace around segments, line breaks between them, and a trailing segment delimiter. Documents are also
+// serialized to and deserialized from the serde formats the crate documentation promises.
+use edi::{parse, parse_with_options, EdiDocument, ParseOptions};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fs::read_to_string;
+
+fn normalize(input: &str, segment_delimiter: char) -> String {
+    input
+        .split(segment_delimiter)
+        .map(|segment| segment.trim())
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<&str>>()
+        .join(&segment_delimiter.to_string())
+}
+
+fn assert_round_trips(input: &str) {
+    let document = parse(input).unwrap();
+    let output = document.to_x12_string();
+    assert_eq!(output, normalize(input, document.segment_delimiter));
+    assert_eq!(parse(&output).unwrap(), document);
+}
+
+fn sample_document() -> String {
+    read_to_string(format!(
+        "{}/examples/sample_edi.txt",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap()
+}
+
+#[test]
+fn round_trip_sample_document() {
+    assert_round_trips(&sample_document());
+}
+
+#[test]
+fn round_trip_empty_elements() {
+    assert_round_trips("ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001*~
+BEG*00*SA*A99999-01**19970214~
+REF*VR*54321*~
+ITD*01*3*1**15**16~
+N1*ST**92*123~
+N4*ATLANTA*GA*31139-0020**SN*9999~
+PER*IC***~
+SE*8*000000001~
+GE*1*1~
+IEA*1*000000001~");
+}
+
+#[test]
+fn round_trip_multiple_groups_and_interchanges() {
+    assert_round_trips("ISA*01*0000000000*01*0000000000*ZZ*ABCDEFGHIJKLMNO*ZZ*123456789012345*101127*1719*U*00400*000003438*0*P*>
+GS*PO*4405197800*999999999*20101127*1719*1421*X*004010VICS
+ST*850*0001*005010X220A1
+BEG*00*SA*1*
+SE*3*0001
+ST*850*0002
+BEG*00*SA*2*
+REF*DP*099
+SE*4*0002
+GE*2*1421
+GS*IN*4405197800*999999999*20101205*1710*1320*X*004010VICS
+ST*810*1004
+BIG*20101204*217224*20101204*P792940
+SE*3*1004
+GE*1*1320
+IEA*2*000003438
+ISA*01*0000000000*01*0000000000*ZZ*ABCDEFGHIJKLMNO*ZZ*123456789012345*101127*1719*U*00400*000003439*0*P*>
+GS*PO*4405197800*999999999*20101127*1719*1422*X*004010VICS
+ST*850*0003
+BEG*00*SA*3*
+SE*3*0003
+GE*1*1422
+IEA*1*000003439
+");
+}
+
+#[test]
+fn round_trip_whitespace_around_empty_elements() {
+    // trimming the whitespace around a segment must not take its trailing empty elements with it
+    let document = parse("ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+  N1*ST**92*123  ~
+PER*IC** ~
+SE*4*000000001~
+GE*1*1~
+IEA*1*000000001~").unwrap();
+    let segments: Vec<String> = document
+        .segments()
+        .map(|segment| segment.to_x12_string('*'))
+        .collect();
+    assert_eq!(segments, vec!["N1*ST**92*123", "PER*IC**"]);
+}
+
+#[test]
+fn round_trip_crlf_terminated_segments() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>\r\nGS*PO*SENDERGS*007326879*20020226*1534*1*X*004010\r\nST*850*000000001\r\nBEG*00*SA*A99999-01**19970214\r\nREF*VR*54321\r\nSE*4*000000001\r\nGE*1*1\r\nIEA*1*000000001";
+    let document = parse(input).unwrap();
+    assert_eq!(document.segment_delimiter, '\r');
+    assert_eq!(document.to_x12_string_pretty(), input);
+    assert_round_trips(input);
+}
+
+#[test]
+fn round_trip_binary_segment() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*841*0001~SPI*00~BIN*10* ~*~BIN* \n~SE*4*0001~GE*1*1~IEA*1*000000001";
+    let document = parse(input).unwrap();
+    let segment = &document.interchanges[0].functional_groups[0].transactions[0].segments[1];
+    assert_eq!(segment.element(2), Some(" ~*~BIN* \n"));
+    assert_eq!(document.to_x12_string(), input);
+}
+
+#[test]
+fn round_trip_escaped_delimiters() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+MSG*SIZE 3?*4?~ 10*X?>Y??~
+SE*3*000000001~
+GE*1*1~
+IEA*1*000000001";
+    let options = ParseOptions {
+        release_character: Some('?'),
+        ..ParseOptions::default()
+    };
+    let document = parse_with_options(input, &options).unwrap();
+    let segment = document.segments().next().unwrap();
+    assert_eq!(segment.element(1), Some("SIZE 3*4~ 10"));
+    assert!(matches!(segment.elements[0], Cow::Owned(_)));
+    assert_eq!(segment.element(2), Some("X?>Y??"));
+    assert!(matches!(segment.elements[1], Cow::Borrowed(_)));
+    assert_eq!(document.release_character, Some('?'));
+
+    let output = document.to_x12_string();
+    assert_eq!(output, input.replace('\n', ""));
+    assert_eq!(parse_with_options(&output, &options).unwrap(), document);
+}
+
+#[test]
+fn round_trip_escaped_delimiters_with_binary_segment() {
+    // the data of a binary segment is taken by its length, so its delimiters and release characters are not escapes
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*841*0001~REF*ZZ*A?~B~BIN*5*a?~~c~SE*4*0001~GE*1*1~IEA*1*000000001";
+    let options = ParseOptions {
+        release_character: Some('?'),
+        ..ParseOptions::default()
+    };
+    let document = parse_with_options(input, &options).unwrap();
+    let mut segments = document.segments();
+    assert_eq!(segments.next().unwrap().element(2), Some("A~B"));
+    assert_eq!(segments.next().unwrap().element(2), Some("a?~~c"));
+
+    let output = document.to_x12_string();
+    assert_eq!(output, input);
+    assert_eq!(parse_with_options(&output, &options).unwrap(), document);
+}
+
+#[test]
+fn round_trip_original_trailers() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*1~
+BEG*00*SA*A99-01~
+SE*0003*0001~
+GE*01*000001~
+IEA*1*1";
+    let options = ParseOptions {
+        preserve_original_trailers: true,
+        numeric_control_numbers: true,
+        ..ParseOptions::default()
+    };
+    let document = parse_with_options(input, &options).unwrap();
+    assert_eq!(document.to_x12_string(), input.replace('\n', ""));
+    let generated = ParseOptions {
+        preserve_original_trailers: false,
+        ..options.clone()
+    };
+    assert_eq!(
+        parse_with_options(input, &generated)
+            .unwrap()
+            .to_x12_string(),
+        input
+            .replace('\n', "")
+            .replace("SE*0003*0001~GE*01*000001", "SE*3*1~GE*1*1")
+            .replace("IEA*1*1", "IEA*1*000000001")
+    );
+    let transaction = document.transactions().next().unwrap();
+    assert_eq!(
+        transaction.original_trailer,
+        Some(vec![Cow::from("0003"), Cow::from("0001")])
+    );
+    // a trailer which no longer agrees with the document is generated
+    let mut modified = document.clone();
+    modified.interchanges[0].functional_groups[0].transactions[0]
+        .segments
+        .push_back(edi::GenericSegment::new("REF", vec!["IA", "99999"]));
+    let output = modified.to_x12_string();
+    assert!(output.contains("~SE*4*1~GE*01*000001~IEA*1*1"));
+    assert_eq!(parse_with_options(&output, &options).unwrap(), {
+        let mut reparsed = modified;
+        reparsed.interchanges[0].functional_groups[0].transactions[0].original_trailer =
+            Some(vec![Cow::from("4"), Cow::from("1")]);
+        reparsed
+    });
+}
+
+#[test]
+fn compare_documents() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*0001~REF*VR*54321~SE*3*0001~GE*1*1~IEA*1*000000001";
+    let document = parse(input).unwrap();
+    let mut copy = document.clone();
+    assert_eq!(copy, document);
+
+    copy.segment_delimiter = '\n';
+    assert_ne!(copy, document);
+
+    let mut copy = document.clone();
+    copy.interchanges[0].functional_groups[0].transactions[0].segments[0].elements[1] =
+        "54322".into();
+    assert_ne!(copy, document);
+
+    let documents: HashSet<EdiDocument> =
+        vec![document.clone(), copy, document].into_iter().collect();
+    assert_eq!(documents.len(), 2);
+}
+
+#[test]
+fn round_trip_json() {
+    let input = sample_document();
+    let document = parse(&input).unwrap();
+    let json = serde_json::to_string(&document).unwrap();
+    assert_eq!(
+        serde_json::from_str::<EdiDocument>(&json).unwrap(),
+        document
+    );
+
+    // strings with escape sequences can't be borrowed from the input, so they are copied instead
+    let escaped = json
+        .replace("Ship Notice/Manifest", "Ship Notice\\/Manifest")
+        .replace("ABCDEFGHIJKLMNO", "ABCDEFGHIJKLMN\\u004F");
+    assert_ne!(escaped, json);
+    assert_eq!(
+        serde_json::from_str::<EdiDocument>(&escaped).unwrap(),
+        document
+    );
+
+    // a transaction name which is not bundled can't be borrowed, since the name is not a Cow, so the bundled
+    // name of the transaction's code is used instead
+    let custom_name = escaped.replace("Ship Notice\\/Manifest", "Custom\\/Name");
+    assert_ne!(custom_name, escaped);
+    assert_eq!(
+        serde_json::from_str::<EdiDocument>(&custom_name).unwrap(),
+        document
+    );
+}
+
+#[test]
+fn round_trip_yaml() {
+    let input = sample_document();
+    let document = parse(&input).unwrap();
+    let yaml = serde_yaml::to_string(&document).unwrap();
+    assert_eq!(
+        serde_yaml::from_str::<EdiDocument>(&yaml).unwrap(),
+        document
+    );
+
+    let escaped = yaml.replace(
+        "transaction_name: Ship Notice/Manifest",
+        "transaction_name: \"Ship Notice\\x2FManifest\"",
+    );
+    assert_ne!(escaped, yaml);
+    assert_eq!(
+        serde_yaml::from_str::<EdiDocument>(&escaped).unwrap(),
+        document
+    );
+}
+
+#[test]
+fn round_trip_bincode() {
+    let input = sample_document();
+    let document = parse(&input).unwrap();
+    let bytes = bincode::serialize(&document).unwrap();
+    assert_eq!(
+        bincode::deserialize::<EdiDocument>(&bytes).unwrap(),
+        document
+    );
+}
+
+#[test]
+fn round_trip_without_borrowing() {
+    // an owned JSON value never lends its strings, like the deserializers of language bindings
+    let input = sample_document();
+    let document = parse(&input).unwrap();
+    let value = serde_json::to_value(&document).unwrap();
+    let deserialized: EdiDocument<'static, 'static> = EdiDocument::deserialize(value).unwrap();
+    assert_eq!(deserialized, document);
+
+    let transaction = &deserialized.interchanges[0].functional_groups[0].transactions[0];
+    assert!(matches!(
+        transaction.transaction_set_control_number,
+        Cow::Owned(_)
+    ));
+    assert!(matches!(transaction.segments[0].elements[0], Cow::Owned(_)));
+}
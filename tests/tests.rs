@@ -1,5 +1,11 @@
 extern crate edi;
-use edi::parse;
+use edi::{
+    parse, parse_from_tokens, parse_with_options, parse_with_progress, AckError, Delimiters,
+    DocumentTokens, EdiDocument, EdiErrorKind, ElementSpec, ElementType, GenericSegment,
+    ParseOptions, EDI_SCHEMA_VERSION,
+};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 // For tests that check behavior of private fields or structs, or individual unit tests, I put the tests in
 // the same file as the struct/function being tested. This avoids unnecessary `pub` at the cost of messier test organization.
 // I think the trade-off is worth it, and the organizational loss is not that bad.
@@ -142,3 +148,1009 @@ IEA*1*000000001~";
         23
     );
 }
+
+#[test]
+fn parse_with_options_preserve_whitespace() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+PID*F****  CRUNCHY CHIPS  ~
+SE*3*000000001~
+GE*1*1~
+IEA*1*000000001~";
+
+    let options = ParseOptions {
+        preserve_whitespace: true,
+        ..ParseOptions::default()
+    };
+    let edi_document = parse_with_options(input, &options).unwrap();
+    assert_eq!(
+        edi_document.interchanges[0].functional_groups[0].transactions[0].segments[0].elements[4],
+        "  CRUNCHY CHIPS  "
+    );
+
+    let edi_document = parse(input).unwrap();
+    assert_eq!(
+        edi_document.interchanges[0].functional_groups[0].transactions[0].segments[0].elements[4],
+        "CRUNCHY CHIPS"
+    );
+}
+
+#[test]
+fn parse_with_options_keep_raw() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+TA1*000000001*020226*1534*A*000~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+  PID*F****  CRUNCHY CHIPS  **~
+SE*3*000000001~
+GE*1*1~
+IEA*1*000000001~";
+
+    let options = ParseOptions {
+        keep_raw: true,
+        allow_segments_before_group: true,
+        ..ParseOptions::default()
+    };
+    let edi_document = parse_with_options(input, &options).unwrap();
+    let segment = &edi_document.interchanges[0].functional_groups[0].transactions[0].segments[0];
+    assert_eq!(segment.elements[4], "CRUNCHY CHIPS");
+    assert_eq!(
+        segment.raw.as_deref(),
+        Some("  PID*F****  CRUNCHY CHIPS  **")
+    );
+    assert_eq!(
+        edi_document.interchanges[0].interchange_segments[0]
+            .raw
+            .as_deref(),
+        Some("TA1*000000001*020226*1534*A*000")
+    );
+    let owned = edi_document.clone().into_owned();
+    assert_eq!(owned, edi_document);
+
+    let edi_document = parse_with_options(
+        input,
+        &ParseOptions {
+            allow_segments_before_group: true,
+            ..ParseOptions::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(edi_document.segments().next().unwrap().raw, None);
+}
+
+#[test]
+fn parse_with_options_free_text_segments() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+NTE*GEN*DELIVER TO DOCK *4* ONLY~
+NTE*GEN~
+PID*F****CHIPS*SALT~
+SE*5*000000001~
+GE*1*1~
+IEA*1*000000001";
+
+    let options = ParseOptions {
+        free_text_segments: &["NTE"],
+        ..ParseOptions::default()
+    };
+    let edi_document = parse_with_options(input, &options).unwrap();
+    let segments = &edi_document.interchanges[0].functional_groups[0].transactions[0].segments;
+    assert_eq!(
+        segments[0].elements,
+        vec!["GEN", "DELIVER TO DOCK *4* ONLY"]
+    );
+    assert_eq!(segments[1].elements, vec!["GEN"]);
+    assert_eq!(segments[2].elements.len(), 6);
+    assert_eq!(edi_document.to_x12_string(), input.replace('\n', ""));
+
+    let edi_document = parse(input).unwrap();
+    let segments = &edi_document.interchanges[0].functional_groups[0].transactions[0].segments;
+    assert_eq!(segments[0].elements.len(), 4);
+}
+
+#[test]
+fn parse_with_options_segments_before_group() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+TA1*000000001*020226*1534*A*000~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+BEG*00*SA*A99999-01**19970214~
+SE*3*000000001~
+GE*1*1~
+IEA*1*000000001~";
+
+    assert!(parse(input).is_err());
+
+    let options = ParseOptions {
+        allow_segments_before_group: true,
+        ..ParseOptions::default()
+    };
+    let edi_document = parse_with_options(input, &options).unwrap();
+    assert_eq!(edi_document.interchanges[0].interchange_segments.len(), 1);
+    assert_eq!(
+        edi_document.interchanges[0].interchange_segments[0].segment_abbreviation,
+        "TA1"
+    );
+    assert_eq!(
+        edi_document.interchanges[0].functional_groups[0].transactions[0]
+            .segments
+            .len(),
+        1
+    );
+    assert!(edi_document
+        .to_x12_string()
+        .contains("*>~TA1*000000001*020226*1534*A*000~GS*PO"));
+}
+
+#[test]
+fn iterate_document_segments() {
+    let input = "ISA*01*0000000000*01*0000000000*ZZ*ABCDEFGHIJKLMNO*ZZ*123456789012345*101127*1719*U*00400*000003438*0*P*>
+GS*PO*4405197800*999999999*20101127*1719*1421*X*004010VICS
+ST*850*0001
+BEG*00*SA*1*
+REF*DP*099
+SE*4*0001
+ST*850*0002
+BEG*00*SA*2*
+SE*3*0002
+GE*2*1421
+GS*IN*4405197800*999999999*20101205*1710*1320*X*004010VICS
+ST*810*1004
+BIG*20101204*217224*20101204*P792940
+CTT*8
+SE*4*1004
+GE*1*1320
+IEA*2*000003438";
+
+    let edi_document = parse(input).unwrap();
+    assert_eq!(edi_document.functional_groups().count(), 2);
+    assert_eq!(
+        edi_document
+            .transactions()
+            .map(|transaction| transaction.transaction_set_control_number.as_ref())
+            .collect::<Vec<&str>>(),
+        vec!["0001", "0002", "1004"]
+    );
+    assert_eq!(
+        edi_document
+            .segments()
+            .map(|segment| segment.segment_abbreviation.as_ref())
+            .collect::<Vec<&str>>(),
+        vec!["BEG", "REF", "BEG", "BIG", "CTT"]
+    );
+}
+
+#[test]
+fn parse_bytes_with_byte_order_mark() {
+    let input = "\u{feff}ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+BEG*00*SA*A99999-01**19970214~
+SE*3*000000001~
+GE*1*1~
+IEA*1*000000001~";
+
+    let edi_document = edi::parse_bytes(input.as_bytes()).unwrap();
+    assert_eq!(edi_document.interchanges[0].sender_id, "SENDERISA");
+    assert_eq!(edi_document.segments().count(), 1);
+
+    assert!(edi::parse_bytes(b"ISA*\xE9").is_err());
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn decode_latin1_bytes() {
+    let mut input = b"ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+N1*BT*CAF"
+        .to_vec();
+    input.push(0xE9); // é in Latin-1
+    input.extend_from_slice(
+        b"*9*1223334444~
+SE*3*000000001~
+GE*1*1~
+IEA*1*000000001~",
+    );
+
+    let decoded = edi::decode_bytes(&input);
+    let edi_document = parse(&decoded).unwrap();
+    assert_eq!(
+        edi_document.segments().next().unwrap().elements[1],
+        "CAF\u{e9}"
+    );
+}
+
+#[test]
+fn parse_case_insensitive_segment_ids() {
+    let input = "isa*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+gs*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ St *850*000000001~
+beg*00*SA*A99999-01**19970214~
+se*3*000000001~
+Ge*1*1~
+iea*1*000000001~";
+
+    assert!(parse(input).is_err());
+
+    let options = ParseOptions {
+        case_insensitive_segment_ids: true,
+        ..ParseOptions::default()
+    };
+    let edi_document = parse_with_options(input, &options).unwrap();
+    assert_eq!(edi_document.transactions().count(), 1);
+    assert_eq!(
+        edi_document.segments().next().unwrap().segment_abbreviation,
+        "beg"
+    );
+}
+
+#[test]
+fn pretty_print_document() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*000000001~BEG*00*SA*A99999-01**19970214~SE*3*000000001~GE*1*1~IEA*1*000000001";
+
+    let edi_document = parse(input).unwrap();
+    let pretty = edi_document.to_x12_string_pretty();
+    assert_eq!(pretty.lines().count(), 7);
+    assert!(pretty.lines().all(|line| !line.is_empty()));
+    assert_eq!(
+        pretty.lines().nth(3).unwrap(),
+        "BEG*00*SA*A99999-01**19970214~"
+    );
+    assert_eq!(
+        parse(&pretty).unwrap().to_x12_string(),
+        edi_document.to_x12_string()
+    );
+}
+
+#[test]
+fn pretty_print_escaped_and_binary_delimiters() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*841*0001~REF*ZZ*A?~B~BIN*5*a?~~c~SE*4*0001~GE*1*1~IEA*1*000000001";
+    let options = ParseOptions {
+        release_character: Some('?'),
+        ..ParseOptions::default()
+    };
+    let edi_document = parse_with_options(input, &options).unwrap();
+    let pretty = edi_document.to_x12_string_pretty();
+    // only the delimiters which end a segment are followed by a line break
+    assert_eq!(pretty.lines().count(), 8);
+    assert_eq!(pretty.lines().nth(3).unwrap(), "REF*ZZ*A?~B~");
+    assert_eq!(pretty.lines().nth(4).unwrap(), "BIN*5*a?~~c~");
+    assert_eq!(pretty.replace('\n', ""), input);
+    assert_eq!(parse_with_options(&pretty, &options).unwrap(), edi_document);
+}
+
+#[test]
+fn count_helpers_match_trailers() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*00*SA*A99999-01**19970214~
+REF*VR*54321~
+SE*4*0001~
+ST*850*0002~
+BEG*00*SA*A99999-02**19970214~
+SE*3*0002~
+GE*2*1~
+IEA*1*000000001~";
+
+    let edi_document = parse(input).unwrap();
+    let interchange = &edi_document.interchanges[0];
+    assert_eq!(interchange.functional_group_count(), 1);
+    assert_eq!(interchange.functional_groups[0].transaction_count(), 2);
+    assert_eq!(
+        interchange.functional_groups[0].transactions[0].segment_count_including_envelope(),
+        4
+    );
+    assert_eq!(
+        interchange.functional_groups[0].transactions[1].segment_count_including_envelope(),
+        3
+    );
+}
+
+#[test]
+fn display_summaries() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+BEG*00*SA*A99999-01**19970214~
+REF*VR*54321~
+SE*4*000000001~
+GE*1*1~
+IEA*1*000000001~";
+
+    let edi_document = parse(input).unwrap();
+    let interchange = &edi_document.interchanges[0];
+    assert_eq!(
+        interchange.to_string(),
+        "Interchange #000000001 from ZZ:SENDERISA to 14:0073268795005 with 1 functional group"
+    );
+    assert_eq!(
+        interchange.functional_groups[0].to_string(),
+        "Functional group PO #1 from SENDERGS to 007326879 with 1 transaction"
+    );
+    assert_eq!(
+        interchange.functional_groups[0].transactions[0].to_string(),
+        "Transaction 850 (Purchase Order) #000000001 with 2 segments"
+    );
+    assert_eq!(
+        interchange.functional_groups[0].transactions[0].segments[1].to_string(),
+        "REF (Reference Identification) segment with 2 elements"
+    );
+}
+
+#[test]
+fn peek_at_transaction_codes() {
+    let input = "ISA*01*0000000000*01*0000000000*ZZ*ABCDEFGHIJKLMNO*ZZ*123456789012345*101127*1719*U*00400*000003438*0*P*>
+GS*PO*4405197800*999999999*20101127*1719*1421*X*004010VICS
+ST*850*0001
+BEG*00*SA*1*
+SE*3*0001
+GE*1*1421
+GS*IN*4405197800*999999999*20101205*1710*1320*X*004010VICS
+ST*810*1004
+BIG*20101204*217224*20101204*P792940
+SE*3*1004
+GE*1*1320
+IEA*2*000003438";
+
+    assert_eq!(
+        edi::peek_transaction_codes(input).unwrap(),
+        vec!["850", "810"]
+    );
+    // the codes agree with the parsed document even with whitespace around them
+    let padded = input.replace("ST*850", "ST* 850 ");
+    assert_eq!(
+        edi::peek_transaction_codes(&padded).unwrap(),
+        parse(&padded)
+            .unwrap()
+            .transactions()
+            .map(|transaction| transaction.transaction_code.to_string())
+            .collect::<Vec<String>>()
+    );
+    assert!(edi::peek_transaction_codes("ST*850*0001").is_err());
+}
+
+#[test]
+fn check_interchange_complete() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*0001~
+BEG*00*SA*A99-01~
+SE*3*0001~
+GE*1*1~
+IEA*1*000000001~
+";
+    assert!(edi::is_interchange_complete(input));
+    assert!(edi::is_interchange_complete(&format!("\u{feff}{}", input)));
+    assert!(edi::is_interchange_complete(&input.repeat(2)));
+
+    // truncated
+    let truncated = &input[..input.find("GE*").unwrap()];
+    assert!(!edi::is_interchange_complete(truncated));
+    assert!(!edi::is_interchange_complete(&format!(
+        "{}{}",
+        input, truncated
+    )));
+    assert!(!edi::is_interchange_complete(&input[..50]));
+    // the IEA closes a different interchange
+    assert!(!edi::is_interchange_complete(
+        &input.replace("IEA*1*000000001", "IEA*1*000000002")
+    ));
+    // trailing data after the IEA
+    assert!(!edi::is_interchange_complete(&format!("{}GE*1*1~", input)));
+    // no ISA
+    assert!(!edi::is_interchange_complete(
+        &input[input.find("GS*").unwrap()..]
+    ));
+    assert!(!edi::is_interchange_complete(""));
+}
+
+#[test]
+fn parse_non_standard_isa_with_explicit_delimiters() {
+    let input = "ISA*01*0000000000*01*0000000000*ZZ*ABCDEFGHIJKLMNO*ZZ*12345*101127*1719*U*00400*000003438*0*P*>
+GS*PO*4405197800*999999999*20101127*1719*1421*X*004010VICS
+ST*850*0001
+BEG*00*SA*1*
+SE*3*0001
+GE*1*1421
+IEA*1*000003438";
+    assert!(parse(input).is_err());
+
+    let options = ParseOptions {
+        delimiters: Some(('\n', '*', '>')),
+        ..ParseOptions::default()
+    };
+    let document = parse_with_options(input, &options).unwrap();
+    assert_eq!(document.interchanges[0].receiver_id, "12345");
+    assert_eq!(document.segment_delimiter, '\n');
+    assert_eq!(document.transactions().count(), 1);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn write_x12_to_io_sink() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*000000001~BEG*00*SA*A99999-01**19970214~REF*VR*54321~SE*4*000000001~GE*1*1~IEA*1*000000001";
+    let document = parse(input).unwrap();
+
+    let mut output = Vec::new();
+    document.write_x12(&mut output).unwrap();
+    assert_eq!(String::from_utf8(output).unwrap(), input);
+    assert_eq!(document.to_x12_string(), input);
+}
+
+#[test]
+fn indexed_access() {
+    let input = "ISA*01*0000000000*01*0000000000*ZZ*ABCDEFGHIJKLMNO*ZZ*123456789012345*101127*1719*U*00400*000003438*0*P*>
+GS*PO*4405197800*999999999*20101127*1719*1421*X*004010VICS
+ST*850*0001
+BEG*00*SA*1*
+SE*3*0001
+GE*1*1421
+GS*IN*4405197800*999999999*20101205*1710*1320*X*004010VICS
+ST*810*1004
+BIG*20101204*217224*20101204*P792940
+REF*DP*038
+SE*4*1004
+GE*1*1320
+IEA*2*000003438";
+    let document = parse(input).unwrap();
+    let interchange = &document.interchanges[0];
+
+    let transaction = interchange
+        .functional_group(1)
+        .and_then(|group| group.transaction(0))
+        .unwrap();
+    assert_eq!(transaction.transaction_code, "810");
+    assert_eq!(transaction.segment(1).unwrap().segment_abbreviation, "REF");
+    assert!(transaction.segment(2).is_none());
+    assert!(interchange
+        .functional_group(0)
+        .and_then(|group| group.transaction(1))
+        .is_none());
+    assert!(interchange.functional_group(2).is_none());
+}
+
+#[test]
+fn detach_document_from_input() {
+    let document: edi::EdiDocument<'static, 'static> = {
+        let input = String::from("ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*000000001~BEG*00*SA*A99999-01**19970214~REF*VR*54321~SE*4*000000001~GE*1*1~IEA*1*000000001");
+        parse(&input).unwrap().into_owned()
+    };
+    assert_eq!(document.interchanges[0].sender_id, "SENDERISA");
+    assert_eq!(
+        document.transactions().next().unwrap().segments[1].elements[1],
+        "54321"
+    );
+    assert!(document.to_x12_string().ends_with("~IEA*1*000000001"));
+}
+
+#[test]
+fn build_hl_tree_from_sample_ship_notice() {
+    let input = std::fs::read_to_string(format!(
+        "{}/examples/sample_edi.txt",
+        env!("CARGO_MANIFEST_DIR")
+    ))
+    .unwrap();
+    let document = parse(&input).unwrap();
+    let tree = document
+        .transactions()
+        .next()
+        .unwrap()
+        .build_hl_tree()
+        .unwrap();
+
+    assert_eq!(tree.segments[0].segment_abbreviation, "BSN");
+    let shipment = &tree.children[0];
+    assert_eq!(shipment.level_code(), Some("S"));
+    let order = &shipment.children[0];
+    assert_eq!(order.level_code(), Some("O"));
+    let items: Vec<&str> = order
+        .children
+        .iter()
+        .map(|item| item.segments[0].elements[2].as_ref())
+        .collect();
+    assert_eq!(items, vec!["87787D", "99887D"]);
+}
+
+#[test]
+fn parse_isa_without_sub_element_separator() {
+    let input = "ISA*01*0000000000*01*0000000000*ZZ*ABCDEFGHIJKLMNO*ZZ*123456789012345*101127*1719*U*00400*000003438*0*P
+GS*PO*4405197800*999999999*20101127*1719*1421*X*004010VICS
+ST*850*0001
+BEG*00*SA*1*
+SE*3*0001
+GE*1*1421
+IEA*1*000003438";
+    assert!(parse(input).is_err());
+
+    let options = ParseOptions {
+        allow_isa_without_sub_element_separator: true,
+        ..ParseOptions::default()
+    };
+    let document = parse_with_options(input, &options).unwrap();
+    assert_eq!(document.sub_element_delimiter, ':');
+    assert_eq!(document.interchanges[0].test_indicator, "P");
+    assert_eq!(document.transactions().count(), 1);
+
+    // the missing ISA16 is not mistaken for a delimiter collision
+    let options = ParseOptions {
+        check_envelope_element_counts: true,
+        ..options
+    };
+    assert_eq!(parse_with_options(input, &options).unwrap(), document);
+}
+
+#[test]
+fn serialize_with_other_delimiters() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*000000001~REF*VR*54321~SE*3*000000001~GE*1*1~IEA*1*000000001";
+    let document = parse(input).unwrap();
+    let delimiters = document.delimiters();
+    assert_eq!(delimiters, edi::Delimiters::new('~', '*', '>'));
+    assert_eq!(document.to_x12_string_with(delimiters), input);
+
+    let output = document.to_x12_string_with(edi::Delimiters::new('\n', '|', '^'));
+    assert_eq!(
+        output,
+        input.replace('~', "\n").replace('*', "|").replace('>', "^")
+    );
+    let reparsed = parse(&output).unwrap();
+    assert_eq!(reparsed.delimiters().element, '|');
+    assert_eq!(reparsed.to_x12_string(), output);
+}
+
+#[test]
+fn grow_a_parsed_document() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*000000001~REF*VR*54321~SE*3*000000001~GE*1*1~IEA*1*000000001";
+    let mut document = parse(input).unwrap();
+    let mut functional_group = document.interchanges[0].functional_groups[0].clone();
+
+    let mut transaction = functional_group.transactions.pop_front().unwrap();
+    transaction.transaction_set_control_number = "000000002".into();
+    transaction.push_segment(GenericSegment::new("REF", vec!["DP", "099"]));
+    document.interchanges[0].functional_groups[0].push_transaction(transaction);
+
+    functional_group.group_control_number = "2".into();
+    document.interchanges[0].push_functional_group(functional_group);
+
+    let output = document.to_x12_string();
+    assert!(output.contains(
+        "~SE*3*000000001~ST*850*000000002~REF*VR*54321~REF*DP*099~SE*4*000000002~GE*2*1~GS*PO"
+    ));
+    assert!(output.ends_with("~GE*0*2~IEA*2*000000001"));
+    assert!(parse(&output).is_ok());
+}
+
+#[test]
+fn validate_element_types() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*000000001~BEG*00*SA*A99999-01**20190231~PO1*1*FOUR*EA*8.60**UP*123456789012~PO1*2*2*EA*12.345.6**UP*123456789013~SE*5*000000001~GE*1*1~IEA*1*000000001";
+    let document = parse(input).unwrap();
+
+    let mut specs = BTreeMap::new();
+    specs.insert("BEG03", ElementSpec::new(ElementType::String, 1, 22));
+    specs.insert("BEG05", ElementSpec::new(ElementType::Date, 8, 8));
+    specs.insert("PO102", ElementSpec::new(ElementType::Decimal, 1, 15));
+    specs.insert("PO104", ElementSpec::new(ElementType::Decimal, 1, 17));
+    specs.insert("PO107", ElementSpec::new(ElementType::String, 1, 48));
+
+    let errors = document.validate_elements(&specs).err().unwrap();
+    let reasons: Vec<&str> = errors.iter().map(|error| error.reason()).collect();
+    assert_eq!(reasons.len(), 3, "{:?}", reasons);
+    assert!(reasons[0].starts_with("BEG05 failed validation: element is not a valid date"));
+    assert!(reasons[1].starts_with("PO102 failed validation: element is not numeric"));
+    assert!(reasons[2].starts_with("PO104 failed validation: element is not numeric"));
+    assert_eq!(errors[0].error_segment().unwrap()[0], "BEG");
+
+    specs.insert("PO1", ElementSpec::new(ElementType::Identifier, 2, 2));
+    assert_eq!(document.validate_elements(&specs).err().unwrap().len(), 4);
+
+    let mut specs = BTreeMap::new();
+    specs.insert(
+        String::from("BEG03"),
+        ElementSpec::new(ElementType::String, 1, 22),
+    );
+    specs.insert(
+        String::from("PO103"),
+        ElementSpec::new(ElementType::Identifier, 2, 2),
+    );
+    assert!(document.validate_elements(&specs).is_ok());
+}
+
+#[test]
+fn renumber_control_numbers() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000007*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*5*X*004010~ST*850*000000001~REF*VR*54321~SE*3*000000001~ST*850*000000001~REF*VR*54322~SE*3*000000001~GE*2*5~GS*PO*SENDERGS*007326879*20020226*1534*5*X*004010~ST*850*9~REF*VR*54323~SE*3*9~GE*1*5~IEA*2*000000007~ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000007*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*5*X*004010~ST*850*9~REF*VR*54324~SE*3*9~GE*1*5~IEA*1*000000007";
+    let mut document = parse(input).unwrap();
+    assert!(document.check_control_number_uniqueness().is_err());
+
+    document.renumber(999_999_999);
+    assert!(document.check_control_number_uniqueness().is_ok());
+
+    let output = document.to_x12_string();
+    assert!(output.contains("*000000000*0*T*>~"));
+    assert!(output.contains(
+        "~ST*850*0001~REF*VR*54321~SE*3*0001~ST*850*0002~REF*VR*54322~SE*3*0002~GE*2*1~"
+    ));
+    assert!(output.contains("~ST*850*0001~REF*VR*54323~SE*3*0001~GE*1*2~IEA*2*999999999~"));
+    assert!(output.ends_with("~GE*1*1~IEA*1*000000000"));
+    assert!(parse(&output).is_ok());
+}
+
+#[test]
+fn derive_a_transaction_from_another() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*000000001~REF*VR*54321~SE*3*000000001~GE*1*1~IEA*1*000000001";
+    let mut document = parse(input).unwrap();
+    let original = &document.interchanges[0].functional_groups[0].transactions[0];
+
+    let mut copy = original.clone();
+    // cloning borrowed elements copies the references, not the data
+    assert!(matches!(copy.segments[0].elements[1], Cow::Borrowed(_)));
+    assert_eq!(&copy, original);
+
+    copy.transaction_set_control_number = "000000002".into();
+    copy.segments[0].elements[1] = "54322".into();
+    assert_eq!(original.segments[0].elements[1], "54321");
+
+    document.interchanges[0].functional_groups[0].push_transaction(copy);
+    assert!(document
+        .to_x12_string()
+        .contains("~SE*3*000000001~ST*850*000000002~REF*VR*54322~SE*3*000000002~GE*2*1~"));
+}
+
+#[test]
+fn final_segment_terminator_is_optional() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~
+GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~
+ST*850*000000001~
+REF*VR*54321~
+SE*3*000000001~
+GE*1*1~
+IEA*1*000000001";
+    let options = [
+        ParseOptions::default(),
+        ParseOptions::loose(),
+        ParseOptions {
+            preserve_whitespace: true,
+            ..ParseOptions::default()
+        },
+    ];
+    for options in options.iter() {
+        let expected = parse_with_options(input, options).unwrap();
+        for ending in ["~", "~\n", "~\r\n", "\n", "  ", "~  \n"].iter() {
+            let terminated = format!("{}{}", input, ending);
+            assert_eq!(
+                parse_with_options(&terminated, options).unwrap(),
+                expected,
+                "{:?} {:?}",
+                ending,
+                options
+            );
+        }
+    }
+
+    // the final segment's range ends before the terminator, so it is the same with or without one
+    let options = ParseOptions {
+        record_segment_offsets: true,
+        ..ParseOptions::default()
+    };
+    assert_eq!(
+        parse_with_options(&format!("{}~", input), &options).unwrap(),
+        parse_with_options(input, &options).unwrap()
+    );
+
+    // a document delimited by line breaks
+    let input = input.replace("~\n", "\n");
+    let expected = parse(&input).unwrap();
+    assert_eq!(expected.segment_delimiter, '\n');
+    assert_eq!(parse(&format!("{}\n", input)).unwrap(), expected);
+    assert_eq!(parse(&format!("{}\r\n", input)).unwrap(), expected);
+}
+
+#[test]
+fn build_999_acknowledgement() {
+    let input = "ISA*00*          *00*          *ZZ*SUBMITTER      *ZZ*RECEIVER       *200101*1200*^*00501*000000905*1*T*:~GS*HC*SUBMITTER*RECEIVER*20200101*1200*7*X*005010X222A1~ST*837*0001*005010X222A1~BHT*0019*00*0123*20200101*1200*CH~CLM*A37YH556*500***11:B:1*Y*A*Y*I~DTP*472*D8*20201301~SE*5*0001~ST*837*0002*005010X222A1~BHT*0019*00*0124*20200101*1200*CH~SE*3*0002~GE*2*7~IEA*1*000000905";
+    let document = parse(input).unwrap();
+
+    let accepted = document.build_999_for(0, &[]).unwrap();
+    assert_eq!(
+        accepted.to_x12_string(),
+        "ISA*00*          *00*          *ZZ*RECEIVER       *ZZ*SUBMITTER      *200101*1200*^*00501*000000001*0*T*:~GS*FA*RECEIVER*SUBMITTER*20200101*1200*1*X*005010X231A1~ST*999*0001*005010X231A1~AK1*HC*7*005010X222A1~AK2*837*0001*005010X222A1~IK5*A~AK2*837*0002*005010X222A1~IK5*A~AK9*A*2*2*2~SE*8*0001~GE*1*1~IEA*1*000000001"
+    );
+    assert_eq!(
+        accepted.transactions().next().unwrap().transaction_name,
+        "Implementation Acknowledgment"
+    );
+
+    let errors = [
+        AckError::element(0, 0, 4, 3, "8"),
+        AckError::segment(0, 0, 3, "2"),
+        AckError::element(0, 0, 4, 1, "7"),
+    ];
+    let rejected = document.build_999_for(0, &errors).unwrap().to_x12_string();
+    assert!(rejected.contains(
+        "~AK2*837*0001*005010X222A1~IK3*CLM*3**2~IK3*DTP*4**8~IK4*3**8*20201301~IK4*1**7*472~IK5*R*5~AK2*837*0002*005010X222A1~IK5*A~AK9*P*2*2*1~"
+    ));
+    assert!(parse(&rejected).is_ok());
+
+    let rejected = document
+        .build_999_for(
+            0,
+            &[
+                AckError::transaction(0, 1, "2"),
+                AckError::transaction(0, 0, "23"),
+            ],
+        )
+        .unwrap()
+        .to_x12_string();
+    assert!(rejected.contains("~IK5*R*23~AK2*837*0002*005010X222A1~IK5*R*2~AK9*R*2*2*0~"));
+
+    assert!(document.build_999_for(1, &[]).is_err());
+    assert!(document
+        .build_999_for(0, &[AckError::transaction(0, 2, "2")])
+        .is_err());
+    assert!(document
+        .build_999_for(0, &[AckError::segment(0, 1, 4, "2")])
+        .is_err());
+    assert!(document
+        .build_999_for(0, &[AckError::element(0, 1, 2, 0, "2")])
+        .is_err());
+}
+
+#[test]
+fn refuse_to_serialize_incomplete_envelopes() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*000000001~REF*VR*54321~SE*3*000000001~GE*1*1~IEA*1*000000001";
+    let mut document = parse(input).unwrap();
+    assert_eq!(document.try_to_x12_string().unwrap(), input);
+
+    document.interchanges[0].functional_groups[0].version = "".into();
+    assert_eq!(
+        document.try_to_x12_string().err().unwrap().reason(),
+        "GS08 is required but empty"
+    );
+    // the infallible serializer still writes it
+    assert!(document
+        .to_x12_string()
+        .contains("~GS*PO*SENDERGS*007326879*20020226*1534*1*X*~"));
+}
+
+#[test]
+fn name_unidentified_transactions() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*0001~REF*VR*54321~SE*3*0001~ST*001*0002~REF*VR*54322~SE*3*0002~GE*2*1~IEA*1*000000001";
+    let names = |options: &ParseOptions| {
+        parse_with_options(input, options)
+            .unwrap()
+            .transactions()
+            .map(|transaction| (transaction.transaction_name, transaction.is_recognized()))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        names(&ParseOptions::default()),
+        vec![("Purchase Order", true), ("unidentified", false)]
+    );
+    assert_eq!(
+        names(&ParseOptions {
+            unidentified_transaction_name: "Custom Transaction Set",
+            ..ParseOptions::default()
+        }),
+        vec![("Purchase Order", true), ("Custom Transaction Set", false)]
+    );
+}
+
+#[test]
+fn explode_into_single_transaction_documents() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000007*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*5*X*004010~ST*850*0001~REF*VR*54321~SE*3*0001~ST*850*0002~REF*VR*54322~REF*DP*099~SE*4*0002~GE*2*5~GS*IN*SENDERGS*007326879*20020226*1534*6*X*004010~ST*810*0003~REF*VR*54323~SE*3*0003~GE*1*6~IEA*2*000000007";
+    let document = parse(input).unwrap();
+
+    let documents = document.explode(None);
+    assert_eq!(documents.len(), 3);
+    let header = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000007*0*T*>~";
+    assert_eq!(
+        documents[1].to_x12_string(),
+        format!("{}GS*PO*SENDERGS*007326879*20020226*1534*5*X*004010~ST*850*0002~REF*VR*54322~REF*DP*099~SE*4*0002~GE*1*5~IEA*1*000000007", header)
+    );
+    assert!(documents[2]
+        .to_x12_string()
+        .contains("~GS*IN*SENDERGS*007326879*20020226*1534*6*X*004010~ST*810*0003~"));
+    for exploded in documents.iter() {
+        assert_eq!(&parse(&exploded.to_x12_string()).unwrap(), exploded);
+    }
+
+    let documents = document.explode(Some(100));
+    let control_numbers: Vec<&str> = documents
+        .iter()
+        .map(|exploded| exploded.interchanges[0].interchange_control_number.as_ref())
+        .collect();
+    assert_eq!(control_numbers, vec!["000000100", "000000101", "000000102"]);
+    assert!(documents[2]
+        .to_x12_string()
+        .ends_with("~ST*810*0001~REF*VR*54323~SE*3*0001~GE*1*1~IEA*1*000000102"));
+}
+
+#[test]
+fn filter_transactions_by_type() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*IN*SENDERGS*007326879*20020226*1534*1*X*004010~ST*810*0001~REF*VR*1~SE*3*0001~ST*850*0002~REF*VR*2~SE*3*0002~GE*2*1~GS*IN*SENDERGS*007326879*20020226*1534*2*X*004010~ST*810*0003~REF*VR*3~SE*3*0003~GE*1*2~IEA*2*000000001";
+    let document = parse(input).unwrap();
+
+    let invoices: Vec<&str> = document
+        .transactions_of_type("810")
+        .map(|transaction| transaction.transaction_set_control_number.as_ref())
+        .collect();
+    assert_eq!(invoices, vec!["0001", "0003"]);
+    assert_eq!(document.transactions_named("Invoice").count(), 2);
+    assert_eq!(document.transactions_named("Purchase Order").count(), 1);
+    assert_eq!(document.transactions_of_type("856").count(), 0);
+}
+
+#[test]
+fn build_document_from_tokens() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*0001~BEG*00*SA*A99999-01~REF*VR*54321~SE*4*0001~GE*1*1~IEA*1*000000001";
+    let mut tokens: DocumentTokens = input
+        .split('~')
+        .map(|segment| segment.split('*').collect())
+        .collect();
+    let delimiters = Delimiters::new('~', '*', '>');
+    assert_eq!(
+        parse_from_tokens(tokens.clone(), delimiters, false).unwrap(),
+        parse(input).unwrap()
+    );
+
+    // the tokens can be edited before the document is built
+    tokens.retain(|segment| segment[0] != "REF");
+    assert!(parse_from_tokens(tokens.clone(), delimiters, false).is_err());
+    let document = parse_from_tokens(tokens.clone(), delimiters, true).unwrap();
+    assert_eq!(document.segments().count(), 1);
+    assert_eq!(
+        document.to_x12_string(),
+        input.replace("REF*VR*54321~SE*4", "SE*3")
+    );
+
+    tokens.push(vec![]);
+    assert!(parse_from_tokens(tokens, delimiters, true).is_err());
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn serialize_to_canonical_json() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*0001~BEG*00*SA*A99999-01~ZZZ*1~SE*4*0001~GE*1*1~IEA*1*000000001";
+    let document = parse(input).unwrap();
+    let json = document.to_canonical_json();
+    assert_eq!(json, parse(input).unwrap().to_canonical_json());
+
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let transaction = &value["interchanges"][0]["functional_groups"][0]["transactions"][0];
+    assert_eq!(transaction["transaction_name"], "Purchase Order");
+    assert_eq!(
+        transaction["segments"][0]["segment_name"],
+        "Beginning Segment for Purchase Order"
+    );
+    assert!(transaction["segments"][1]["segment_name"].is_null());
+
+    // keys are sorted, so the transaction's fields appear in alphabetical order
+    let keys: Vec<usize> = [
+        "\"implementation_convention_reference\"",
+        "\"segments\"",
+        "\"transaction_code\"",
+        "\"transaction_name\"",
+    ]
+    .iter()
+    .map(|key| json.find(key).unwrap())
+    .collect();
+    assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+
+    // the plain serde representation is unchanged
+    assert!(!serde_json::to_string(&document)
+        .unwrap()
+        .contains("segment_name"));
+}
+
+#[test]
+fn merge_documents_into_one_batch() {
+    let first = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000100*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*7*X*004010~ST*850*0009~REF*VR*1~SE*3*0009~GE*1*7~IEA*1*000000100";
+    let second = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020227*0900*U*00401*000000042*0*T*>~GS*PO*SENDERGS*007326879*20020227*0900*3*X*004010~ST*850*0001~REF*VR*2~SE*3*0001~GE*1*3~GS*IN*SENDERGS*007326879*20020227*0900*4*X*004010~ST*810*0001~REF*VR*3~SE*3*0001~GE*1*4~IEA*2*000000042";
+    let documents = || vec![parse(first).unwrap(), parse(second).unwrap()];
+
+    let merged = edi::EdiDocument::merge(documents(), false).unwrap();
+    assert_eq!(merged.interchanges.len(), 2);
+    let output = merged.to_x12_string();
+    assert!(output.contains(
+        "*000000100*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*0001~"
+    ));
+    assert!(output.ends_with("~GE*1*2~IEA*2*000000101"));
+    assert_eq!(parse(&output).unwrap(), merged);
+
+    let merged = edi::EdiDocument::merge(documents(), true).unwrap();
+    assert_eq!(merged.interchanges.len(), 1);
+    let interchange = &merged.interchanges[0];
+    assert_eq!(interchange.date, "020226");
+    assert_eq!(interchange.functional_groups.len(), 2);
+    let purchase_orders: Vec<&str> = interchange.functional_groups[0]
+        .transactions
+        .iter()
+        .map(|transaction| transaction.transaction_set_control_number.as_ref())
+        .collect();
+    assert_eq!(purchase_orders, vec!["0001", "0002"]);
+    assert_eq!(interchange.functional_groups[1].group_control_number, "2");
+    assert!(parse(&merged.to_x12_string()).is_ok());
+
+    let mut other_delimiters = parse(second).unwrap();
+    other_delimiters.segment_delimiter = '\n';
+    assert!(edi::EdiDocument::merge(vec![parse(first).unwrap(), other_delimiters], false).is_err());
+    assert!(edi::EdiDocument::merge(vec![], true).is_err());
+}
+
+#[test]
+fn parse_with_progress_reports_segments() {
+    let mut input = String::from("ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*000000001~");
+    for _ in 0..2500 {
+        input.push_str("REF*VR*54321~");
+    }
+    input.push_str("SE*2502*000000001~GE*1*1~IEA*1*000000001~");
+
+    let mut reports = Vec::new();
+    let edi_document =
+        parse_with_progress(&input, |processed, total| reports.push((processed, total))).unwrap();
+    assert_eq!(edi_document, parse(&input).unwrap());
+    assert_eq!(reports, vec![(1000, 2506), (2000, 2506), (2506, 2506)]);
+}
+
+#[test]
+fn serde_schema_version() {
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*0001~REF*VR*54321~SE*3*0001~GE*1*1~IEA*1*000000001";
+    let document = parse(input).unwrap();
+    let json = serde_json::to_string(&document).unwrap();
+    assert!(json.starts_with(&format!("{{\"edi_schema_version\":{},", EDI_SCHEMA_VERSION)));
+    assert_eq!(
+        serde_json::from_str::<EdiDocument>(&json).unwrap(),
+        document
+    );
+
+    // documents stored before the version and the optional fields were introduced still deserialize
+    let mut stored: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let fields = stored.as_object_mut().unwrap();
+    fields.remove("edi_schema_version");
+    fields.remove("segment_offsets");
+    stored["interchanges"][0]["functional_groups"][0]["transactions"][0]["segments"][0]
+        .as_object_mut()
+        .unwrap()
+        .remove("raw");
+    let stored = stored.to_string();
+    assert_eq!(
+        serde_json::from_str::<EdiDocument>(&stored).unwrap(),
+        document
+    );
+
+    // documents from a newer version are rejected
+    let newer = json.replacen(
+        &format!("\"edi_schema_version\":{}", EDI_SCHEMA_VERSION),
+        &format!("\"edi_schema_version\":{}", EDI_SCHEMA_VERSION + 1),
+        1,
+    );
+    assert!(serde_json::from_str::<EdiDocument>(&newer).is_err());
+}
+
+#[test]
+fn construct_document_with_delimiters() {
+    let mut document = EdiDocument::new(Delimiters::new('~', '*', '>')).unwrap();
+    assert!(document.interchanges.is_empty());
+    assert_eq!(document.delimiters(), Delimiters::new('~', '*', '>'));
+    assert_eq!(document.to_x12_string(), "");
+
+    let input = "ISA*00*          *00*          *ZZ*SENDERISA      *14*0073268795005  *020226*1534*U*00401*000000001*0*T*>~GS*PO*SENDERGS*007326879*20020226*1534*1*X*004010~ST*850*0001~REF*VR*54321~SE*3*0001~GE*1*1~IEA*1*000000001";
+    document.interchanges = parse(input).unwrap().interchanges;
+    assert_eq!(document.to_x12_string(), input);
+
+    for (segment, element, sub_element) in
+        [('~', '*', '*'), ('~', '~', '>'), (':', '*', ':')].iter()
+    {
+        let error =
+            EdiDocument::new(Delimiters::new(*segment, *element, *sub_element)).unwrap_err();
+        assert_eq!(error.kind(), EdiErrorKind::InvalidArgument);
+    }
+    assert_eq!(
+        EdiDocument::new(Delimiters::new('~', '~', '>'))
+            .unwrap_err()
+            .reason(),
+        "element and segment delimiters cannot be the same  --  expected: ~  received: ~"
+    );
+}